@@ -0,0 +1,19 @@
+//! Decodes arbitrary bytes as a client packet, as the server does with every frame it receives.
+//! It must never panic, and whatever is decoded must encode back to a packet that decodes the
+//! same.
+
+#![no_main]
+
+use dalang_protocol::ClientPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(packet) = ClientPacket::try_from(data) else {
+        return;
+    };
+
+    let encoded: Vec<u8> = packet.clone().try_into().expect("Failed to encode packet");
+    let decoded = ClientPacket::try_from(encoded.as_slice()).expect("Failed to decode packet");
+
+    assert_eq!(decoded, packet);
+});
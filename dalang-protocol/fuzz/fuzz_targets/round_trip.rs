@@ -0,0 +1,58 @@
+//! Encodes arbitrary packets and decodes them back, they must be equal unless they were
+//! rejected by their validation rules.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use dalang_protocol::{
+    authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+    editor::{ClientEditorPacket, ServerEditorPacket},
+    protocol::{ClientProtocolPacket, ServerProtocolPacket},
+    user::{ClientUserPacket, ServerUserPacket},
+    Packet, PacketCategoryDecodeError,
+};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+enum AnyPacket<'a> {
+    ClientProtocol(ClientProtocolPacket),
+    ServerProtocol(ServerProtocolPacket),
+    ClientAuthentication(ClientAuthenticationPacket<'a>),
+    ServerAuthentication(ServerAuthenticationPacket),
+    ClientUser(ClientUserPacket),
+    ServerUser(ServerUserPacket),
+    ClientEditor(ClientEditorPacket),
+    ServerEditor(ServerEditorPacket),
+}
+
+fn round_trip<'a, P: Packet<'a> + Clone + PartialEq + std::fmt::Debug>(
+    packet: P,
+    buffer: &'a mut Vec<u8>,
+) {
+    let opcode = packet.as_opcode();
+    *buffer = packet
+        .clone()
+        .encode_payload()
+        .expect("Failed to encode packet");
+
+    match P::decode_packet(opcode, buffer) {
+        Ok(decoded) => assert_eq!(decoded, packet),
+        Err(PacketCategoryDecodeError::Validation { .. }) => (),
+        Err(err) => panic!("Failed to decode {packet:?}: {err:?}"),
+    }
+}
+
+fuzz_target!(|packet: AnyPacket| {
+    let mut buffer = Vec::new();
+
+    match packet {
+        AnyPacket::ClientProtocol(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ServerProtocol(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ClientAuthentication(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ServerAuthentication(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ClientUser(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ServerUser(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ClientEditor(packet) => round_trip(packet, &mut buffer),
+        AnyPacket::ServerEditor(packet) => round_trip(packet, &mut buffer),
+    }
+});
@@ -0,0 +1,29 @@
+//! Exports the schema of the dalang protocol.
+//!
+//! Usage: `dalang-schema <json|typescript|opcodes>`
+//!
+//! The outputs are checked into the repository, regenerate them after changing the packets:
+//!
+//! ```sh
+//! cargo run -p dalang-protocol --bin dalang-schema -- json > specs/schema.json
+//! cargo run -p dalang-protocol --bin dalang-schema -- typescript > frontend/src/services/connection/protocol.d.ts
+//! cargo run -p dalang-protocol --bin dalang-schema -- opcodes > frontend/src/services/connection/opcodes.js
+//! ```
+
+use dalang_protocol::schema;
+
+fn main() {
+    let schema = schema::protocol_schema();
+
+    let output = match std::env::args().nth(1).as_deref() {
+        Some("json") => schema::to_json(&schema),
+        Some("typescript") => schema::to_typescript(&schema),
+        Some("opcodes") => schema::to_opcodes_js(&schema),
+        _ => {
+            eprintln!("usage: dalang-schema <json|typescript|opcodes>");
+            std::process::exit(2);
+        }
+    };
+
+    print!("{output}");
+}
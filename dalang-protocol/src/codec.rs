@@ -0,0 +1,148 @@
+//! A [`tokio_util::codec`] of dalang packets, to send them over any byte stream (TCP, Unix
+//! sockets, pipes) where there are no websocket messages to delimit them. Every packet is
+//! prefixed by its length in bytes, as a big-endian u32:
+//!
+//! ```text
+//! [length: u32][packet: `length` bytes of msgpack]
+//! ```
+//!
+//! Wrap a stream with [`tokio_util::codec::Framed`] to read and write packets:
+//!
+//! ```ignore
+//! let mut framed = Framed::new(stream, PacketCodec::default());
+//!
+//! framed.send(ClientPacket::User(ClientUserPacket::GetUsername)).await?;
+//!
+//! let frame = framed.next().await.unwrap()?;
+//! let packet = ServerPacket::try_from(&frame[..])?;
+//! ```
+
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use rmp::encode::ValueWriteError;
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{ClientPacket, DecodeLimits, PacketDecodeError, ServerPacket};
+
+/// The size of the length prefix of every packet
+const LENGTH_SIZE: usize = 4;
+
+/// Splits a byte stream into packets, and writes packets into it. The decoded items are the
+/// bytes of a whole packet, decode them with `ClientPacket::decode_with_limits` or
+/// `ServerPacket::decode_with_limits`, since the packets borrow from them.
+///
+/// Packets longer than `max_size` of the [`DecodeLimits`] are rejected before being buffered.
+#[derive(Debug, Clone, Default)]
+pub struct PacketCodec {
+    limits: DecodeLimits,
+}
+
+impl PacketCodec {
+    pub fn new(limits: DecodeLimits) -> Self {
+        PacketCodec { limits }
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Io(io::Error),
+    /// The length prefix exceeds the limits, only [`PacketDecodeError::TooLarge`] is reported
+    /// by the codec itself
+    Decode(PacketDecodeError),
+    Encode(ValueWriteError),
+}
+
+impl From<io::Error> for CodecError {
+    fn from(value: io::Error) -> Self {
+        CodecError::Io(value)
+    }
+}
+
+impl From<PacketDecodeError> for CodecError {
+    fn from(value: PacketDecodeError) -> Self {
+        CodecError::Decode(value)
+    }
+}
+
+impl From<ValueWriteError> for CodecError {
+    fn from(value: ValueWriteError) -> Self {
+        CodecError::Encode(value)
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = BytesMut;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(length) = src.get(..LENGTH_SIZE) else {
+            return Ok(None);
+        };
+
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+
+        if length > self.limits.max_size {
+            return Err(PacketDecodeError::TooLarge {
+                size: length,
+                max: self.limits.max_size,
+            })?;
+        }
+
+        if src.len() < LENGTH_SIZE + length {
+            // wait for the rest of the packet
+            src.reserve(LENGTH_SIZE + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_SIZE);
+        Ok(Some(src.split_to(length)))
+    }
+}
+
+/// Writes an already encoded packet
+impl Encoder<&[u8]> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let length = u32::try_from(item.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the packet is too large to be sent")
+        })?;
+
+        dst.reserve(LENGTH_SIZE + item.len());
+        dst.put_u32(length);
+        dst.put_slice(item);
+
+        Ok(())
+    }
+}
+
+impl Encoder<Vec<u8>> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(item.as_slice(), dst)
+    }
+}
+
+impl Encoder<ServerPacket> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: ServerPacket, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let packet: Vec<u8> = item.try_into()?;
+        self.encode(packet.as_slice(), dst)
+    }
+}
+
+impl Encoder<ClientPacket<'_>> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: ClientPacket<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let packet: Vec<u8> = item.try_into()?;
+        self.encode(packet.as_slice(), dst)
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+/// The flag of packets whose payload is a binary of deflate-compressed msgpack
+pub(crate) const FLAG_COMPRESSED: u8 = 0x01;
+
+/// The name of the extension, advertised in [`crate::EXTENSIONS`]
+pub const EXTENSION: &str = "compression";
+
+/// How the payloads of the packets sent to a client that accepted the `compression` extension
+/// are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    /// Payloads of this size or smaller (in bytes) are sent as they are
+    pub threshold: usize,
+    /// The deflate level, from 0 (fastest) to 9 (smallest)
+    pub level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            threshold: 1024,
+            level: 6,
+        }
+    }
+}
+
+impl Compression {
+    /// Compresses a payload that is larger than the threshold, returns `None` when the payload
+    /// should be sent as it is, including when it doesn't get any smaller.
+    pub(crate) fn compress(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.len() <= self.threshold {
+            return None;
+        }
+
+        let mut encoder = DeflateEncoder::new(
+            Vec::with_capacity(payload.len() / 2),
+            flate2::Compression::new(self.level),
+        );
+
+        encoder.write_all(payload).ok()?;
+        let compressed = encoder.finish().ok()?;
+
+        (compressed.len() < payload.len()).then_some(compressed)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum DecompressError {
+    /// The payload would be larger than the given maximum size
+    TooLarge,
+    Invalid,
+}
+
+/// Decompresses a payload, without ever holding more than `max_size` bytes of it
+pub(crate) fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut payload = Vec::new();
+
+    DeflateDecoder::new(data)
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut payload)
+        .map_err(|_| DecompressError::Invalid)?;
+
+    if payload.len() > max_size {
+        return Err(DecompressError::TooLarge);
+    }
+
+    Ok(payload)
+}
@@ -1,6 +1,6 @@
 use std::io;
 
-use rmp::decode::{ValueReadError, MarkerReadError};
+use rmp::{decode::{ValueReadError, MarkerReadError}, encode::ValueWriteError};
 
 use super::Category;
 
@@ -11,7 +11,31 @@ pub enum PacketDecodeError {
     UnknownOpcode { category: Category, opcode: u16 },
     UnknownCategory { given_category: u16 },
     InvalidPayload { category: Category, opcode: u16 },
+    Validation { category: Category, opcode: u16, field: &'static str, error: ValidationError },
     Msgpack(ValueReadError),
+
+    // the limits of `DecodeLimits`, checked before the packet is decoded
+    /// The packet is larger than `max_size`
+    TooLarge { size: usize, max: usize },
+    /// Arrays and maps are nested deeper than `max_depth`
+    TooDeep { max: usize },
+    /// An array or a map has more items than `max_collection_len`
+    CollectionTooLong { len: usize, max: usize },
+    /// A string or a binary is longer than `max_string_len`
+    StringTooLong { len: usize, max: usize },
+}
+
+impl PacketDecodeError {
+    /// Whether the packet was rejected for exceeding the `DecodeLimits`
+    pub fn is_limit_exceeded(&self) -> bool {
+        matches!(
+            self,
+            PacketDecodeError::TooLarge { .. }
+                | PacketDecodeError::TooDeep { .. }
+                | PacketDecodeError::CollectionTooLong { .. }
+                | PacketDecodeError::StringTooLong { .. }
+        )
+    }
 }
 
 impl From<ValueReadError> for PacketDecodeError {
@@ -26,14 +50,17 @@ impl From<MarkerReadError> for PacketDecodeError {
     }
 }
 
-impl<Opcode: Into<u16> + TryFrom<u16>> From<(Category, PacketCategoryDecodeError<Opcode>)> for PacketDecodeError {
-    fn from((category, value): (Category, PacketCategoryDecodeError<Opcode>)) -> Self {
+impl From<(Category, PacketCategoryDecodeError)> for PacketDecodeError {
+    fn from((category, value): (Category, PacketCategoryDecodeError)) -> Self {
         match value {
             PacketCategoryDecodeError::UnknownOpcode { opcode }
                 => PacketDecodeError::UnknownOpcode { category, opcode },
 
             PacketCategoryDecodeError::InvalidPayload { opcode }
-                => PacketDecodeError::InvalidPayload { category, opcode: opcode.into() },
+                => PacketDecodeError::InvalidPayload { category, opcode },
+
+            PacketCategoryDecodeError::Validation { opcode, field, error }
+                => PacketDecodeError::Validation { category, opcode, field, error },
             
             PacketCategoryDecodeError::Msgpack(err)
                 => PacketDecodeError::Msgpack(err),
@@ -42,26 +69,24 @@ impl<Opcode: Into<u16> + TryFrom<u16>> From<(Category, PacketCategoryDecodeError
 }
 
 // === Packet Category Decode Error
+/// An error of decoding a packet within its category, returned by
+/// [`Packet::decode_packet`](crate::Packet::decode_packet)
 #[derive(Debug)]
-pub enum PacketCategoryDecodeError<Opcode>
-where Opcode: Into<u16> + TryFrom<u16>
-{
+pub enum PacketCategoryDecodeError {
     UnknownOpcode { opcode: u16 },
-    InvalidPayload { opcode: Opcode },
+    InvalidPayload { opcode: u16 },
+    /// A field of the packet failed one of its `#[validate(...)]` rules
+    Validation { opcode: u16, field: &'static str, error: ValidationError },
     Msgpack(ValueReadError),
 }
 
-impl<Opcode> From<ValueReadError> for PacketCategoryDecodeError<Opcode>
-where Opcode: Into<u16> + TryFrom<u16>
-{
+impl From<ValueReadError> for PacketCategoryDecodeError {
     fn from(value: ValueReadError) -> Self {
         PacketCategoryDecodeError::Msgpack(value)
     }
 }
 
-impl<Opcode> From<rmpv::decode::Error> for PacketCategoryDecodeError<Opcode>
-where Opcode: Into<u16> + TryFrom<u16>
-{
+impl From<rmpv::decode::Error> for PacketCategoryDecodeError {
     fn from(value: rmpv::decode::Error) -> Self {
         match value {
             rmpv::decode::Error::InvalidMarkerRead(err)
@@ -74,4 +99,52 @@ where Opcode: Into<u16> + TryFrom<u16>
                 ))
         }
     }
-}
\ No newline at end of file
+}
+
+// === Validation Error
+/// The rule of `#[validate(...)]` that a field has failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// `len(min = ..)`, strings are counted in characters
+    TooShort { min: usize },
+    /// `len(max = ..)`
+    TooLong { max: usize },
+    /// `range(min = .., max = ..)`
+    OutOfRange,
+    /// `regex = ".."`
+    PatternMismatch { pattern: &'static str },
+}
+// === Json Error
+/// An error of converting a packet between msgpack and its JSON form, see [`crate::json`]
+#[derive(Debug)]
+pub enum JsonError {
+    Json(serde_json::Error),
+    /// The JSON isn't an object with a `packet` name and a `payload`
+    InvalidStructure,
+    /// No packet of the given name is sent by this side
+    UnknownPacket(String),
+    /// The packet has no name in the schema (such as the editor packets)
+    UnknownOpcode { category: u16, opcode: u16 },
+    /// The msgpack value has no JSON equivalent, such as a map with non-string keys
+    Unrepresentable,
+    Msgpack(rmpv::decode::Error),
+    Encode(ValueWriteError),
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(value: serde_json::Error) -> Self {
+        JsonError::Json(value)
+    }
+}
+
+impl From<rmpv::decode::Error> for JsonError {
+    fn from(value: rmpv::decode::Error) -> Self {
+        JsonError::Msgpack(value)
+    }
+}
+
+impl From<ValueWriteError> for JsonError {
+    fn from(value: ValueWriteError) -> Self {
+        JsonError::Encode(value)
+    }
+}
@@ -0,0 +1,175 @@
+//! A JSON form of the packets, for debugging. Packets are named after their category and
+//! variant instead of their opcode:
+//!
+//! ```json
+//! { "packet": "Authentication.Login", "payload": { "username": "lorem", "password": "ipsum" } }
+//! ```
+//!
+//! The payload is the msgpack payload as JSON, binaries are `{ "$bin": "<hex>" }`. Packets are
+//! converted from and to their msgpack encoding, so they're decoded and validated exactly like
+//! the packets that are sent as msgpack.
+
+use rmp::encode::{write_array_len, write_u32};
+use rmpv::Value;
+use serde_json::json;
+
+use crate::{
+    error::JsonError,
+    schema::{self, VariantSchema},
+};
+
+/// The name of the extension, only offered by servers that enable it
+pub const EXTENSION: &str = "json-debug";
+
+/// The side of the connection that sends a packet, both sides have their own opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sender {
+    Client,
+    Server,
+}
+
+fn variants(category: &schema::CategorySchema, sender: Sender) -> &'static [VariantSchema] {
+    match sender {
+        Sender::Client => category.client,
+        Sender::Server => category.server,
+    }
+}
+
+/// Converts an encoded (and uncompressed) packet into its JSON form
+pub fn to_json(mut packet: &[u8], sender: Sender) -> Result<String, JsonError> {
+    let Value::Array(mut items) = rmpv::decode::read_value(&mut packet)? else {
+        return Err(JsonError::InvalidStructure);
+    };
+
+    let (Some(payload), Some(header), true) = (items.pop(), items.pop(), items.is_empty()) else {
+        return Err(JsonError::InvalidStructure);
+    };
+
+    let header = header.as_u64().ok_or(JsonError::InvalidStructure)?;
+    let (category, opcode) = ((header >> 16) as u16, header as u16);
+
+    let name = schema::protocol_schema()
+        .categories
+        .iter()
+        .find(|schema| schema.id == category)
+        .and_then(|schema| {
+            variants(schema, sender)
+                .iter()
+                .find(|variant| variant.opcode == opcode)
+                .map(|variant| format!("{}.{}", schema.name, variant.name))
+        })
+        .ok_or(JsonError::UnknownOpcode { category, opcode })?;
+
+    Ok(json!({ "packet": name, "payload": value_to_json(&payload)? }).to_string())
+}
+
+/// Converts a packet in its JSON form into its msgpack encoding
+pub fn from_json(text: &str, sender: Sender) -> Result<Vec<u8>, JsonError> {
+    let serde_json::Value::Object(mut packet) = serde_json::from_str(text)? else {
+        return Err(JsonError::InvalidStructure);
+    };
+
+    let Some(serde_json::Value::String(name)) = packet.remove("packet") else {
+        return Err(JsonError::InvalidStructure);
+    };
+
+    let payload = json_to_value(packet.remove("payload").unwrap_or_default())?;
+
+    let header = name
+        .split_once('.')
+        .and_then(|(category, variant)| {
+            let schema = schema::protocol_schema();
+            let category = schema.categories.iter().find(|schema| schema.name == category)?;
+            let variant = variants(category, sender)
+                .iter()
+                .find(|schema| schema.name == variant)?;
+
+            Some((category.id as u32) << 16 | variant.opcode as u32)
+        })
+        .ok_or(JsonError::UnknownPacket(name))?;
+
+    let mut buffer = Vec::new();
+
+    // the opcode is always a u32, `rmpv` would write the smallest integer it fits in
+    write_array_len(&mut buffer, 2)?;
+    write_u32(&mut buffer, header)?;
+    rmpv::encode::write_value(&mut buffer, &payload).map_err(|_| JsonError::Unrepresentable)?;
+
+    Ok(buffer)
+}
+
+/// Converts a msgpack value into JSON, binaries are `{ "$bin": "<hex>" }`
+pub(crate) fn value_to_json(value: &Value) -> Result<serde_json::Value, JsonError> {
+    Ok(match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Boolean(bool) => json!(bool),
+        Value::Integer(int) => match (int.as_u64(), int.as_i64()) {
+            (Some(int), _) => json!(int),
+            (_, Some(int)) => json!(int),
+            _ => return Err(JsonError::Unrepresentable),
+        },
+        Value::F32(float) => json!(float),
+        Value::F64(float) => json!(float),
+        Value::String(string) => json!(string.as_str().ok_or(JsonError::Unrepresentable)?),
+        Value::Binary(bin) => {
+            json!({ "$bin": bin.iter().map(|byte| format!("{:02x}", byte)).collect::<String>() })
+        }
+        Value::Array(items) => serde_json::Value::Array(
+            items.iter().map(value_to_json).collect::<Result<_, _>>()?,
+        ),
+        Value::Map(entries) => serde_json::Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| {
+                    let key = key.as_str().ok_or(JsonError::Unrepresentable)?;
+                    Ok((key.to_string(), value_to_json(value)?))
+                })
+                .collect::<Result<_, JsonError>>()?,
+        ),
+        Value::Ext(..) => return Err(JsonError::Unrepresentable),
+    })
+}
+
+/// Converts JSON into a msgpack value, the reverse of [`value_to_json`]
+fn json_to_value(value: serde_json::Value) -> Result<Value, JsonError> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(bool) => Value::Boolean(bool),
+        serde_json::Value::Number(number) => {
+            if let Some(int) = number.as_u64() {
+                Value::from(int)
+            } else if let Some(int) = number.as_i64() {
+                Value::from(int)
+            } else {
+                Value::F64(number.as_f64().ok_or(JsonError::Unrepresentable)?)
+            }
+        }
+        serde_json::Value::String(string) => Value::String(string.into()),
+        serde_json::Value::Array(items) => Value::Array(
+            items.into_iter().map(json_to_value).collect::<Result<_, _>>()?,
+        ),
+        serde_json::Value::Object(entries) => {
+            if let (1, Some(serde_json::Value::String(hex))) = (entries.len(), entries.get("$bin")) {
+                return from_hex(hex).map(Value::Binary).ok_or(JsonError::Unrepresentable);
+            }
+
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((Value::String(key.into()), json_to_value(value)?)))
+                    .collect::<Result<_, JsonError>>()?,
+            )
+        }
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,17 +1,22 @@
 use rmp::{
-    decode::{read_marker, read_u32},
-    encode::{write_array_len, write_str, write_str_len, write_u8, ValueWriteError},
+    decode::{read_bin_len, read_int, read_marker, read_u32},
+    encode::{
+        write_array_len, write_bin, write_nil, write_pfix, write_str, write_str_len, write_u32,
+        write_u8, ValueWriteError,
+    },
 };
 
 // might be a good idea to use the version specified on the cargo manifest file
 // but it'd be a problem converting it into these MAJOR, MINOR, and PATCH vars
-pub const VERSION: &'static str = "0.0.1";
+pub const VERSION: &str = "0.0.1";
 
 pub const VERSION_MAJOR: u8 = 0;
 pub const VERSION_MINOR: u8 = 0;
 pub const VERSION_PATCH: u8 = 1;
 
-pub const EXTENSIONS: [&str; 0] = [];
+/// Extensions of the protocol supported by this version, a client uses them once it accepts
+/// them with `ClientProtocolPacket::AcceptExtensions`.
+pub const EXTENSIONS: [&str; 1] = [compression::EXTENSION];
 
 #[cfg(test)]
 mod tests;
@@ -19,13 +24,31 @@ mod tests;
 #[macro_use]
 mod error;
 
+mod compression;
+pub mod json;
+mod limits;
+pub mod schema;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
 pub use error::PacketCategoryDecodeError;
+pub use error::JsonError;
 pub use error::PacketDecodeError;
+pub use error::ValidationError;
+pub use limits::DecodeLimits;
+pub use compression::Compression;
 
 // maybe cache this in some way? I'm too lazy to use `lazy_static` (pun intended)
 /// Generates a packet that contains the version information of the protocol
 /// used at the start of handshake between the server and the client.
 pub fn protocol_version_packet() -> Result<Vec<u8>, ValueWriteError> {
+    protocol_version_packet_with(&EXTENSIONS)
+}
+
+/// Generates the protocol version packet of a server that offers the given extensions, such
+/// as the ones that are not in [`EXTENSIONS`] because they are only enabled on demand.
+pub fn protocol_version_packet_with(extensions: &[&str]) -> Result<Vec<u8>, ValueWriteError> {
     let mut buffer = Vec::new();
 
     write_array_len(&mut buffer, 2)?;
@@ -35,9 +58,9 @@ pub fn protocol_version_packet() -> Result<Vec<u8>, ValueWriteError> {
     write_u8(&mut buffer, VERSION_MINOR)?;
     write_u8(&mut buffer, VERSION_PATCH)?;
 
-    write_array_len(&mut buffer, EXTENSIONS.len() as u32)?;
+    write_array_len(&mut buffer, extensions.len() as u32)?;
 
-    for extension in EXTENSIONS {
+    for extension in extensions {
         write_str_len(&mut buffer, extension.len() as u32)?;
         write_str(&mut buffer, extension)?;
     }
@@ -45,68 +68,309 @@ pub fn protocol_version_packet() -> Result<Vec<u8>, ValueWriteError> {
     Ok(buffer)
 }
 
-pub enum ClientPacket {
-    Authentication(authentication::ClientAuthenticationPacket),
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientPacket<'a> {
+    Protocol(protocol::ClientProtocolPacket),
+    Authentication(authentication::ClientAuthenticationPacket<'a>),
     User(user::ClientUserPacket),
     Editor(editor::ClientEditorPacket),
 }
 
-impl TryFrom<&[u8]> for ClientPacket {
-    type Error = PacketDecodeError;
+impl<'a> ClientPacket<'a> {
+    /// Decodes a packet that must be within the given limits, see [`DecodeLimits`]
+    pub fn decode_with_limits(
+        value: &'a [u8],
+        limits: &DecodeLimits,
+    ) -> Result<Self, PacketDecodeError> {
+        limits.check(value)?;
+        Self::decode(value)
+    }
 
-    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
-        // the client packet is an array of two items:
-        // 0 - the opcode
-        // 1 - an object of payload, may be null
+    fn decode(mut value: &'a [u8]) -> Result<Self, PacketDecodeError> {
+        let (category, opcode, flags) = decode_header(&mut value)?;
 
-        // read an array of two items
-        let rmp::Marker::FixArray(2) = read_marker(&mut value)? else {
+        // only the server compresses its packets
+        if flags != 0 {
             Err(PacketDecodeError::InvalidStructure)?
-        };
+        }
 
-        let opcode = read_u32(&mut value)?;
-        let category = (opcode >> 16) as u16;
+        Ok(match category {
+            Category::Protocol => ClientPacket::Protocol(
+                protocol::ClientProtocolPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+            Category::Authentication => ClientPacket::Authentication(
+                authentication::ClientAuthenticationPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+            Category::User => ClientPacket::User(
+                user::ClientUserPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+            Category::Editor => ClientPacket::Editor(
+                editor::ClientEditorPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+        })
+    }
+}
 
-        let Ok(category): Result<Category, _> = category.try_into() else {
-            // unknown category
-            Err(PacketDecodeError::UnknownCategory { given_category: category })?
+/// Reads the start of a packet up until its payload, returns its category, opcode and flags
+fn decode_header(value: &mut &[u8]) -> Result<(Category, u16, u8), PacketDecodeError> {
+    // the packet is an array of two items:
+    // 0 - the opcode
+    // 1 - an object of payload, may be null
+    //
+    // or three items, when the packet has flags:
+    // 0 - the opcode
+    // 1 - the flags
+    // 2 - the payload
+    let len = match read_marker(value)? {
+        rmp::Marker::FixArray(len @ 2..=3) => len,
+        _ => Err(PacketDecodeError::InvalidStructure)?,
+    };
+
+    let opcode = read_u32(value)?;
+    let category = (opcode >> 16) as u16;
+
+    let Ok(category): Result<Category, _> = category.try_into() else {
+        // unknown category
+        Err(PacketDecodeError::UnknownCategory { given_category: category })?
+    };
+
+    let flags = if len == 3 {
+        read_int::<u8, _>(value).map_err(|_| PacketDecodeError::InvalidStructure)?
+    } else {
+        0
+    };
+
+    // no other flags are defined
+    if flags & !compression::FLAG_COMPRESSED != 0 {
+        Err(PacketDecodeError::InvalidStructure)?
+    }
+
+    Ok((category, (opcode & 0xffff) as u16, flags))
+}
+
+impl<'a> TryFrom<&'a [u8]> for ClientPacket<'a> {
+    type Error = PacketDecodeError;
+
+    /// Decodes a packet within the default [`DecodeLimits`]
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::decode_with_limits(value, &DecodeLimits::default())
+    }
+}
+
+impl TryInto<Vec<u8>> for ClientPacket<'_> {
+    type Error = ValueWriteError;
+
+    fn try_into(self) -> Result<Vec<u8>, Self::Error> {
+        let (category, opcode, payload) = match self {
+            ClientPacket::Protocol(packet) => {
+                (Category::Protocol, packet.as_opcode(), packet.encode_payload())
+            }
+            ClientPacket::Authentication(packet) => (
+                Category::Authentication,
+                packet.as_opcode(),
+                packet.encode_payload(),
+            ),
+            ClientPacket::User(packet) => {
+                (Category::User, packet.as_opcode(), packet.encode_payload())
+            }
+            ClientPacket::Editor(packet) => {
+                (Category::Editor, packet.as_opcode(), packet.encode_payload())
+            }
         };
 
-        let opcode = (opcode & 0xffff) as u16;
+        encode_packet(category, opcode, payload)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerPacket {
+    Protocol(protocol::ServerProtocolPacket),
+    Authentication(authentication::ServerAuthenticationPacket),
+    User(user::ServerUserPacket),
+    Editor(editor::ServerEditorPacket),
+}
+
+impl ServerPacket {
+    /// Decodes a packet that must be within the given limits, see [`DecodeLimits`]
+    ///
+    /// The payload of a compressed packet is held to the same limits once it's decompressed.
+    pub fn decode_with_limits(mut value: &[u8], limits: &DecodeLimits) -> Result<Self, PacketDecodeError> {
+        limits.check(value)?;
+
+        let (category, opcode, flags) = decode_header(&mut value)?;
+
+        if flags & compression::FLAG_COMPRESSED == 0 {
+            return Self::decode(category, opcode, value);
+        }
+
+        let invalid_payload = || PacketDecodeError::InvalidPayload { category, opcode };
 
+        let len = read_bin_len(&mut value).map_err(|_| invalid_payload())? as usize;
+        let data = value.get(..len).ok_or_else(invalid_payload)?;
+
+        let payload = compression::decompress(data, limits.max_size).map_err(|err| match err {
+            compression::DecompressError::TooLarge => PacketDecodeError::TooLarge {
+                size: limits.max_size + 1,
+                max: limits.max_size,
+            },
+            compression::DecompressError::Invalid => invalid_payload(),
+        })?;
+
+        limits.check_payload(&payload)?;
+        Self::decode(category, opcode, &payload)
+    }
+
+    fn decode(category: Category, opcode: u16, value: &[u8]) -> Result<Self, PacketDecodeError> {
         Ok(match category {
-            Category::Authentication => ClientPacket::Authentication(
-                authentication::ClientAuthenticationPacket::decode_packet(opcode, &value)
-                    .ok_or_else(|| PacketDecodeError::InvalidPayload { category, opcode })?,
+            Category::Protocol => ServerPacket::Protocol(
+                protocol::ServerProtocolPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
             ),
-            Category::User => ClientPacket::User(
-                user::ClientUserPacket::decode_packet(opcode, &value)
-                    .ok_or_else(|| PacketDecodeError::InvalidPayload { category, opcode })?,
+            Category::Authentication => ServerPacket::Authentication(
+                authentication::ServerAuthenticationPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+            Category::User => ServerPacket::User(
+                user::ServerUserPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
+            ),
+            Category::Editor => ServerPacket::Editor(
+                editor::ServerEditorPacket::decode_packet(opcode, value)
+                    .map_err(|err| (category, err))?,
             ),
-            Category::Editor => todo!(),
         })
     }
 }
 
-impl TryInto<Vec<u8>> for ClientPacket {
+impl TryFrom<&[u8]> for ServerPacket {
+    type Error = PacketDecodeError;
+
+    /// Decodes a packet within the default [`DecodeLimits`]
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Self::decode_with_limits(value, &DecodeLimits::default())
+    }
+}
+
+impl ServerPacket {
+    /// The category and the opcode of the packet
+    pub fn opcode(&self) -> (Category, u16) {
+        match self {
+            ServerPacket::Protocol(packet) => (Category::Protocol, packet.as_opcode()),
+            ServerPacket::Authentication(packet) => (Category::Authentication, packet.as_opcode()),
+            ServerPacket::User(packet) => (Category::User, packet.as_opcode()),
+            ServerPacket::Editor(packet) => (Category::Editor, packet.as_opcode()),
+        }
+    }
+
+    /// Whether the packet is an event, sent without being requested, see [`EVENT_OPCODES`]
+    pub fn is_event(&self) -> bool {
+        EVENT_OPCODES.contains(&self.opcode().1)
+    }
+
+    /// Encodes the packet, its payload is compressed when it's worth it, see [`Compression`].
+    /// Only send these to a client that accepted the `compression` extension.
+    pub fn encode_compressed(self, compression: &Compression) -> Result<Vec<u8>, ValueWriteError> {
+        let (category, opcode, payload) = self.into_parts();
+
+        let Some(compressed) = payload.as_deref().and_then(|payload| compression.compress(payload)) else {
+            return encode_packet(category, opcode, payload);
+        };
+
+        let mut buffer = Vec::with_capacity(compressed.len() + 12);
+
+        write_array_len(&mut buffer, 3)?;
+        write_u32(&mut buffer, (category as u32) << 16 | opcode as u32)?;
+        write_pfix(&mut buffer, compression::FLAG_COMPRESSED).map_err(ValueWriteError::InvalidMarkerWrite)?;
+        write_bin(&mut buffer, &compressed)?;
+
+        Ok(buffer)
+    }
+
+    fn into_parts(self) -> (Category, u16, Option<Vec<u8>>) {
+        match self {
+            ServerPacket::Protocol(packet) => {
+                (Category::Protocol, packet.as_opcode(), packet.encode_payload())
+            }
+            ServerPacket::Authentication(packet) => (
+                Category::Authentication,
+                packet.as_opcode(),
+                packet.encode_payload(),
+            ),
+            ServerPacket::User(packet) => {
+                (Category::User, packet.as_opcode(), packet.encode_payload())
+            }
+            ServerPacket::Editor(packet) => {
+                (Category::Editor, packet.as_opcode(), packet.encode_payload())
+            }
+        }
+    }
+}
+
+impl TryInto<Vec<u8>> for ServerPacket {
     type Error = ValueWriteError;
 
     fn try_into(self) -> Result<Vec<u8>, Self::Error> {
-        // seems a bit of a waste to implement
-        // servers don't need to serialize client packets
-        unimplemented!()
+        let (category, opcode, payload) = self.into_parts();
+
+        encode_packet(category, opcode, payload)
     }
 }
 
-pub enum ServerPacket {
+macro_rules! impl_from_category {
+    ($($variant:ident($packet:ty)),* $(,)?) => {
+        $(
+            impl From<$packet> for ServerPacket {
+                fn from(packet: $packet) -> Self {
+                    ServerPacket::$variant(packet)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_category!(
+    Protocol(protocol::ServerProtocolPacket),
     Authentication(authentication::ServerAuthenticationPacket),
     User(user::ServerUserPacket),
     Editor(editor::ServerEditorPacket),
+);
+
+/// Writes a packet as an array of two items, the opcode (prefixed by its category) and the
+/// payload. An empty payload (packets without fields) is written as nil.
+fn encode_packet(
+    category: Category,
+    opcode: u16,
+    payload: Option<Vec<u8>>,
+) -> Result<Vec<u8>, ValueWriteError> {
+    let payload = payload.ok_or_else(|| {
+        ValueWriteError::InvalidDataWrite(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the payload couldn't be encoded",
+        ))
+    })?;
+
+    let mut buffer = Vec::with_capacity(payload.len() + 6);
+
+    write_array_len(&mut buffer, 2)?;
+    write_u32(&mut buffer, (category as u32) << 16 | opcode as u32)?;
+
+    if payload.is_empty() {
+        write_nil(&mut buffer).map_err(ValueWriteError::InvalidMarkerWrite)?;
+    } else {
+        buffer.extend_from_slice(&payload);
+    }
+
+    Ok(buffer)
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 #[repr(u16)]
 pub enum Category {
+    Protocol = 0x00,
     Authentication = 0x01,
     User = 0x02,
     Editor = 0x03,
@@ -117,6 +381,7 @@ impl TryFrom<u16> for Category {
 
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         Ok(match value {
+            0x00 => Category::Protocol,
             0x01 => Category::Authentication,
             0x02 => Category::User,
             0x03 => Category::Editor,
@@ -127,64 +392,202 @@ impl TryFrom<u16> for Category {
 }
 
 /// The trait that will be implemented in every packets
-pub trait Packet
+///
+/// `'a` is the lifetime of the payload buffer given to [`Packet::decode_packet`], packets with
+/// borrowed fields such as `&'a str` are decoded without copying from the buffer.
+pub trait Packet<'a>
 where
     Self: Sized,
 {
-    fn decode_packet(opcode: u16, payload: &[u8]) -> Option<Self>;
+    /// Decodes the payload of the packet with the given opcode, fields with `#[validate(...)]`
+    /// rules are checked here.
+    fn decode_packet(opcode: u16, payload: &'a [u8]) -> Result<Self, PacketCategoryDecodeError>;
 
     fn as_opcode(&self) -> u16;
     fn encode_payload(self) -> Option<Vec<u8>>;
 }
 
+/// Items that the code generated by the `Packet` derive refers to, glob import this on the
+/// module where the packets are defined.
+pub mod prelude {
+    pub use crate::schema::{
+        FieldSchema, PacketSchema, PayloadSchema, TypeKind, TypeSchema, TypeVariantSchema,
+        VariantSchema,
+    };
+    pub use crate::{Packet, PacketCategoryDecodeError, Request, ValidationError, EVENT_OPCODES};
+    pub use protocol_derive::{Packet, Payload};
+}
+
+/// Opcodes in this range are errors, they are allowed as the response of any request.
+pub const ERROR_OPCODES: std::ops::RangeInclusive<u16> = 0xff00..=0xffff;
+
+/// Server packets with opcodes in this range are events, sent without being requested (a
+/// project list that changed, the progress of a render...). They are never a response.
+pub const EVENT_OPCODES: std::ops::RangeInclusive<u16> = 0xfe00..=0xfeff;
+
+/// Implemented on packets that declare their responses with `#[responds_with(...)]`.
+///
+/// A request may only be answered with one of the responses it declares, or with an error (see
+/// [`ERROR_OPCODES`]). Responses on another category are not covered.
+pub trait Request {
+    /// The packet type of the responses
+    type Response;
+
+    /// Returns the check of [`Request::declares_response`] for this request. It doesn't borrow
+    /// the request, to check a response made once the request (and the payload it borrows) is
+    /// dropped, see [`response_allowed`].
+    fn response_check(&self) -> fn(&Self::Response) -> bool;
+
+    /// Checks if the given response is one that is declared for this request, errors that
+    /// aren't declared explicitly are not counted.
+    fn declares_response(&self, response: &Self::Response) -> bool {
+        (self.response_check())(response)
+    }
+
+    /// Checks if the given response is allowed to be sent as the response of this request.
+    fn allows_response(&self, response: &Self::Response) -> bool
+    where
+        Self::Response: for<'a> Packet<'a>,
+    {
+        response_allowed(self.response_check(), response)
+    }
+}
+
+/// Checks if the given response is allowed for the request `check` was returned by (see
+/// [`Request::response_check`]), like [`Request::allows_response`].
+pub fn response_allowed<R: for<'a> Packet<'a>>(check: fn(&R) -> bool, response: &R) -> bool {
+    ERROR_OPCODES.contains(&response.as_opcode()) || check(response)
+}
+
 // +===========================+
 // |     Packet Categories     |
 // +===========================+
 //
 // These modules includes opcodes of each categories, both for the server and client.
 //
-// There are three categories as defined in the `Category` enum:
+// There are four categories as defined in the `Category` enum:
+// - Protocol: 0x0
 // - Authentication: 0x1
 // - User: 0x2
 // - Editor: 0x3
 
+// >> Protocol Packet Category
+pub mod protocol {
+    use super::prelude::*;
+
+    #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub enum ClientProtocolPacket {
+        #[opcode(0x00)]
+        SuccessResp,
+        /// Accepts the extensions (from the ones listed in the protocol version packet) that
+        /// the client supports
+        #[opcode(0x10)]
+        #[responds_with(ServerProtocolPacket::ExtensionsAccepted)]
+        AcceptExtensions { extensions: Vec<String> },
+        /// Takes back a session after reconnecting, `last_event` is the number of events that
+        /// were received on it
+        #[opcode(0x20)]
+        #[responds_with(ServerProtocolPacket::SessionResumed, ServerProtocolPacket::ResumeFailed)]
+        ResumeSession { token: String, last_event: u64 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub enum ServerProtocolPacket {
+        #[opcode(0x00)]
+        SuccessResp,
+        /// The extensions that are used from now on, the ones the server doesn't support are
+        /// left out
+        #[opcode(0x10)]
+        ExtensionsAccepted { extensions: Vec<String> },
+        /// Sent after the protocol version packet, the token resumes the session once the
+        /// client reconnects
+        #[opcode(0x20)]
+        SessionStarted { token: String },
+        /// The session is resumed, the events that were missed follow
+        #[opcode(0x21)]
+        SessionResumed,
+        /// The session expired, or too many events were missed to replay them
+        #[opcode(0x22)]
+        ResumeFailed,
+
+        // events
+        /// The server is about to close the connection, such as when it is shutting down
+        #[opcode(0xfe00)]
+        SessionTerminating { reason: String },
+    }
+}
+
 // >> Authentication Packet Category
 pub mod authentication {
-    use super::Packet;
-    use protocol_derive::Packet;
+    use super::prelude::*;
 
     #[derive(Debug, Clone, PartialEq, Packet)]
-    pub enum ClientAuthenticationPacket {
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub enum ClientAuthenticationPacket<'a> {
         #[opcode(0x00)]
         SuccessResp,
         #[opcode(0x10)]
+        #[responds_with(
+            ServerAuthenticationPacket::LoginSuccess,
+            ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+        )]
         Login {
-            #[from_cloned]
-            username: String,
-            #[from_cloned]
-            password: String,
+            username: &'a str,
+            password: &'a str,
         },
         #[opcode(0x11)]
+        #[responds_with(
+            ServerAuthenticationPacket::LoginSuccess,
+            ServerAuthenticationPacket::LoginFailedTokenExpired
+        )]
         LoginWithToken {
-            #[from_cloned]
-            token: String,
+            token: &'a str,
         },
+        /// The invite code is only needed when the server registers users by invitation, see
+        /// `RegisterCheckEnabled`
         #[opcode(0x20)]
+        #[responds_with(
+            ServerAuthenticationPacket::SuccessResp,
+            ServerAuthenticationPacket::RegisterFailedUsernameTaken,
+            ServerAuthenticationPacket::RegisterFailedFeatureDisabled,
+            ServerAuthenticationPacket::RegisterFailedInvalidInvite,
+            ServerAuthenticationPacket::RegisterFailedUsernameInvalid
+        )]
         Register {
-            #[from_cloned]
-            username: String,
-            #[from_cloned]
-            password: String,
+            #[validate(len(min = 3, max = 32), regex = "^[A-Za-z0-9_.-]+$")]
+            username: &'a str,
+            #[validate(len(min = 8, max = 128))]
+            password: &'a str,
+            invite: Option<&'a str>,
         },
         #[opcode(0x21)]
+        #[responds_with(
+            ServerAuthenticationPacket::SuccessResp,
+            ServerAuthenticationPacket::RegisterFailedFeatureDisabled,
+            ServerAuthenticationPacket::RegisterInviteOnly
+        )]
         RegisterCheckEnabled,
+        /// Checks whether a username can be registered, the server answers that registration is
+        /// disabled instead when it is
         #[opcode(0xf0)]
-        UsernameCheckExists,
+        #[responds_with(
+            ServerAuthenticationPacket::SuccessResp,
+            ServerAuthenticationPacket::RegisterFailedUsernameTaken,
+            ServerAuthenticationPacket::RegisterFailedUsernameInvalid,
+            ServerAuthenticationPacket::RegisterFailedFeatureDisabled
+        )]
+        UsernameCheckExists {
+            username: &'a str,
+        },
         #[opcode(0x00ff)]
+        #[responds_with(ServerAuthenticationPacket::SuccessResp)]
         Logout,
     }
 
     #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
     pub enum ServerAuthenticationPacket {
         #[opcode(0x00)]
         SuccessResp,
@@ -201,6 +604,19 @@ pub mod authentication {
         RegisterFailedUsernameTaken,
         #[opcode(0x21)]
         RegisterFailedFeatureDisabled,
+        /// The server registers users by invitation, and the invite code is missing, unknown
+        /// or was already used
+        #[opcode(0x22)]
+        RegisterFailedInvalidInvite,
+        /// The username is reserved, or doesn't follow the rules of the usernames
+        #[opcode(0x23)]
+        RegisterFailedUsernameInvalid,
+        /// Registration is enabled, but only with an invite code
+        #[opcode(0x24)]
+        RegisterInviteOnly,
+        /// The server failed to process the request
+        #[opcode(0xfffe)]
+        ErrorInternal,
         #[opcode(0xffff)]
         ErrorAlreadyLoggedIn,
     }
@@ -208,31 +624,56 @@ pub mod authentication {
 
 // >> User Packet Category
 pub mod user {
-    use super::Packet;
-    use protocol_derive::Packet;
+    use super::prelude::*;
 
     #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
     pub enum ClientUserPacket {
         #[opcode(0x00)]
         SuccessResp,
 
         #[opcode(0x01)]
+        #[responds_with(ServerUserPacket::UsernameResp)]
         GetUsername,
+        #[opcode(0x02)]
+        #[responds_with(ServerUserPacket::ProfileResp)]
+        GetProfile,
+        /// Replaces the profile of the user, the fields left out are cleared. Responds with
+        /// the updated profile
+        #[opcode(0x03)]
+        #[responds_with(ServerUserPacket::ProfileResp)]
+        UpdateProfile {
+            #[validate(len(min = 1, max = 64))]
+            display_name: Option<String>,
+            #[validate(len(max = 254), regex = "^[^@\\s]+@[^@\\s]+$")]
+            email: Option<String>,
+            avatar: Option<u64>,
+        },
 
         #[opcode(0x10)]
+        #[responds_with(ServerUserPacket::ProjectsListResp)]
         RetrieveProjects,
         #[opcode(0x11)]
-        RetrieveProjectsPaged { offset: u64, count: u64 },
+        #[responds_with(ServerUserPacket::ProjectsListResp)]
+        RetrieveProjectsPaged {
+            offset: u64,
+            #[validate(range(max = 100))]
+            count: u64,
+        },
         #[opcode(0x12)]
+        #[responds_with(ServerUserPacket::ProjectsTotalResp)]
         RetrieveProjectsTotal,
         #[opcode(0x13)]
+        #[responds_with(ServerUserPacket::ProjectImageResp)]
         RetrieveProjectImage { imgid: u64 },
 
+        // responds on the editor category
         #[opcode(0x1f)]
         OpenProject,
     }
 
     #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
     pub enum ServerUserPacket {
         #[opcode(0x00)]
         SuccessResp,
@@ -242,77 +683,88 @@ pub mod user {
             #[from_cloned]
             username: String,
         },
+        #[opcode(0x02)]
+        ProfileResp { profile: UserProfile },
 
         #[opcode(0x10)]
-        ProjectsListResp {
-            // projects: ProjectData,
-            _temp: u64,
-        },
+        ProjectsListResp { projects: Vec<ProjectData> },
         #[opcode(0x11)]
         ProjectsTotalResp { total: u64 },
         #[opcode(0x12)]
         ProjectImageResp {
-            #[from_cloned]
+            #[packet(bytes)]
             data: Vec<u8>,
         },
 
+        // events
+        /// A project of the user was created, renamed or deleted, the list should be retrieved
+        /// again
+        #[opcode(0xfe00)]
+        ProjectsListChanged,
+
+        /// The server failed to process the request
+        #[opcode(0xfffe)]
+        ErrorInternal,
         #[opcode(0xffff)]
         ErrorNotAuthenticated,
     }
 
-    fn decode_u8_array(val: rmpv::ValueRef) -> Option<Vec<u8>> {
-        todo!()
-    }
-
-    #[derive(Clone, Debug, PartialEq)]
+    #[derive(Clone, Debug, PartialEq, Payload)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
     pub struct ProjectData {
         pub id: u32,
         pub title: String,
+        /// Timestamps
         pub lastedit: u64,
         pub created: u64,
+        /// Used on `RetrieveProjectImage`
         pub imgid: u32,
     }
 
-    impl Into<rmpv::Value> for ProjectData {
-        fn into(self) -> rmpv::Value {
-            todo!()
-        }
-    }
-
-    fn decode_project_data(val: rmpv::ValueRef) -> Option<ProjectData> {
-        todo!()
+    #[derive(Clone, Debug, PartialEq, Payload)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub struct UserProfile {
+        pub uid: u64,
+        pub username: String,
+        /// Shown instead of the username, when it's set
+        pub display_name: Option<String>,
+        pub email: Option<String>,
+        /// The blob of the avatar picture
+        pub avatar: Option<u64>,
+        /// `user` or `admin`
+        pub role: String,
+        /// Timestamps, `last_login` is none if the user never logged in
+        pub created: u64,
+        pub last_login: Option<u64>,
     }
 }
 
 // >> Editor Packet Category
 pub mod editor {
-    #[derive(Clone, Debug, PartialEq)]
-    pub struct ClientEditorPacket {
-        pub opcode: ClientOpcode,
-        pub payload: Option<ClientPacketPayload>,
-    }
+    use super::prelude::*;
 
-    #[derive(Clone, Debug, PartialEq)]
-    pub struct ServerEditorPacket {
-        pub opcode: ServerOpcode,
-        pub payload: Option<ServerPacketPayload>,
-    }
-
-    #[repr(u16)]
-    #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-    pub enum ClientOpcode {
-        SuccessResp = 0x00,
+    #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub enum ClientEditorPacket {
+        #[opcode(0x00)]
+        SuccessResp,
     }
 
-    #[derive(Clone, Debug, PartialEq)]
-    pub enum ClientPacketPayload {}
+    #[derive(Debug, Clone, PartialEq, Packet)]
+    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
+    pub enum ServerEditorPacket {
+        #[opcode(0x00)]
+        SuccessResp,
 
-    #[repr(u16)]
-    #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-    pub enum ServerOpcode {
-        SuccessResp = 0x00,
+        // events
+        /// Another user opened the project
+        #[opcode(0xfe00)]
+        UserJoined { username: String },
+        /// Another user closed the project
+        #[opcode(0xfe01)]
+        UserLeft { username: String },
+        /// A render job of the project rendered another frame
+        #[opcode(0xfe10)]
+        RenderProgress { job: u32, frame: u64, frames: u64 },
     }
-
-    #[derive(Clone, Debug, PartialEq)]
-    pub enum ServerPacketPayload {}
 }
@@ -0,0 +1,202 @@
+use rmp::{decode::read_marker, Marker};
+
+use super::PacketDecodeError;
+
+/// Limits of the packets that are decoded, checked before anything is decoded so that a packet
+/// can't make the decoder allocate more than its limits allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The size of the whole packet, in bytes
+    pub max_size: usize,
+    /// How deep arrays and maps can be nested, the packet itself is an array at depth 1
+    pub max_depth: usize,
+    /// The number of items of an array, or entries of a map
+    pub max_collection_len: usize,
+    /// The length of a string or a binary, in bytes
+    pub max_string_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_size: 64 * 1024,
+            max_depth: 16,
+            max_collection_len: 1024,
+            max_string_len: 16 * 1024,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Walks through the msgpack value in `bytes` without decoding it, and checks it against
+    /// the limits. Malformed or truncated values are left for the decoder to report.
+    pub fn check(&self, bytes: &[u8]) -> Result<(), PacketDecodeError> {
+        self.check_size(bytes)?;
+
+        // the number of values left to walk through in each of the arrays and maps we're in,
+        // starting with the packet itself
+        self.walk(bytes, vec![1])
+    }
+
+    /// Checks a payload that was sent apart from its packet (decompressed), it is nested in the
+    /// packet as if it was never taken out of it.
+    pub(crate) fn check_payload(&self, bytes: &[u8]) -> Result<(), PacketDecodeError> {
+        self.check_size(bytes)?;
+
+        // the packet has nothing else left to walk through than the payload
+        self.walk(bytes, vec![0, 1])
+    }
+
+    fn check_size(&self, bytes: &[u8]) -> Result<(), PacketDecodeError> {
+        if bytes.len() > self.max_size {
+            return Err(PacketDecodeError::TooLarge {
+                size: bytes.len(),
+                max: self.max_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn walk(&self, mut bytes: &[u8], mut remaining: Vec<usize>) -> Result<(), PacketDecodeError> {
+        while let Some(left) = remaining.last_mut() {
+            if *left == 0 {
+                remaining.pop();
+                continue;
+            }
+
+            *left -= 1;
+
+            let Ok(marker) = read_marker(&mut bytes) else {
+                return Ok(());
+            };
+
+            let skip = match marker {
+                Marker::FixPos(_)
+                | Marker::FixNeg(_)
+                | Marker::Null
+                | Marker::True
+                | Marker::False
+                | Marker::Reserved => 0,
+
+                Marker::U8 | Marker::I8 => 1,
+                Marker::U16 | Marker::I16 => 2,
+                Marker::U32 | Marker::I32 | Marker::F32 => 4,
+                Marker::U64 | Marker::I64 | Marker::F64 => 8,
+
+                Marker::FixStr(len) => self.check_string(len as usize)?,
+                Marker::Str8 | Marker::Bin8 => match read_len(&mut bytes, 1) {
+                    Some(len) => self.check_string(len)?,
+                    None => return Ok(()),
+                },
+                Marker::Str16 | Marker::Bin16 => match read_len(&mut bytes, 2) {
+                    Some(len) => self.check_string(len)?,
+                    None => return Ok(()),
+                },
+                Marker::Str32 | Marker::Bin32 => match read_len(&mut bytes, 4) {
+                    Some(len) => self.check_string(len)?,
+                    None => return Ok(()),
+                },
+
+                Marker::FixArray(len) => {
+                    self.enter_collection(&mut remaining, len as usize, 1)?;
+                    0
+                }
+                Marker::Array16 | Marker::Array32 => {
+                    let size = if marker == Marker::Array16 { 2 } else { 4 };
+                    let Some(len) = read_len(&mut bytes, size) else {
+                        return Ok(());
+                    };
+
+                    self.enter_collection(&mut remaining, len, 1)?;
+                    0
+                }
+                Marker::FixMap(len) => {
+                    self.enter_collection(&mut remaining, len as usize, 2)?;
+                    0
+                }
+                Marker::Map16 | Marker::Map32 => {
+                    let size = if marker == Marker::Map16 { 2 } else { 4 };
+                    let Some(len) = read_len(&mut bytes, size) else {
+                        return Ok(());
+                    };
+
+                    self.enter_collection(&mut remaining, len, 2)?;
+                    0
+                }
+
+                // the type of the extension, then its data
+                Marker::FixExt1 => 2,
+                Marker::FixExt2 => 3,
+                Marker::FixExt4 => 5,
+                Marker::FixExt8 => 9,
+                Marker::FixExt16 => 17,
+                Marker::Ext8 | Marker::Ext16 | Marker::Ext32 => {
+                    let size = match marker {
+                        Marker::Ext8 => 1,
+                        Marker::Ext16 => 2,
+                        _ => 4,
+                    };
+
+                    match read_len(&mut bytes, size) {
+                        Some(len) => self.check_string(len)? + 1,
+                        None => return Ok(()),
+                    }
+                }
+            };
+
+            let Some(rest) = bytes.get(skip..) else {
+                return Ok(());
+            };
+
+            bytes = rest;
+        }
+
+        Ok(())
+    }
+
+    fn check_string(&self, len: usize) -> Result<usize, PacketDecodeError> {
+        if len > self.max_string_len {
+            return Err(PacketDecodeError::StringTooLong {
+                len,
+                max: self.max_string_len,
+            });
+        }
+
+        Ok(len)
+    }
+
+    /// Enters an array (1 value per item) or a map (2 values per entry) of `len` items
+    fn enter_collection(
+        &self,
+        remaining: &mut Vec<usize>,
+        len: usize,
+        values_per_item: usize,
+    ) -> Result<(), PacketDecodeError> {
+        if len > self.max_collection_len {
+            return Err(PacketDecodeError::CollectionTooLong {
+                len,
+                max: self.max_collection_len,
+            });
+        }
+
+        // `remaining` holds the packet itself on top of the collections we're in
+        if remaining.len() > self.max_depth {
+            return Err(PacketDecodeError::TooDeep {
+                max: self.max_depth,
+            });
+        }
+
+        remaining.push(len * values_per_item);
+
+        Ok(())
+    }
+}
+
+/// Reads a big-endian length of `size` bytes
+fn read_len(bytes: &mut &[u8], size: usize) -> Option<usize> {
+    let (len, rest) = (bytes.get(..size)?, bytes.get(size..)?);
+    *bytes = rest;
+
+    Some(len.iter().fold(0, |acc, byte| acc << 8 | *byte as usize))
+}
@@ -0,0 +1,389 @@
+//! A machine-readable description of the packets of the protocol, generated by the `Packet`
+//! derive. It is exported by the `dalang-schema` binary as JSON, TypeScript definitions, and a
+//! javascript opcodes module, so other clients don't need to mirror the packets by hand.
+
+use serde::Serialize;
+
+use super::{
+    authentication, editor, protocol, user, Category, ERROR_OPCODES, EVENT_OPCODES, EXTENSIONS,
+    VERSION,
+};
+
+/// Implemented by every packet enums deriving `Packet`
+pub trait PacketSchema {
+    /// The variants of the packet, in declaration order
+    const VARIANTS: &'static [VariantSchema];
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantSchema {
+    pub name: &'static str,
+    pub opcode: u16,
+    /// Named fields are encoded as an array in declaration order, see `#[packet(compact)]`
+    pub compact: bool,
+    pub fields: &'static [FieldSchema],
+    /// Names of the variants declared with `#[responds_with(...)]`
+    pub responses: &'static [&'static str],
+    /// Sent by the server without being requested, its opcode is in [`crate::EVENT_OPCODES`]
+    pub event: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldSchema {
+    /// The name of the field, or its index on a tuple variant
+    pub name: &'static str,
+    /// `str`, `bin`, `bool`, a number (`u8`-`u64`, `i8`-`i64`, `f32`, `f64`), `array<T>`,
+    /// `map<K,V>`, `option<T>`, or the name of another type
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+}
+
+/// Implemented by every types deriving `Payload`, the types used in the fields of packets
+pub trait PayloadSchema {
+    const SCHEMA: TypeSchema;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TypeSchema {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub kind: TypeKind,
+}
+
+/// How a type is encoded, see the "Payload types" section of `specs/protocol.md`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TypeKind {
+    /// A map of the fields, or an array in declaration order if compact. Tuple structs are
+    /// always compact.
+    Struct {
+        compact: bool,
+        fields: &'static [FieldSchema],
+    },
+    /// A struct of a single unnamed field, encoded as that field
+    Newtype {
+        #[serde(rename = "type")]
+        ty: &'static str,
+    },
+    /// Encoded as nil
+    Unit,
+    /// An array of the name of the variant and its fields
+    Enum {
+        variants: &'static [TypeVariantSchema],
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TypeVariantSchema {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategorySchema {
+    pub name: &'static str,
+    pub id: u16,
+    /// Packets sent by the client
+    pub client: &'static [VariantSchema],
+    /// Packets sent by the server
+    pub server: &'static [VariantSchema],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtocolSchema {
+    pub version: &'static str,
+    pub extensions: &'static [&'static str],
+    pub categories: Vec<CategorySchema>,
+    /// The types used in the fields of packets
+    pub types: Vec<TypeSchema>,
+}
+
+/// Retrieves the schema of every packet categories
+pub fn protocol_schema() -> ProtocolSchema {
+    use authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket};
+    use editor::{ClientEditorPacket, ServerEditorPacket};
+    use protocol::{ClientProtocolPacket, ServerProtocolPacket};
+    use user::{ClientUserPacket, ProjectData, ServerUserPacket, UserProfile};
+
+    ProtocolSchema {
+        version: VERSION,
+        extensions: &EXTENSIONS,
+        categories: vec![
+            CategorySchema {
+                name: "Protocol",
+                id: Category::Protocol as u16,
+                client: ClientProtocolPacket::VARIANTS,
+                server: ServerProtocolPacket::VARIANTS,
+            },
+            CategorySchema {
+                name: "Authentication",
+                id: Category::Authentication as u16,
+                client: ClientAuthenticationPacket::VARIANTS,
+                server: ServerAuthenticationPacket::VARIANTS,
+            },
+            CategorySchema {
+                name: "User",
+                id: Category::User as u16,
+                client: ClientUserPacket::VARIANTS,
+                server: ServerUserPacket::VARIANTS,
+            },
+            CategorySchema {
+                name: "Editor",
+                id: Category::Editor as u16,
+                client: ClientEditorPacket::VARIANTS,
+                server: ServerEditorPacket::VARIANTS,
+            },
+        ],
+        types: vec![ProjectData::SCHEMA, UserProfile::SCHEMA],
+    }
+}
+
+const GENERATED_HEADER: &str = "Generated by `cargo run -p dalang-protocol --bin dalang-schema`, do not edit.";
+
+/// Exports the schema as JSON
+pub fn to_json(schema: &ProtocolSchema) -> String {
+    let mut json = serde_json::to_string_pretty(schema).expect("the schema is always valid json");
+    json.push('\n');
+    json
+}
+
+/// Exports the schema as TypeScript definitions, every category has a union type of the packets
+/// sent by the client and the server, discriminated by their opcodes.
+pub fn to_typescript(schema: &ProtocolSchema) -> String {
+    let mut ts = format!("// {GENERATED_HEADER}\n");
+
+    if !schema.types.is_empty() {
+        ts += "\n// Payload Types ==========\n";
+    }
+
+    for typ in &schema.types {
+        ts += &format!("\nexport type {} =", typ.name);
+
+        match &typ.kind {
+            TypeKind::Enum { variants } => {
+                for variant in variants.iter() {
+                    ts += &format!(
+                        "\n  | [\"{}\", {}]",
+                        variant.name,
+                        typescript_kind(&variant.kind, &schema.types)
+                    );
+                }
+            }
+            kind => ts += &format!(" {}", typescript_kind(kind, &schema.types)),
+        }
+
+        ts += ";\n";
+    }
+
+    for category in &schema.categories {
+        ts += &format!("\n// {} Category ==========\n", category.name);
+
+        for (side, variants) in [("Client", category.client), ("Server", category.server)] {
+            ts += &format!("\nexport type {side}{}Packet =", category.name);
+
+            if variants.is_empty() {
+                ts += " never;\n";
+                continue;
+            }
+
+            for variant in variants {
+                ts += &format!(
+                    "\n  // {}{}\n  | {{ opcode: 0x{:02x}; payload: {} }}",
+                    variant.name,
+                    if variant.event { " (event)" } else { "" },
+                    variant.opcode,
+                    typescript_fields(variant.fields, variant.compact, &schema.types)
+                );
+            }
+
+            ts += ";\n";
+        }
+    }
+
+    ts
+}
+
+fn typescript_kind(kind: &TypeKind, types: &[TypeSchema]) -> String {
+    match kind {
+        TypeKind::Struct { compact, fields } => typescript_fields(fields, *compact, types),
+        TypeKind::Newtype { ty } => typescript_type(ty, types),
+        TypeKind::Unit => "null".to_string(),
+        TypeKind::Enum { variants } => variants
+            .iter()
+            .map(|variant| {
+                format!(
+                    "[\"{}\", {}]",
+                    variant.name,
+                    typescript_kind(&variant.kind, types)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" | "),
+    }
+}
+
+fn typescript_fields(fields: &[FieldSchema], compact: bool, types: &[TypeSchema]) -> String {
+    let Some(first) = fields.first() else {
+        return "null".to_string();
+    };
+
+    let is_tuple = first.name.starts_with(|c: char| c.is_ascii_digit());
+
+    let fields = fields.iter().map(|field| {
+        let ty = typescript_type(field.ty, types);
+
+        if is_tuple {
+            ty
+        } else {
+            format!("{}: {ty}", field.name)
+        }
+    });
+
+    if is_tuple || compact {
+        format!("[{}]", fields.collect::<Vec<_>>().join(", "))
+    } else {
+        format!("{{ {} }}", fields.collect::<Vec<_>>().join("; "))
+    }
+}
+
+fn typescript_type(ty: &str, types: &[TypeSchema]) -> String {
+    if let Some((container, args)) = split_generic(ty) {
+        let args = args
+            .into_iter()
+            .map(|arg| typescript_type(arg, types))
+            .collect::<Vec<_>>();
+
+        match (container, args.as_slice()) {
+            ("array", [item]) => return format!("Array<{item}>"),
+            ("map", [key, val]) => return format!("Record<{key}, {val}>"),
+            ("option", [inner]) => return format!("{inner} | null"),
+            _ => (),
+        }
+    }
+
+    match ty {
+        "str" => "string".to_string(),
+        "bin" => "Uint8Array".to_string(),
+        "bool" => "boolean".to_string(),
+        "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" | "f32" | "f64" => {
+            "number".to_string()
+        }
+        _ if types.iter().any(|typ| typ.name == ty) => ty.to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// `map<str,array<u8>>` -> `("map", ["str", "array<u8>"])`
+fn split_generic(ty: &str) -> Option<(&str, Vec<&str>)> {
+    let (container, args) = ty.strip_suffix('>')?.split_once('<')?;
+
+    let mut depth = 0;
+    let mut start = 0;
+    let mut result = Vec::new();
+
+    for (idx, c) in args.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+
+    result.push(args[start..].trim());
+
+    Some((container, result))
+}
+
+/// Exports the schema as a javascript module containing the categories and opcodes as constants
+pub fn to_opcodes_js(schema: &ProtocolSchema) -> String {
+    let mut js = format!("// All the opcodes used in dalang\n// {GENERATED_HEADER}\n");
+
+    js += &format!(
+        "\n// Opcodes of errors, the response of any request\n\
+        export const ERROR_OPCODE_START = 0x{:x};\nexport const ERROR_OPCODE_END = 0x{:x};\n",
+        ERROR_OPCODES.start(),
+        ERROR_OPCODES.end()
+    );
+    js += &format!(
+        "\n// Opcodes of the events sent by the server without being requested\n\
+        export const EVENT_OPCODE_START = 0x{:x};\nexport const EVENT_OPCODE_END = 0x{:x};\n",
+        EVENT_OPCODES.start(),
+        EVENT_OPCODES.end()
+    );
+
+    for category in &schema.categories {
+        let category_name = screaming_snake_case(category.name);
+
+        js += &format!(
+            "\n// {} Category ==========\nexport const CATEGORY_{category_name} = 0x{:x};\n",
+            category.name, category.id
+        );
+
+        for (prefix, side, variants) in [
+            ("C", "Client", category.client),
+            ("S", "Server", category.server),
+        ] {
+            if variants.is_empty() {
+                continue;
+            }
+
+            js += &format!("\n// {side} opcodes\n");
+
+            for variant in variants {
+                js += &format!(
+                    "export const {prefix}_OPCODE_{category_name}_{} = 0x{:02x};",
+                    screaming_snake_case(variant.name),
+                    variant.opcode
+                );
+
+                if let Some(data) = opcodes_js_data(variant) {
+                    js += &format!(" // data: {data}");
+                }
+
+                js += "\n";
+            }
+        }
+    }
+
+    js
+}
+
+fn opcodes_js_data(variant: &VariantSchema) -> Option<String> {
+    let first = variant.fields.first()?;
+    let is_tuple = first.name.starts_with(|c: char| c.is_ascii_digit());
+
+    let fields = variant
+        .fields
+        .iter()
+        .map(|field| match is_tuple {
+            true => field.ty.to_string(),
+            false => format!("{}: {}", field.name, field.ty),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Some(match is_tuple || variant.compact {
+        true => format!("[{fields}]"),
+        false => format!("{{ {fields} }}"),
+    })
+}
+
+/// `LoginWithToken` -> `LOGIN_WITH_TOKEN`
+fn screaming_snake_case(name: &str) -> String {
+    let mut result = String::new();
+
+    for (idx, c) in name.char_indices() {
+        if c.is_ascii_uppercase() && idx != 0 {
+            result.push('_');
+        }
+
+        result.push(c.to_ascii_uppercase());
+    }
+
+    result
+}
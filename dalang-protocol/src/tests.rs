@@ -1,13 +1,25 @@
-use crate::Packet;
+use crate::{response_allowed, Packet, Request};
 
-use super::{authentication::{ClientAuthenticationPacket}, Category};
+#[cfg(feature = "codec")]
+mod codec;
+mod round_trip;
+mod vectors;
+
+use super::{
+    authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+    user::{ClientUserPacket, ProjectData, ServerUserPacket},
+    json, schema, Category, ClientPacket, Compression, DecodeLimits, JsonError, PacketDecodeError,
+    ServerPacket, ValidationError,
+};
 
 #[test]
 fn test_categories() {
+    assert_eq!(Category::Protocol as u16, 0x0);
     assert_eq!(Category::Authentication as u16, 0x1);
     assert_eq!(Category::User as u16, 0x2);
     assert_eq!(Category::Editor as u16, 0x3);
 
+    assert_eq!(Category::try_from(0x0), Ok(Category::Protocol));
     assert_eq!(Category::try_from(0x1), Ok(Category::Authentication));
     assert_eq!(Category::try_from(0x2), Ok(Category::User));
     assert_eq!(Category::try_from(0x3), Ok(Category::Editor));
@@ -29,8 +41,391 @@ fn test_client_auth_packet_login() {
     assert_eq!(
         packet,
         ClientAuthenticationPacket::Login {
-            username: "lorem".to_string(),
-            password: "ipsum".to_string()
+            username: "lorem",
+            password: "ipsum"
+        }
+    )
+}
+
+#[test]
+fn test_server_user_packet_project_image_is_bin() {
+    // payload of { data: bin([137, 80, 78, 71]) }
+    let expected: [u8; 12] = [129, 164, 100, 97, 116, 97, 196, 4, 137, 80, 78, 71];
+
+    let packet = ServerUserPacket::ProjectImageResp {
+        data: vec![137, 80, 78, 71],
+    };
+
+    assert_eq!(packet.encode_payload().expect("Failed to encode packet"), expected);
+}
+
+#[test]
+fn test_server_user_packet_projects_list() {
+    let packet = ServerUserPacket::ProjectsListResp {
+        projects: vec![ProjectData {
+            id: 1,
+            title: "intro".to_string(),
+            lastedit: 1700000000,
+            created: 1600000000,
+            imgid: 4,
+        }],
+    };
+
+    let payload = packet
+        .clone()
+        .encode_payload()
+        .expect("Failed to encode packet");
+
+    // payload of { projects: [{ id: 1, title: "intro", .. }] }
+    let value = rmpv::decode::read_value(&mut payload.as_slice()).unwrap();
+    assert_eq!(value["projects"][0]["title"].as_str(), Some("intro"));
+
+    let decoded = ServerUserPacket::decode_packet(0x10, &payload).expect("Failed to decode packet");
+    assert_eq!(decoded, packet);
+}
+
+#[test]
+fn test_client_auth_packet_responses() {
+    let login = ClientAuthenticationPacket::Login { username: "lorem", password: "ipsum" };
+
+    assert!(login.allows_response(&ServerAuthenticationPacket::LoginSuccess {
+        token: String::new()
+    }));
+    assert!(login.allows_response(
+        &ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+    ));
+    assert!(!login.allows_response(&ServerAuthenticationPacket::RegisterFailedUsernameTaken));
+
+    // errors are allowed even though they aren't declared
+    assert!(!login.declares_response(&ServerAuthenticationPacket::ErrorAlreadyLoggedIn));
+    assert!(login.allows_response(&ServerAuthenticationPacket::ErrorAlreadyLoggedIn));
+}
+
+#[test]
+fn test_response_allowed_after_the_request() {
+    let check = {
+        let username = String::from("lorem");
+        ClientAuthenticationPacket::UsernameCheckExists { username: &username }.response_check()
+    };
+
+    assert!(response_allowed(check, &ServerAuthenticationPacket::SuccessResp));
+    assert!(response_allowed(check, &ServerAuthenticationPacket::ErrorInternal));
+    assert!(!response_allowed(check, &ServerAuthenticationPacket::LoginSuccess { token: String::new() }));
+}
+
+#[test]
+fn test_client_user_packet_responses() {
+    let total = ClientUserPacket::RetrieveProjectsTotal;
+
+    assert!(total.allows_response(&ServerUserPacket::ProjectsTotalResp { total: 0 }));
+    assert!(total.allows_response(&ServerUserPacket::ErrorNotAuthenticated));
+    assert!(!total.allows_response(&ServerUserPacket::SuccessResp));
+}
+
+#[test]
+fn test_client_packet_register_validation() {
+    // packet of [0x10020, { username: "lo", password: "12345678" }]
+    let mut packet = vec![146, 206, 0, 1, 0, 32];
+    packet.extend(
+        ClientAuthenticationPacket::Register { username: "lo", password: "12345678", invite: None }
+            .encode_payload()
+            .expect("Failed to encode packet"),
+    );
+
+    let result = ClientPacket::try_from(packet.as_slice());
+
+    assert!(matches!(
+        result,
+        Err(PacketDecodeError::Validation {
+            category: Category::Authentication,
+            opcode: 0x20,
+            field: "username",
+            error: ValidationError::TooShort { min: 3 }
+        })
+    ));
+}
+
+#[test]
+fn test_client_packet_update_profile_validation() {
+    let update = |email: Option<&str>| -> Vec<u8> {
+        ClientPacket::User(ClientUserPacket::UpdateProfile {
+            display_name: None,
+            email: email.map(str::to_string),
+            avatar: Some(1),
+        })
+        .try_into()
+        .expect("Failed to encode packet")
+    };
+
+    // the rules only apply to the fields that are set
+    assert!(ClientPacket::try_from(update(None).as_slice()).is_ok());
+    assert!(ClientPacket::try_from(update(Some("lorem@ipsum.dolor")).as_slice()).is_ok());
+
+    assert!(matches!(
+        ClientPacket::try_from(update(Some("lorem ipsum")).as_slice()),
+        Err(PacketDecodeError::Validation {
+            category: Category::User,
+            opcode: 0x03,
+            field: "email",
+            error: ValidationError::PatternMismatch { .. }
+        })
+    ));
+}
+
+/// Encodes a login packet of the given username and password
+fn login_packet(username: &str, password: &str) -> Vec<u8> {
+    ClientPacket::Authentication(ClientAuthenticationPacket::Login { username, password })
+        .try_into()
+        .expect("Failed to encode packet")
+}
+
+#[test]
+fn test_decode_limits_size() {
+    let packet = login_packet("lorem", "ipsum");
+    let limits = DecodeLimits {
+        max_size: packet.len() - 1,
+        ..Default::default()
+    };
+
+    assert!(ClientPacket::decode_with_limits(&packet, &DecodeLimits::default()).is_ok());
+    assert!(matches!(
+        ClientPacket::decode_with_limits(&packet, &limits),
+        Err(PacketDecodeError::TooLarge { max, .. }) if max == packet.len() - 1
+    ));
+}
+
+#[test]
+fn test_decode_limits_string_len() {
+    let limits = DecodeLimits {
+        max_string_len: 8,
+        ..Default::default()
+    };
+
+    let packet = login_packet("lorem", "ipsum");
+    assert!(ClientPacket::decode_with_limits(&packet, &limits).is_ok());
+
+    let packet = login_packet("lorem", "a password that is too long");
+    assert!(matches!(
+        ClientPacket::decode_with_limits(&packet, &limits),
+        Err(PacketDecodeError::StringTooLong { len: 27, max: 8 })
+    ));
+}
+
+#[test]
+fn test_decode_limits_depth_and_collection_len() {
+    // packet of [0x10010, [[[[]]]]], the payload is nested 4 deep
+    let packet = [146, 206, 0, 1, 0, 16, 145, 145, 145, 144];
+
+    let limits = DecodeLimits {
+        max_depth: 4,
+        ..Default::default()
+    };
+    assert!(matches!(
+        ClientPacket::decode_with_limits(&packet, &limits),
+        Err(PacketDecodeError::TooDeep { max: 4 })
+    ));
+
+    // within the limits, it's just an invalid login payload
+    let limits = DecodeLimits {
+        max_depth: 5,
+        ..Default::default()
+    };
+    assert!(matches!(
+        ClientPacket::decode_with_limits(&packet, &limits),
+        Err(PacketDecodeError::InvalidPayload { .. })
+    ));
+
+    // packet of [0x10010, [nil x 65535]] that only carries the length of the array
+    let packet = [146, 206, 0, 1, 0, 16, 220, 255, 255];
+    assert!(matches!(
+        ClientPacket::try_from(packet.as_slice()),
+        Err(PacketDecodeError::CollectionTooLong { len: 65535, max: 1024 })
+    ));
+}
+
+fn projects_list(count: u32) -> ServerPacket {
+    ServerPacket::User(ServerUserPacket::ProjectsListResp {
+        projects: (0..count)
+            .map(|id| ProjectData {
+                id,
+                title: format!("project {id}"),
+                lastedit: 1700000000,
+                created: 1600000000,
+                imgid: id,
+            })
+            .collect(),
+    })
+}
+
+/// Deflates the given bytes, as a client would receive them from a compressed packet
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// A server packet of the User category and the given opcode, flagged as compressed
+fn compressed_packet(opcode: u8, compressed: &[u8]) -> Vec<u8> {
+    let mut packet = vec![147, 206, 0, 2, 0, opcode, 1];
+    rmp::encode::write_bin(&mut packet, compressed).unwrap();
+    packet
+}
+
+#[test]
+fn test_compressed_packet_round_trip() {
+    let packet = projects_list(100);
+
+    let plain: Vec<u8> = packet.clone().try_into().unwrap();
+    let compressed = packet.clone().encode_compressed(&Compression::default()).unwrap();
+
+    // an array of 3 items, where the flags say that the payload is compressed
+    assert_eq!(compressed[0], 147);
+    assert_eq!(compressed[6], 1);
+    assert!(compressed.len() < plain.len() / 2);
+
+    assert_eq!(ServerPacket::try_from(compressed.as_slice()).unwrap(), packet);
+}
+
+#[test]
+fn test_small_packet_is_not_compressed() {
+    let packet = projects_list(1);
+    let plain: Vec<u8> = packet.clone().try_into().unwrap();
+
+    assert_eq!(packet.encode_compressed(&Compression::default()).unwrap(), plain);
+}
+
+#[test]
+fn test_decompression_is_bounded_by_the_limits() {
+    // a payload of 1MiB of zeroes deflates down to about a kilobyte
+    let packet = compressed_packet(0x10, &deflate(&vec![0; 1024 * 1024]));
+    assert!(packet.len() < 2048);
+
+    assert!(matches!(
+        ServerPacket::try_from(packet.as_slice()),
+        Err(PacketDecodeError::TooLarge { max: 65536, .. })
+    ));
+
+    // the decompressed payload is held to the other limits as well, and is nested in the
+    // packet: [[[[]]]] is 5 deep
+    let packet = compressed_packet(0x10, &deflate(&[145, 145, 145, 144]));
+    let limits = DecodeLimits {
+        max_depth: 4,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        ServerPacket::decode_with_limits(&packet, &limits),
+        Err(PacketDecodeError::TooDeep { max: 4 })
+    ));
+}
+
+#[test]
+fn test_client_packets_are_never_compressed() {
+    let packet = [147, 206, 0, 1, 0, 16, 1, 196, 0];
+
+    assert!(matches!(
+        ClientPacket::try_from(packet.as_slice()),
+        Err(PacketDecodeError::InvalidStructure)
+    ));
+}
+
+#[test]
+fn test_events() {
+    assert!(ServerPacket::User(ServerUserPacket::ProjectsListChanged).is_event());
+    assert!(!ServerPacket::User(ServerUserPacket::SuccessResp).is_event());
+
+    for category in schema::protocol_schema().categories {
+        // events are only sent by the server, and never as a response
+        assert!(category.client.iter().all(|variant| !variant.event));
+
+        for response in category.client.iter().flat_map(|variant| variant.responses) {
+            assert!(!category
+                .server
+                .iter()
+                .any(|variant| variant.name == *response && variant.event));
         }
+    }
+}
+
+#[test]
+fn test_json_form() {
+    let packet = login_packet("lorem", "ipsum");
+
+    assert_eq!(
+        json::to_json(&packet, json::Sender::Client).unwrap(),
+        r#"{"packet":"Authentication.Login","payload":{"password":"ipsum","username":"lorem"}}"#
+    );
+
+    let from_json = json::from_json(
+        r#"{"packet": "Authentication.Login", "payload": {"username": "lorem", "password": "ipsum"}}"#,
+        json::Sender::Client,
     )
-}
\ No newline at end of file
+    .unwrap();
+
+    assert_eq!(
+        ClientPacket::try_from(from_json.as_slice()).unwrap(),
+        ClientPacket::try_from(packet.as_slice()).unwrap()
+    );
+
+    // packets without fields may leave out their payload
+    let from_json = json::from_json(r#"{"packet": "User.GetUsername"}"#, json::Sender::Client).unwrap();
+    assert_eq!(
+        ClientPacket::try_from(from_json.as_slice()).unwrap(),
+        ClientPacket::User(ClientUserPacket::GetUsername)
+    );
+}
+
+#[test]
+fn test_json_form_binary() {
+    let packet: Vec<u8> = ServerPacket::User(ServerUserPacket::ProjectImageResp { data: vec![0, 255] })
+        .try_into()
+        .unwrap();
+
+    let text = json::to_json(&packet, json::Sender::Server).unwrap();
+    assert_eq!(text, r#"{"packet":"User.ProjectImageResp","payload":{"data":{"$bin":"00ff"}}}"#);
+
+    assert_eq!(json::from_json(&text, json::Sender::Server).unwrap(), packet);
+}
+
+#[test]
+fn test_json_form_unknown_packet() {
+    // the opcodes of the client and the server are not shared
+    assert!(matches!(
+        json::from_json(r#"{"packet": "Authentication.LoginSuccess"}"#, json::Sender::Client),
+        Err(JsonError::UnknownPacket(name)) if name == "Authentication.LoginSuccess"
+    ));
+    assert!(matches!(
+        json::from_json(r#"["Authentication.Login"]"#, json::Sender::Client),
+        Err(JsonError::InvalidStructure)
+    ));
+}
+
+#[test]
+fn test_generated_bindings_up_to_date() {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let schema = schema::protocol_schema();
+
+    for (path, generated, kind) in [
+        ("specs/schema.json", schema::to_json(&schema), "json"),
+        (
+            "frontend/src/services/connection/protocol.d.ts",
+            schema::to_typescript(&schema),
+            "typescript",
+        ),
+        (
+            "frontend/src/services/connection/opcodes.js",
+            schema::to_opcodes_js(&schema),
+            "opcodes",
+        ),
+    ] {
+        let current = std::fs::read_to_string(root.join(path)).expect("Failed to read the file");
+
+        assert!(
+            current == generated,
+            "`{path}` is out of date, regenerate it with \
+            `cargo run -p dalang-protocol --bin dalang-schema -- {kind} > {path}`"
+        );
+    }
+}
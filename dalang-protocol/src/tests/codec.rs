@@ -0,0 +1,71 @@
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    authentication::ClientAuthenticationPacket,
+    codec::{CodecError, PacketCodec},
+    user::ServerUserPacket,
+    ClientPacket, DecodeLimits, PacketDecodeError, ServerPacket,
+};
+
+#[test]
+fn test_codec_round_trip() {
+    let mut codec = PacketCodec::default();
+    let mut buffer = BytesMut::new();
+
+    let login = ClientPacket::Authentication(ClientAuthenticationPacket::Login {
+        username: "lorem",
+        password: "ipsum",
+    });
+    let total = ServerPacket::User(ServerUserPacket::ProjectsTotalResp { total: 3 });
+
+    codec.encode(login.clone(), &mut buffer).unwrap();
+    codec.encode(total.clone(), &mut buffer).unwrap();
+
+    let frame = codec.decode(&mut buffer).unwrap().expect("a whole packet");
+    assert_eq!(ClientPacket::try_from(&frame[..]).unwrap(), login);
+
+    let frame = codec.decode(&mut buffer).unwrap().expect("a whole packet");
+    assert_eq!(ServerPacket::try_from(&frame[..]).unwrap(), total);
+
+    assert!(codec.decode(&mut buffer).unwrap().is_none());
+}
+
+#[test]
+fn test_codec_partial_packet() {
+    let mut codec = PacketCodec::default();
+
+    let mut encoded = BytesMut::new();
+    codec
+        .encode(ServerPacket::User(ServerUserPacket::SuccessResp), &mut encoded)
+        .unwrap();
+
+    // [0, 0, 0, 7][0x92, 0xce, 0, 2, 0, 0, nil]
+    assert_eq!(&encoded[..], [0, 0, 0, 7, 146, 206, 0, 2, 0, 0, 192]);
+
+    // the packet arrives byte by byte
+    let mut buffer = BytesMut::new();
+
+    for (idx, byte) in encoded.iter().enumerate() {
+        buffer.extend_from_slice(&[*byte]);
+
+        let frame = codec.decode(&mut buffer).unwrap();
+        assert_eq!(frame.is_some(), idx == encoded.len() - 1);
+    }
+}
+
+#[test]
+fn test_codec_too_large() {
+    let mut codec = PacketCodec::new(DecodeLimits {
+        max_size: 16,
+        ..Default::default()
+    });
+
+    // only the length of the packet is needed to reject it
+    let mut buffer = BytesMut::from(&[0, 0, 0, 17][..]);
+
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Err(CodecError::Decode(PacketDecodeError::TooLarge { size: 17, max: 16 }))
+    ));
+}
@@ -0,0 +1,139 @@
+//! Generated tests: every packet encodes and decodes back to itself, and no input makes the
+//! decoding panic. The packets are generated with `arbitrary` out of pseudo-random buffers from
+//! a fixed seed, so a failure is reproducible.
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::{
+    authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+    editor::{ClientEditorPacket, ServerEditorPacket},
+    protocol::{ClientProtocolPacket, ServerProtocolPacket},
+    user::{ClientUserPacket, ServerUserPacket},
+    Category, ClientPacket, Packet, PacketCategoryDecodeError,
+};
+
+const CASES: usize = 2000;
+
+/// Generates `count` buffers of pseudo-random lengths and bytes (splitmix64)
+fn random_buffers(seed: u64, count: usize) -> impl Iterator<Item = Vec<u8>> {
+    let mut state = seed;
+
+    let mut next = move || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    (0..count).map(move |_| {
+        let len = (next() % 512) as usize;
+        (0..len).map(|_| next() as u8).collect()
+    })
+}
+
+/// Checks that `decode_packet(as_opcode(p), encode_payload(p)) == p` for arbitrary values of
+/// the given packet. Packets that don't pass their `#[validate(...)]` rules are rejected with a
+/// validation error instead.
+macro_rules! round_trip_test {
+    ($name:ident, $packet:ty, $seed:expr) => {
+        #[test]
+        fn $name() {
+            for buffer in random_buffers($seed, CASES) {
+                let mut unstructured = Unstructured::new(&buffer);
+                let Ok(packet) = <$packet>::arbitrary(&mut unstructured) else {
+                    continue;
+                };
+
+                let opcode = packet.as_opcode();
+                let payload = packet
+                    .clone()
+                    .encode_payload()
+                    .expect("Failed to encode packet");
+
+                match <$packet>::decode_packet(opcode, &payload) {
+                    Ok(decoded) => assert_eq!(decoded, packet),
+                    Err(PacketCategoryDecodeError::Validation { .. }) => (),
+                    Err(err) => panic!("Failed to decode {packet:?}: {err:?}"),
+                }
+            }
+        }
+    };
+}
+
+round_trip_test!(
+    test_round_trip_client_authentication,
+    ClientAuthenticationPacket,
+    1
+);
+round_trip_test!(
+    test_round_trip_server_authentication,
+    ServerAuthenticationPacket,
+    2
+);
+round_trip_test!(test_round_trip_client_user, ClientUserPacket, 3);
+round_trip_test!(test_round_trip_server_user, ServerUserPacket, 4);
+round_trip_test!(test_round_trip_client_protocol, ClientProtocolPacket, 8);
+round_trip_test!(test_round_trip_server_protocol, ServerProtocolPacket, 9);
+round_trip_test!(test_round_trip_client_editor, ClientEditorPacket, 10);
+round_trip_test!(test_round_trip_server_editor, ServerEditorPacket, 11);
+
+#[test]
+fn test_round_trip_client_packet() {
+    for buffer in random_buffers(5, CASES) {
+        let mut unstructured = Unstructured::new(&buffer);
+
+        let packet = match unstructured.int_in_range(0..=3).unwrap() {
+            0 => match ClientAuthenticationPacket::arbitrary(&mut unstructured) {
+                Ok(packet) => ClientPacket::Authentication(packet),
+                Err(_) => continue,
+            },
+            1 => match ClientProtocolPacket::arbitrary(&mut unstructured) {
+                Ok(packet) => ClientPacket::Protocol(packet),
+                Err(_) => continue,
+            },
+            2 => match ClientEditorPacket::arbitrary(&mut unstructured) {
+                Ok(packet) => ClientPacket::Editor(packet),
+                Err(_) => continue,
+            },
+            _ => match ClientUserPacket::arbitrary(&mut unstructured) {
+                Ok(packet) => ClientPacket::User(packet),
+                Err(_) => continue,
+            },
+        };
+
+        let encoded: Vec<u8> = packet.clone().try_into().expect("Failed to encode packet");
+
+        match ClientPacket::try_from(encoded.as_slice()) {
+            Ok(decoded) => assert_eq!(decoded, packet),
+            Err(crate::PacketDecodeError::Validation { .. }) => (),
+            Err(err) => panic!("Failed to decode {packet:?}: {err:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_hostile_client_packet_never_panics() {
+    // completely random input, mostly rejected at the structure
+    for buffer in random_buffers(6, CASES) {
+        let _ = ClientPacket::try_from(buffer.as_slice());
+    }
+
+    // a valid header of every category followed by random payloads
+    for (idx, buffer) in random_buffers(7, CASES * 4).enumerate() {
+        let category = [
+            Category::Protocol,
+            Category::Authentication,
+            Category::User,
+            Category::Editor,
+        ][idx % 4];
+        let opcode = buffer.first().copied().unwrap_or_default() as u32;
+
+        let mut packet = vec![146, 206];
+        packet.extend(((category as u32) << 16 | opcode).to_be_bytes());
+        packet.extend(&buffer);
+
+        let _ = ClientPacket::try_from(packet.as_slice());
+    }
+}
@@ -0,0 +1,190 @@
+//! Runs the conformance vectors of `specs/vectors` against `ClientPacket` and `ServerPacket`
+
+use std::{fs, path::{Path, PathBuf}};
+
+use rmpv::Value;
+use serde::Deserialize;
+
+use crate::{
+    json::{self, value_to_json, Sender},
+    schema, Category, ClientPacket, PacketDecodeError, ServerPacket,
+};
+
+#[derive(Debug, Deserialize)]
+struct Vector {
+    name: String,
+    hex: String,
+    #[serde(default)]
+    packet: Option<ExpectedPacket>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpectedPacket {
+    category: String,
+    opcode: u16,
+    name: String,
+    payload: serde_json::Value,
+}
+
+/// A packet decoded from a vector, with its payload encoded back into msgpack
+struct DecodedPacket {
+    category: Category,
+    opcode: u16,
+    payload: Value,
+}
+
+fn vectors_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../specs/vectors")
+}
+
+fn read_vectors(side: &str) -> Vec<(String, Vector)> {
+    let dir = vectors_dir().join(side);
+    let mut files = fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", dir.display(), err))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+
+    files.sort();
+
+    files
+        .into_iter()
+        .flat_map(|path| {
+            let file = path.file_name().unwrap().to_string_lossy().into_owned();
+            let vectors: Vec<Vector> = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+                .unwrap_or_else(|err| panic!("{} is not a valid vector file: {}", file, err));
+
+            vectors.into_iter().map(move |vector| (format!("{}/{}#{}", side, file, vector.name), vector))
+        })
+        .collect()
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    assert!(hex.len().is_multiple_of(2), "odd number of hex digits");
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex digit"))
+        .collect()
+}
+
+/// Splits an encoded packet into its category, opcode and payload
+fn split_packet(bytes: Vec<u8>) -> DecodedPacket {
+    let Value::Array(mut packet) = rmpv::decode::read_value(&mut bytes.as_slice()).unwrap() else {
+        panic!("packets are arrays");
+    };
+
+    let payload = packet.pop().unwrap();
+    let header = packet.pop().unwrap().as_u64().unwrap();
+
+    DecodedPacket {
+        category: Category::try_from((header >> 16) as u16).unwrap(),
+        opcode: header as u16,
+        payload,
+    }
+}
+
+/// The name of a `PacketDecodeError` variant
+fn error_name(err: &PacketDecodeError) -> String {
+    let debug = format!("{:?}", err);
+    debug[..debug.find([' ', '(', '{']).unwrap_or(debug.len())].to_string()
+}
+
+fn run_vectors(side: &str, decode: impl Fn(&[u8]) -> Result<DecodedPacket, PacketDecodeError>) {
+    let schema = schema::protocol_schema();
+    let vectors = read_vectors(side);
+
+    assert!(!vectors.is_empty(), "no vectors in {}", side);
+
+    for (name, vector) in vectors {
+        let result = decode(&from_hex(&vector.hex));
+
+        match (vector.packet, vector.error, result) {
+            (Some(expected), None, Ok(packet)) => {
+                let category = schema
+                    .categories
+                    .iter()
+                    .find(|category| category.id == packet.category as u16)
+                    .unwrap();
+                let variants = if side == "client" { category.client } else { category.server };
+                let variant = variants.iter().find(|variant| variant.opcode == packet.opcode);
+
+                assert_eq!(category.name, expected.category, "{}: category", name);
+                assert_eq!(packet.opcode, expected.opcode, "{}: opcode", name);
+                assert_eq!(variant.map(|variant| variant.name), Some(expected.name.as_str()), "{}: packet", name);
+                assert_eq!(value_to_json(&packet.payload).unwrap(), expected.payload, "{}: payload", name);
+
+                // the JSON form of the packet decodes to the same packet
+                let text = serde_json::json!({
+                    "packet": format!("{}.{}", expected.category, expected.name),
+                    "payload": expected.payload,
+                });
+                let sender = if side == "client" { Sender::Client } else { Sender::Server };
+                let from_json = json::from_json(&text.to_string(), sender)
+                    .map(|bytes| decode(&bytes))
+                    .unwrap_or_else(|err| panic!("{}: invalid JSON form: {:?}", name, err))
+                    .unwrap_or_else(|err| panic!("{}: failed to decode the JSON form: {:?}", name, err));
+
+                assert_eq!(
+                    (from_json.category, from_json.opcode, from_json.payload),
+                    (packet.category, packet.opcode, packet.payload),
+                    "{}: JSON form",
+                    name
+                );
+            }
+
+            (None, Some(expected), Err(err)) => {
+                assert_eq!(error_name(&err), expected, "{}: error", name);
+            }
+
+            (Some(_), None, Err(err)) => panic!("{}: failed to decode: {:?}", name, err),
+            (None, Some(expected), Ok(_)) => panic!("{}: decoded, expected {}", name, expected),
+            _ => panic!("{}: a vector has either a packet or an error", name),
+        }
+    }
+}
+
+#[test]
+fn test_client_vectors() {
+    run_vectors("client", |bytes| {
+        let packet = ClientPacket::try_from(bytes)?;
+        Ok(split_packet(packet.try_into().expect("failed to encode the packet")))
+    });
+}
+
+#[test]
+fn test_server_vectors() {
+    run_vectors("server", |bytes| {
+        let packet = ServerPacket::try_from(bytes)?;
+        Ok(split_packet(packet.try_into().expect("failed to encode the packet")))
+    });
+}
+
+#[test]
+fn test_vectors_cover_every_opcode() {
+    let schema = schema::protocol_schema();
+
+    for side in ["client", "server"] {
+        let covered = read_vectors(side)
+            .into_iter()
+            .filter_map(|(_, vector)| vector.packet)
+            .map(|packet| (packet.category, packet.opcode))
+            .collect::<Vec<_>>();
+
+        for category in &schema.categories {
+            let variants = if side == "client" { category.client } else { category.server };
+
+            for variant in variants {
+                assert!(
+                    covered.contains(&(category.name.to_string(), variant.opcode)),
+                    "no {} vector for {}::{}",
+                    side,
+                    category.name,
+                    variant.name
+                );
+            }
+        }
+    }
+}
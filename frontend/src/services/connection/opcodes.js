@@ -1,52 +1,88 @@
 // All the opcodes used in dalang
+// Generated by `cargo run -p dalang-protocol --bin dalang-schema`, do not edit.
 
-// Auth Category =========
-export const CATEGORY_AUTH = 0x1;
+// Opcodes of errors, the response of any request
+export const ERROR_OPCODE_START = 0xff00;
+export const ERROR_OPCODE_END = 0xffff;
+
+// Opcodes of the events sent by the server without being requested
+export const EVENT_OPCODE_START = 0xfe00;
+export const EVENT_OPCODE_END = 0xfeff;
+
+// Protocol Category ==========
+export const CATEGORY_PROTOCOL = 0x0;
+
+// Client opcodes
+export const C_OPCODE_PROTOCOL_SUCCESS_RESP = 0x00;
+export const C_OPCODE_PROTOCOL_ACCEPT_EXTENSIONS = 0x10; // data: { extensions: array<str> }
+export const C_OPCODE_PROTOCOL_RESUME_SESSION = 0x20; // data: { token: str, last_event: u64 }
+
+// Server opcodes
+export const S_OPCODE_PROTOCOL_SUCCESS_RESP = 0x00;
+export const S_OPCODE_PROTOCOL_EXTENSIONS_ACCEPTED = 0x10; // data: { extensions: array<str> }
+export const S_OPCODE_PROTOCOL_SESSION_STARTED = 0x20; // data: { token: str }
+export const S_OPCODE_PROTOCOL_SESSION_RESUMED = 0x21;
+export const S_OPCODE_PROTOCOL_RESUME_FAILED = 0x22;
+export const S_OPCODE_PROTOCOL_SESSION_TERMINATING = 0xfe00; // data: { reason: str }
+
+// Authentication Category ==========
+export const CATEGORY_AUTHENTICATION = 0x1;
 
 // Client opcodes
-export const C_OPCODE_AUTH_SUCCESS = 0x00;
-export const C_OPCODE_AUTH_LOGIN = 0x10; // data: { username: str, password: str }
-export const C_OPCODE_AUTH_LOGIN_WITH_TOKEN = 0x11; // data: { token: str }
-export const C_OPCODE_AUTH_REGISTER = 0x20; // data: { username: str, password: str }
-export const C_OPCODE_AUTH_CHECK_REGISTER_ENABLED = 0x21;
-export const C_OPCODE_AUTH_CHECK_USERNAME_EXIST = 0xf0; // data: { username: str }
-export const C_OPCODE_AUTH_LOGOUT = 0xff;
+export const C_OPCODE_AUTHENTICATION_SUCCESS_RESP = 0x00;
+export const C_OPCODE_AUTHENTICATION_LOGIN = 0x10; // data: { username: str, password: str }
+export const C_OPCODE_AUTHENTICATION_LOGIN_WITH_TOKEN = 0x11; // data: { token: str }
+export const C_OPCODE_AUTHENTICATION_REGISTER = 0x20; // data: { username: str, password: str, invite: option<str> }
+export const C_OPCODE_AUTHENTICATION_REGISTER_CHECK_ENABLED = 0x21;
+export const C_OPCODE_AUTHENTICATION_USERNAME_CHECK_EXISTS = 0xf0; // data: { username: str }
+export const C_OPCODE_AUTHENTICATION_LOGOUT = 0xff;
 
 // Server opcodes
-export const S_OPCODE_AUTH_SUCCESS = 0x00;
-export const S_OPCODE_AUTH_LOGIN_FAILED_INVALID = 0x10; // invalid username/password
-export const S_OPCODE_AUTH_LOGIN_FAILED_TOK_EXPIRED = 0x11;
-export const S_OPCODE_AUTH_LOGIN_SUCCESS = 0x12; // data: { token: str }
-export const S_OPCODE_AUTH_REGISTER_FAILED_USERNAME_TAKEN = 0x20;
-export const S_OPCODE_AUTH_REGISTER_FAILED_DISABLED = 0x21; // registering is disabled
-export const S_OPCODE_AUTH_ERR_ALREADY_LOGGED_IN = 0xffff;
+export const S_OPCODE_AUTHENTICATION_SUCCESS_RESP = 0x00;
+export const S_OPCODE_AUTHENTICATION_LOGIN_FAILED_INVALID_USERNAME_WRONG_PASSWORD = 0x10;
+export const S_OPCODE_AUTHENTICATION_LOGIN_FAILED_TOKEN_EXPIRED = 0x11;
+export const S_OPCODE_AUTHENTICATION_LOGIN_SUCCESS = 0x12; // data: { token: str }
+export const S_OPCODE_AUTHENTICATION_REGISTER_FAILED_USERNAME_TAKEN = 0x20;
+export const S_OPCODE_AUTHENTICATION_REGISTER_FAILED_FEATURE_DISABLED = 0x21;
+export const S_OPCODE_AUTHENTICATION_REGISTER_FAILED_INVALID_INVITE = 0x22;
+export const S_OPCODE_AUTHENTICATION_REGISTER_FAILED_USERNAME_INVALID = 0x23;
+export const S_OPCODE_AUTHENTICATION_REGISTER_INVITE_ONLY = 0x24;
+export const S_OPCODE_AUTHENTICATION_ERROR_INTERNAL = 0xfffe;
+export const S_OPCODE_AUTHENTICATION_ERROR_ALREADY_LOGGED_IN = 0xffff;
 
 // User Category ==========
 export const CATEGORY_USER = 0x2;
 
 // Client opcodes
-export const C_OPCODE_USER_SUCCESS = 0x00;
+export const C_OPCODE_USER_SUCCESS_RESP = 0x00;
 export const C_OPCODE_USER_GET_USERNAME = 0x01;
-export const C_OPCODE_USER_PROJECTS_RETRIEVE = 0x10;
-export const C_OPCODE_USER_PROJECTS_RETRIEVE_PAGED = 0x11;
-export const C_OPCODE_USER_PROJECTS_RETRIEVE_TOTAL = 0x12;
-export const C_OPCODE_USER_PROJECTS_RETRIEVE_IMAGE = 0x13; // data: { imgid: u32 }
-export const C_OPCODE_USER_PROJECT_OPEN = 0x1f;
+export const C_OPCODE_USER_GET_PROFILE = 0x02;
+export const C_OPCODE_USER_UPDATE_PROFILE = 0x03; // data: { display_name: option<str>, email: option<str>, avatar: option<u64> }
+export const C_OPCODE_USER_RETRIEVE_PROJECTS = 0x10;
+export const C_OPCODE_USER_RETRIEVE_PROJECTS_PAGED = 0x11; // data: { offset: u64, count: u64 }
+export const C_OPCODE_USER_RETRIEVE_PROJECTS_TOTAL = 0x12;
+export const C_OPCODE_USER_RETRIEVE_PROJECT_IMAGE = 0x13; // data: { imgid: u64 }
+export const C_OPCODE_USER_OPEN_PROJECT = 0x1f;
 
 // Server opcodes
-export const S_OPCODE_USER_SUCCESS = 0x00;
-export const S_OPCODE_USER_USERNAME_RESPONSE = 0x01; // data: { username: str }
-export const S_OPCODE_USER_PROJECTS_RESPONSE = 0x10; // data: { projects: [{ id: u32, title: str, lastedit: u64, created: u64, imgid: u32 }] }
-export const S_OPCODE_USER_PROJECTS_TOTAL_RESPONSE = 0x11; // data: { total: u32 }
-export const S_OPCODE_USER_PROJECTS_IMAGE_RESPONSE = 0x12; // data: { total: u32 }
-export const S_OPCODE_USER_ERR_NOT_AUTHENTICATED = 0xffff;
+export const S_OPCODE_USER_SUCCESS_RESP = 0x00;
+export const S_OPCODE_USER_USERNAME_RESP = 0x01; // data: { username: str }
+export const S_OPCODE_USER_PROFILE_RESP = 0x02; // data: { profile: UserProfile }
+export const S_OPCODE_USER_PROJECTS_LIST_RESP = 0x10; // data: { projects: array<ProjectData> }
+export const S_OPCODE_USER_PROJECTS_TOTAL_RESP = 0x11; // data: { total: u64 }
+export const S_OPCODE_USER_PROJECT_IMAGE_RESP = 0x12; // data: { data: bin }
+export const S_OPCODE_USER_PROJECTS_LIST_CHANGED = 0xfe00;
+export const S_OPCODE_USER_ERROR_INTERNAL = 0xfffe;
+export const S_OPCODE_USER_ERROR_NOT_AUTHENTICATED = 0xffff;
 
 // Editor Category ==========
 export const CATEGORY_EDITOR = 0x3;
 
 // Client opcodes
-export const C_OPCODE_EDITOR_SUCCESS = 0x00;
-export const C_OPCODE_EDITOR_CLOSE_PROJECT = 0xff;
+export const C_OPCODE_EDITOR_SUCCESS_RESP = 0x00;
 
 // Server opcodes
-export const S_OPCODE_EDITOR_SUCCESS = 0x00;
+export const S_OPCODE_EDITOR_SUCCESS_RESP = 0x00;
+export const S_OPCODE_EDITOR_USER_JOINED = 0xfe00; // data: { username: str }
+export const S_OPCODE_EDITOR_USER_LEFT = 0xfe01; // data: { username: str }
+export const S_OPCODE_EDITOR_RENDER_PROGRESS = 0xfe10; // data: { job: u32, frame: u64, frames: u64 }
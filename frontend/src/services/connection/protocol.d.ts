@@ -0,0 +1,131 @@
+// Generated by `cargo run -p dalang-protocol --bin dalang-schema`, do not edit.
+
+// Payload Types ==========
+
+export type ProjectData = { id: number; title: string; lastedit: number; created: number; imgid: number };
+
+export type UserProfile = { uid: number; username: string; display_name: string | null; email: string | null; avatar: number | null; role: string; created: number; last_login: number | null };
+
+// Protocol Category ==========
+
+export type ClientProtocolPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // AcceptExtensions
+  | { opcode: 0x10; payload: { extensions: Array<string> } }
+  // ResumeSession
+  | { opcode: 0x20; payload: { token: string; last_event: number } };
+
+export type ServerProtocolPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // ExtensionsAccepted
+  | { opcode: 0x10; payload: { extensions: Array<string> } }
+  // SessionStarted
+  | { opcode: 0x20; payload: { token: string } }
+  // SessionResumed
+  | { opcode: 0x21; payload: null }
+  // ResumeFailed
+  | { opcode: 0x22; payload: null }
+  // SessionTerminating (event)
+  | { opcode: 0xfe00; payload: { reason: string } };
+
+// Authentication Category ==========
+
+export type ClientAuthenticationPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // Login
+  | { opcode: 0x10; payload: { username: string; password: string } }
+  // LoginWithToken
+  | { opcode: 0x11; payload: { token: string } }
+  // Register
+  | { opcode: 0x20; payload: { username: string; password: string; invite: string | null } }
+  // RegisterCheckEnabled
+  | { opcode: 0x21; payload: null }
+  // UsernameCheckExists
+  | { opcode: 0xf0; payload: { username: string } }
+  // Logout
+  | { opcode: 0xff; payload: null };
+
+export type ServerAuthenticationPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // LoginFailedInvalidUsernameWrongPassword
+  | { opcode: 0x10; payload: null }
+  // LoginFailedTokenExpired
+  | { opcode: 0x11; payload: null }
+  // LoginSuccess
+  | { opcode: 0x12; payload: { token: string } }
+  // RegisterFailedUsernameTaken
+  | { opcode: 0x20; payload: null }
+  // RegisterFailedFeatureDisabled
+  | { opcode: 0x21; payload: null }
+  // RegisterFailedInvalidInvite
+  | { opcode: 0x22; payload: null }
+  // RegisterFailedUsernameInvalid
+  | { opcode: 0x23; payload: null }
+  // RegisterInviteOnly
+  | { opcode: 0x24; payload: null }
+  // ErrorInternal
+  | { opcode: 0xfffe; payload: null }
+  // ErrorAlreadyLoggedIn
+  | { opcode: 0xffff; payload: null };
+
+// User Category ==========
+
+export type ClientUserPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // GetUsername
+  | { opcode: 0x01; payload: null }
+  // GetProfile
+  | { opcode: 0x02; payload: null }
+  // UpdateProfile
+  | { opcode: 0x03; payload: { display_name: string | null; email: string | null; avatar: number | null } }
+  // RetrieveProjects
+  | { opcode: 0x10; payload: null }
+  // RetrieveProjectsPaged
+  | { opcode: 0x11; payload: { offset: number; count: number } }
+  // RetrieveProjectsTotal
+  | { opcode: 0x12; payload: null }
+  // RetrieveProjectImage
+  | { opcode: 0x13; payload: { imgid: number } }
+  // OpenProject
+  | { opcode: 0x1f; payload: null };
+
+export type ServerUserPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // UsernameResp
+  | { opcode: 0x01; payload: { username: string } }
+  // ProfileResp
+  | { opcode: 0x02; payload: { profile: UserProfile } }
+  // ProjectsListResp
+  | { opcode: 0x10; payload: { projects: Array<ProjectData> } }
+  // ProjectsTotalResp
+  | { opcode: 0x11; payload: { total: number } }
+  // ProjectImageResp
+  | { opcode: 0x12; payload: { data: Uint8Array } }
+  // ProjectsListChanged (event)
+  | { opcode: 0xfe00; payload: null }
+  // ErrorInternal
+  | { opcode: 0xfffe; payload: null }
+  // ErrorNotAuthenticated
+  | { opcode: 0xffff; payload: null };
+
+// Editor Category ==========
+
+export type ClientEditorPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null };
+
+export type ServerEditorPacket =
+  // SuccessResp
+  | { opcode: 0x00; payload: null }
+  // UserJoined (event)
+  | { opcode: 0xfe00; payload: { username: string } }
+  // UserLeft (event)
+  | { opcode: 0xfe01; payload: { username: string } }
+  // RenderProgress (event)
+  | { opcode: 0xfe10; payload: { job: number; frame: number; frames: number } };
@@ -1,45 +1,80 @@
 use proc_macro::TokenStream;
 use quote::quote;
 
-#[proc_macro_derive(Packet, attributes(opcode, from_cloned))]
+#[proc_macro_derive(
+    Packet,
+    attributes(opcode, from_cloned, packet, responds_with, validate)
+)]
 pub fn derive(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    let (enum_name, variants) = expect_enum(input);
+    let enum_compact = parse_variant_options(&input.attrs).compact;
+    let (enum_name, generics, variants) = expect_enum(input);
 
-    let packets = get_packets_from_variants(variants);
+    let packets = get_packets_from_variants(variants, enum_compact);
 
     // =>> generate the `Packet` trait impl <<=
     // its functions are:
     //
-    //   fn decode_packet(opcode: u16, payload: &[u8]) -> Option<Self> // None on invalid payload / opcode
+    //   fn decode_packet(opcode: u16, payload: &'packet [u8]) -> Result<Self, PacketCategoryDecodeError>
     //
     //   fn as_opcode(&self) -> u16
     //   fn encode_payload(self) -> Vec<u8>
 
-    let decode_packet =
-        decode_packet::generate_decode_packet_function(enum_name.clone(), packets.clone());
+    let (lifetime, impl_generics) = with_packet_lifetime(&generics);
+    let impl_generics = with_bounds(
+        impl_generics,
+        |param| syn::parse_quote!(#param: std::convert::TryFrom<rmpv::ValueRef<#lifetime>>),
+    );
+    let impl_generics = with_bounds(
+        impl_generics,
+        |param| syn::parse_quote!(rmpv::Value: From<#param>),
+    );
+    let (impl_generics, _, where_clause) = impl_generics.split_for_impl();
+    let (_, ty_generics, _) = generics.split_for_impl();
+
+    let decode_packet = decode_packet::generate_decode_packet_function(
+        enum_name.clone(),
+        lifetime.clone(),
+        packets.clone(),
+    );
 
     let encode_payload =
         encode_payload::generate_encode_packet_function(enum_name.clone(), packets.clone());
 
-    let as_opcode = generate_as_opcode_function(packets);
+    let as_opcode = generate_as_opcode_function(packets.clone());
+
+    let schema = schema::generate_schema_impl(&enum_name, &generics, packets.clone());
+
+    let request = request::generate_request_impl(&enum_name, &generics, packets);
 
-    // todo: an error type for this
     quote! {
-        impl Packet for #enum_name {
+        impl #impl_generics Packet<#lifetime> for #enum_name #ty_generics #where_clause {
             #decode_packet
             #as_opcode
             #encode_payload
         }
+
+        #request
+        #schema
     }
     .into()
 }
 
+/// Derives the conversions of a type that is used in the fields of packets, from and into an
+/// `rmpv` value, along with its `PayloadSchema`. See the `payload` module for its encoding.
+#[proc_macro_derive(Payload, attributes(from_cloned, packet))]
+pub fn derive_payload(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    payload::generate_payload_impls(input).into()
+}
+
 fn expect_enum(
     input: syn::DeriveInput,
 ) -> (
     syn::Ident,
+    syn::Generics,
     syn::punctuated::Punctuated<syn::Variant, syn::Token![,]>,
 ) {
     let enum_name = input.ident;
@@ -48,55 +83,169 @@ fn expect_enum(
         _ => panic!("This macro could only derive from an enum"),
     };
 
-    (enum_name, variants)
+    (enum_name, input.generics, variants)
 }
 
-type Packets = Vec<(syn::Ident, syn::Fields, syn::Expr)>;
+/// Retrieves the lifetime of the payload buffer that decoded packets borrow from, it's the first
+/// lifetime parameter of the enum. Enums without lifetimes get a new `'packet` lifetime added to
+/// the generics of the impl, since they don't borrow anything from the payload.
+fn with_packet_lifetime(generics: &syn::Generics) -> (syn::Lifetime, syn::Generics) {
+    if let Some(def) = generics.lifetimes().next() {
+        return (def.lifetime.clone(), generics.clone());
+    }
+
+    let lifetime = syn::Lifetime::new("'packet", proc_macro2::Span::call_site());
+    let mut generics = generics.clone();
+    generics.params.insert(
+        0,
+        syn::GenericParam::Lifetime(syn::LifetimeDef::new(lifetime.clone())),
+    );
+
+    (lifetime, generics)
+}
+
+/// Adds a where predicate for every type parameter of the generics, generic fields are then
+/// converted like any other field
+fn with_bounds(
+    mut generics: syn::Generics,
+    predicate: impl Fn(&syn::Ident) -> syn::WherePredicate,
+) -> syn::Generics {
+    let predicates = generics
+        .type_params()
+        .map(|param| predicate(&param.ident))
+        .collect::<Vec<_>>();
+
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
+
+/// A variant of the packet enum, along with its opcode and the options given to it
+#[derive(Clone)]
+struct PacketVariant {
+    ident: syn::Ident,
+    fields: syn::Fields,
+    opcode: syn::Expr,
+    /// Named fields are encoded as an array in declaration order instead of a map
+    compact: bool,
+    /// Paths to the variants of the responses declared with `#[responds_with(...)]`
+    responses: Vec<syn::Path>,
+}
+
+type Packets = Vec<PacketVariant>;
 
 fn get_packets_from_variants(
     variants: syn::punctuated::Punctuated<syn::Variant, syn::Token![,]>,
+    enum_compact: bool,
 ) -> Packets {
-    // transform variants into Vec<(Ident, Fields, Expr)> which represents the variant identifier,
-    // variant fields, and the opcode value given, respectively.
     variants.into_iter().map(|variant| {
         let Some(opcode) = variant
             .attrs
-            .into_iter()
+            .iter()
             .find_map(|attr|
-                attr.path.is_ident("opcode").then(|| Some(attr.parse_args::<syn::Expr>().ok()?)).flatten()
+                attr.path.is_ident("opcode").then(|| attr.parse_args::<syn::Expr>().ok()).flatten()
             ) else {
                 panic!("#[opcode] attribute is required for every enum variants")
             };
 
-        (variant.ident, variant.fields, opcode)
+        let options = parse_variant_options(&variant.attrs);
+
+        let responses = variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("responds_with"))
+            .flat_map(|attr| {
+                attr.parse_args_with(
+                    syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated,
+                )
+                .expect("expected paths to the response variants in #[responds_with(...)]")
+            })
+            .collect();
+
+        PacketVariant {
+            ident: variant.ident,
+            fields: variant.fields,
+            opcode,
+            compact: enum_compact || options.compact,
+            responses,
+        }
     }).collect()
 }
 
+/// Options that can be given to the enum or one of its variants through the `#[packet(...)]`
+/// attribute. Options given to the enum applies to every variant.
+#[derive(Default)]
+struct VariantOptions {
+    /// `#[packet(compact)]`: named fields are encoded positionally as an array
+    compact: bool,
+}
+
+fn parse_variant_options(attrs: &[syn::Attribute]) -> VariantOptions {
+    let mut options = VariantOptions::default();
+
+    for nested in parse_packet_attributes(attrs) {
+        match nested {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("compact") => {
+                options.compact = true
+            }
+            _ => panic!("unknown option given to the #[packet(...)] attribute of a variant"),
+        }
+    }
+
+    options
+}
+
+/// Collects the options of every `#[packet(...)]` attributes
+fn parse_packet_attributes(attrs: &[syn::Attribute]) -> Vec<syn::NestedMeta> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("packet"))
+        .flat_map(|attr| {
+            let Ok(syn::Meta::List(list)) = attr.parse_meta() else {
+                panic!("expected a list of options in #[packet(...)]")
+            };
+
+            list.nested
+        })
+        .collect()
+}
+
 /// Contains functions to perform `decode_packet()` function generation
 mod decode_packet {
     use quote::quote;
 
     pub(crate) fn generate_decode_packet_function(
         enum_name: syn::Ident,
+        lifetime: syn::Lifetime,
         packets: super::Packets,
     ) -> proc_macro2::TokenStream {
+        let validation = super::validate::generate_validation(&packets);
+
         let decode_packet_match_arms = packets
-            .clone()
             .into_iter()
-            .map(|(variant_name, fields, opcode)| {
-                generate_variant_decode(enum_name.clone(), variant_name, fields, opcode)
+            .map(|packet| {
+                generate_variant_decode(enum_name.clone(), packet.ident, packet.fields, packet.opcode)
             })
             .fold(
                 proc_macro2::TokenStream::new(),
                 |acc, ts| quote! { #acc #ts },
             );
 
+        // the payload is first decoded into the variant, then the fields are validated
         quote! {
-            fn decode_packet(opcode: u16, mut payload: &[u8]) -> Option<Self> {
-                Some(match opcode {
+            fn decode_packet(
+                opcode: u16,
+                payload: &#lifetime [u8],
+            ) -> Result<Self, PacketCategoryDecodeError> {
+                #[allow(clippy::redundant_closure_call, clippy::needless_question_mark)]
+                let packet = match opcode {
                     #decode_packet_match_arms
-                    _ => return None,
-                })
+                    _ => return Err(PacketCategoryDecodeError::UnknownOpcode { opcode }),
+                }
+                .ok_or(PacketCategoryDecodeError::InvalidPayload { opcode })?;
+
+                #validation
+
+                Ok(packet)
             }
         }
     }
@@ -107,74 +256,67 @@ mod decode_packet {
         fields: syn::Fields,
         opcode: syn::Expr,
     ) -> proc_macro2::TokenStream {
+        let value = quote!(rmpv::decode::read_value_ref(&mut payload).ok()?);
+
         // from the opcode, given, we turn the payload to construct the variant
         let code = match fields {
             syn::Fields::Named(fields) => {
-                generate_named_variant_decode(enum_name, variant_name, fields)
+                generate_named_fields_decode(quote!(#enum_name::#variant_name), fields, value)
             }
             syn::Fields::Unnamed(fields) => {
-                generate_unnamed_variant_decode(enum_name, variant_name, fields)
+                generate_unnamed_fields_decode(quote!(#enum_name::#variant_name), fields, value)
             }
             syn::Fields::Unit => {
-                quote! { #enum_name::#variant_name }
+                return quote! { #opcode => Some(#enum_name::#variant_name), };
             }
         };
 
+        // the payload is decoded in a closure so the field conversions can bail out with `?`
         quote! {
-            #opcode => {
-                #code
-            },
+            #opcode => (|| -> Option<Self> {
+                let mut payload = payload;
+                Some({ #code })
+            })(),
         }
     }
 
-    fn generate_named_variant_decode(
-        enum_name: syn::Ident,
-        variant_name: syn::Ident,
+    /// Generates the construction of `constructor` (a path to a struct or a variant) with
+    /// named fields out of `value`, an expression of `rmpv::ValueRef`. It must be used in a
+    /// function that returns an `Option`.
+    pub(crate) fn generate_named_fields_decode(
+        constructor: proc_macro2::TokenStream,
         fields: syn::FieldsNamed,
+        value: proc_macro2::TokenStream,
     ) -> proc_macro2::TokenStream {
-        let decode_payload = quote! {
-            rmpv::decode::read_value_ref(&mut payload)
-                .ok()
-                .map(|v| match v {
-                    ValueRef::Map(map) => Some(map),
-                    _ => None,
-                })
-                .flatten()?
-        };
-
-        // Construct a variant that has named field
         let mut names = Vec::new();
 
-        // construction arms
-        let fields_construction = fields
-            .named
-            .into_iter()
-            .map(|field| {
-                let has_clone_attr = field
-                    .attrs
-                    .into_iter()
-                    .find(|attr| attr.path.is_ident("from_cloned"))
-                    .is_some();
-
-                let ident = field.ident.unwrap();
-                let typ = field.ty;
-
-                let name = syn::LitStr::new(ident.to_string().as_str(), ident.span());
-                names.push(name.clone());
-
-                if !has_clone_attr {
-                    quote!(#ident: <#typ as std::convert::TryFrom<rmpv::ValueRef>>::try_from(map.remove(#name)?).ok()?)
-                } else {
-                    quote!(#ident: <#typ as std::convert::TryFrom<rmpv::Value>>::try_from(map.remove(#name)?.to_owned()).ok()?)
-                }
-            })
-            .collect::<Vec<proc_macro2::TokenStream>>();
+        // construction arms, from a map (keyed by the field names) and from an array (compact
+        // packets, in the declaration order of the fields)
+        let mut fields_construction = Vec::new();
+        let mut fields_compact_construction = Vec::new();
+
+        for field in fields.named {
+            let ident = field.ident.clone().unwrap();
+
+            let name = syn::LitStr::new(ident.to_string().as_str(), ident.span());
+            names.push(name.clone());
+
+            // optional fields may be left out of the map
+            let entry = if super::field::is_option(&field.ty) {
+                quote!(map.remove(#name).unwrap_or(rmpv::ValueRef::Nil))
+            } else {
+                quote!(map.remove(#name)?)
+            };
+
+            let decode = super::field::generate_field_decode(&field, entry);
+            fields_construction.push(quote!(#ident: #decode));
+
+            let decode = super::field::generate_field_decode(&field, quote!(payload.next()?));
+            fields_compact_construction.push(quote!(#ident: #decode));
+        }
 
         let initialization = quote! {
             use std::collections::HashMap;
-            use rmpv::ValueRef;
-
-            let payload = #decode_payload;
 
             let mut map = payload
                 .into_iter()
@@ -192,50 +334,51 @@ mod decode_packet {
                 });
         };
 
+        // both forms are always accepted, regardless of #[packet(compact)]
         quote! {
-            #initialization
-            #enum_name::#variant_name {
-                #(#fields_construction),*
+            use rmpv::ValueRef;
+
+            match #value {
+                ValueRef::Map(payload) => {
+                    #initialization
+                    #constructor {
+                        #(#fields_construction),*
+                    }
+                }
+                ValueRef::Array(payload) => {
+                    let mut payload = payload.into_iter();
+                    #constructor {
+                        #(#fields_compact_construction),*
+                    }
+                }
+                _ => return None,
             }
         }
     }
 
-    fn generate_unnamed_variant_decode(
-        enum_name: syn::Ident,
-        variant_name: syn::Ident,
+    /// Generates the construction of `constructor` with unnamed fields out of `value`, an
+    /// array of the fields in order. It must be used in a function that returns an `Option`.
+    pub(crate) fn generate_unnamed_fields_decode(
+        constructor: proc_macro2::TokenStream,
         fields: syn::FieldsUnnamed,
+        value: proc_macro2::TokenStream,
     ) -> proc_macro2::TokenStream {
         let initialization = quote! {
-            use rmpv::ValueRef;
+            let rmpv::ValueRef::Array(payload) = #value else {
+                return None;
+            };
 
-            let mut payload = rmpv::decode::read_value_ref(&mut payload)
-                .ok()
-                .map(|v| match v {
-                    ValueRef::Array(arr) => Some(arr),
-                    _ => None,
-                })
-                .flatten()?;
+            let mut payload = payload.into_iter();
         };
 
-        let fields = fields.unnamed.into_iter().enumerate().map(|(idx, field)| {
-            let has_clone_attr = field
-                .attrs
-                .into_iter()
-                .find(|attr| attr.path.is_ident("from_cloned"))
-                .is_some();
-
-            let typ = field.ty;
-
-            if !has_clone_attr {
-                quote!(<#typ as std::convert::TryFrom<rmpv::ValueRef>>::try_from(payload.remove(#idx)).ok()?)
-            } else {
-                quote!(<#typ as std::convert::TryFrom<rmpv::Value>>::try_from(payload.remove(#idx).to_owned()).ok()?)
-            }
+        // fields are taken in order, a payload with fewer items than the fields is invalid
+        let fields = fields.unnamed.into_iter().map(|field| {
+            super::field::generate_field_decode(&field, quote!(payload.next()?))
         });
 
         quote! {
             #initialization
-            #enum_name::#variant_name(#(#fields),*)
+            #constructor(#(#fields),*)
         }
     }
 }
@@ -247,8 +390,8 @@ mod encode_payload {
         enum_name: syn::Ident,
         packets: super::Packets,
     ) -> proc_macro2::TokenStream {
-        let encode_payload_packets = packets.into_iter().map(|(ident, fields, _opcode)| {
-            generate_variant_encode(enum_name.clone(), fields, ident)
+        let encode_payload_packets = packets.into_iter().map(|packet| {
+            generate_variant_encode(enum_name.clone(), packet.fields, packet.ident, packet.compact)
         });
 
         quote! {
@@ -264,74 +407,1000 @@ mod encode_payload {
         enum_name: syn::Ident,
         fields: syn::Fields,
         ident: syn::Ident,
+        compact: bool,
     ) -> proc_macro2::TokenStream {
-        if fields.is_empty() {
-            quote!(#enum_name::#ident => vec![])
+        let (pattern, value) = match fields {
+            syn::Fields::Unit => return quote!(#enum_name::#ident => vec![]),
+            syn::Fields::Named(named) if named.named.is_empty() => {
+                return quote!(#enum_name::#ident {} => vec![])
+            }
+            syn::Fields::Unnamed(unnamed) if unnamed.unnamed.is_empty() => {
+                return quote!(#enum_name::#ident() => vec![])
+            }
+            syn::Fields::Named(named) => {
+                let (bindings, value) = generate_named_fields_encode(named, compact);
+                (quote!(#enum_name::#ident { #(#bindings),* }), value)
+            }
+            syn::Fields::Unnamed(unnamed) => {
+                let (bindings, value) = generate_unnamed_fields_encode(unnamed);
+                (quote!(#enum_name::#ident(#(#bindings),*)), value)
+            }
+        };
+
+        quote! {
+            #pattern => {
+                let mut res = Vec::new();
+                rmpv::encode::write_value(&mut res, &#value).ok()?;
+                res
+            }
+        }
+    }
+
+    /// Generates an `rmpv::Value` expression of named fields, a map keyed by the field names,
+    /// or an array in the declaration order of the fields if `compact` is given. Returns the
+    /// bindings that hold the values of the fields, named after them.
+    pub(crate) fn generate_named_fields_encode(
+        fields: syn::FieldsNamed,
+        compact: bool,
+    ) -> (Vec<syn::Ident>, proc_macro2::TokenStream) {
+        let mut names = vec![];
+        let mut values = vec![];
+
+        for field in fields.named {
+            let name = field.ident.clone().unwrap();
+            values.push(super::field::generate_field_encode(&field, quote!(#name)));
+            names.push(name);
+        }
+
+        let names_lit = names
+            .iter()
+            .map(|name| syn::LitStr::new(&name.to_string(), name.span()));
+
+        let value = if compact {
+            // compact payloads leave out the field names
+            quote!(rmpv::Value::Array(vec![#(#values),*]))
         } else {
-            match fields {
-                syn::Fields::Named(named) => {
-                    let mut names = vec![];
-                    let mut typs = vec![];
-
-                    for field in named.named {
-                        names.push(field.ident.unwrap());
-                        typs.push(field.ty);
+            quote!(rmpv::Value::Map(vec![#((#names_lit.into(), #values)),*]))
+        };
+
+        (names, value)
+    }
+
+    /// Generates an `rmpv::Value` expression of unnamed fields, an array of the fields in order.
+    /// Returns the bindings that hold the values of the fields, `p0` to `pN`.
+    pub(crate) fn generate_unnamed_fields_encode(
+        fields: syn::FieldsUnnamed,
+    ) -> (Vec<syn::Ident>, proc_macro2::TokenStream) {
+        let names = (0..fields.unnamed.len())
+            .map(|num| syn::Ident::new(&format!("p{}", num), proc_macro2::Span::call_site()))
+            .collect::<Vec<_>>();
+
+        let values = names
+            .iter()
+            .zip(fields.unnamed)
+            .map(|(name, field)| super::field::generate_field_encode(&field, quote!(#name)));
+
+        let value = quote!(rmpv::Value::Array(vec![#(#values),*]));
+
+        (names, value)
+    }
+}
+
+/// Contains functions to generate the conversions of the types used in the fields of packets,
+/// from `#[derive(Payload)]`. They are encoded like the payload of packets:
+///  - structs with named fields as a map, or an array with `#[packet(compact)]`
+///  - tuple structs as an array of their fields, newtypes (a single field) as their field
+///  - unit structs as nil
+///  - enums as a tagged array of two items, `[name of the variant, fields of the variant]`,
+///    where the fields are encoded like a struct
+mod payload {
+    use quote::quote;
+
+    pub(crate) fn generate_payload_impls(input: syn::DeriveInput) -> proc_macro2::TokenStream {
+        let name = input.ident;
+        let compact = super::parse_variant_options(&input.attrs).compact;
+        let type_name = name.to_string();
+
+        let (decode, encode, kind) = match input.data {
+            syn::Data::Struct(data) => {
+                let kind = generate_kind_schema(&data.fields, compact);
+                let decode =
+                    generate_fields_decode(quote!(Self), data.fields.clone(), quote!(value));
+                let (pattern, encode) = generate_fields_encode(quote!(#name), data.fields, compact);
+
+                (decode, quote!({ let #pattern = value; #encode }), kind)
+            }
+            syn::Data::Enum(data) => {
+                let mut decode_arms = Vec::new();
+                let mut encode_arms = Vec::new();
+                let mut variants = Vec::new();
+
+                for variant in data.variants {
+                    let ident = variant.ident;
+                    let tag = syn::LitStr::new(&ident.to_string(), ident.span());
+                    let compact = compact || super::parse_variant_options(&variant.attrs).compact;
+
+                    let kind = generate_kind_schema(&variant.fields, compact);
+                    variants.push(quote!(TypeVariantSchema { name: #tag, kind: #kind }));
+
+                    let decode = generate_fields_decode(
+                        quote!(Self::#ident),
+                        variant.fields.clone(),
+                        quote!(value),
+                    );
+                    decode_arms.push(quote!(#tag => { #decode }));
+
+                    let (pattern, encode) =
+                        generate_fields_encode(quote!(#name::#ident), variant.fields, compact);
+                    encode_arms.push(quote! {
+                        #pattern => rmpv::Value::Array(vec![#tag.into(), #encode])
+                    });
+                }
+
+                let decode = quote! {
+                    let rmpv::ValueRef::Array(tagged) = value else {
+                        return None;
+                    };
+
+                    let [rmpv::ValueRef::String(tag), value] =
+                        <[rmpv::ValueRef; 2]>::try_from(tagged).ok()?
+                    else {
+                        return None;
+                    };
+
+                    match tag.into_str()? {
+                        #(#decode_arms,)*
+                        _ => return None,
                     }
+                };
 
-                    let names_lit = names
-                        .clone()
-                        .into_iter()
-                        .map(|name| syn::LitStr::new(&name.to_string(), name.span()));
-
-                    quote! {
-                        #enum_name::#ident { #(#names),* } => {
-                            use rmpv::Value;
-                            let mut res = Vec::new();
-                            rmpv::encode::write_value(&mut res, &Value::Map(vec![
-                                #((#names_lit.into(), <rmpv::Value as From<#typs>>::from(#names))),*
-                            ])).ok()?;
-                            res
-                        }
+                let encode = quote! {
+                    match value {
+                        #(#encode_arms,)*
                     }
+                };
+
+                let kind = quote!(TypeKind::Enum { variants: &[#(#variants),*] });
+
+                (decode, encode, kind)
+            }
+            syn::Data::Union(_) => panic!("This macro could only derive from a struct or an enum"),
+        };
+
+        let (lifetime, decode_generics) = super::with_packet_lifetime(&input.generics);
+        let decode_generics = super::with_bounds(
+            decode_generics,
+            |param| syn::parse_quote!(#param: std::convert::TryFrom<rmpv::ValueRef<#lifetime>>),
+        );
+        let (decode_impl_generics, _, decode_where_clause) = decode_generics.split_for_impl();
+
+        let encode_generics = super::with_bounds(
+            input.generics.clone(),
+            |param| syn::parse_quote!(rmpv::Value: From<#param>),
+        );
+        let (encode_impl_generics, _, encode_where_clause) = encode_generics.split_for_impl();
+
+        let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+        quote! {
+            impl #encode_impl_generics From<#name #ty_generics> for rmpv::Value #encode_where_clause {
+                fn from(value: #name #ty_generics) -> Self {
+                    #encode
                 }
-                syn::Fields::Unnamed(unnamed) => {
-                    let len = unnamed.unnamed.len();
-                    let names = (0..len).map(|num| {
-                        let ident =
-                            syn::Ident::new(&format!("p{}", num), proc_macro2::Span::call_site());
-                        quote!(#ident)
-                    });
+            }
+
+            impl #decode_impl_generics std::convert::TryFrom<rmpv::ValueRef<#lifetime>>
+                for #name #ty_generics #decode_where_clause
+            {
+                type Error = ();
+
+                // decoded in a closure so the field conversions can bail out with `?`
+                #[allow(clippy::redundant_closure_call, clippy::needless_question_mark)]
+                fn try_from(value: rmpv::ValueRef<#lifetime>) -> Result<Self, Self::Error> {
+                    (|| -> Option<Self> { Some({ #decode }) })().ok_or(())
+                }
+            }
+
+            impl #impl_generics PayloadSchema for #name #ty_generics #where_clause {
+                const SCHEMA: TypeSchema = TypeSchema {
+                    name: #type_name,
+                    kind: #kind,
+                };
+            }
+        }
+    }
+
+    /// Generates the construction of `constructor` (a path to a struct or a variant) out of
+    /// `value`, an expression of `rmpv::ValueRef`
+    fn generate_fields_decode(
+        constructor: proc_macro2::TokenStream,
+        fields: syn::Fields,
+        value: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        match fields {
+            syn::Fields::Named(fields) => {
+                super::decode_packet::generate_named_fields_decode(constructor, fields, value)
+            }
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let decode = super::field::generate_field_decode(&fields.unnamed[0], value);
+                quote!(#constructor(#decode))
+            }
+            syn::Fields::Unnamed(fields) => {
+                super::decode_packet::generate_unnamed_fields_decode(constructor, fields, value)
+            }
+            syn::Fields::Unit => quote!({
+                let _ = #value;
+                #constructor
+            }),
+        }
+    }
+
+    /// Generates the pattern that binds the fields of `path` (a path to a struct or a variant)
+    /// and the `rmpv::Value` expression of these fields
+    fn generate_fields_encode(
+        path: proc_macro2::TokenStream,
+        fields: syn::Fields,
+        compact: bool,
+    ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        match fields {
+            syn::Fields::Named(fields) => {
+                let (bindings, value) =
+                    super::encode_payload::generate_named_fields_encode(fields, compact);
+                (quote!(#path { #(#bindings),* }), value)
+            }
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let value = super::field::generate_field_encode(&fields.unnamed[0], quote!(p0));
+                (quote!(#path(p0)), value)
+            }
+            syn::Fields::Unnamed(fields) => {
+                let (bindings, value) =
+                    super::encode_payload::generate_unnamed_fields_encode(fields);
+                (quote!(#path(#(#bindings),*)), value)
+            }
+            syn::Fields::Unit => (quote!(#path), quote!(rmpv::Value::Nil)),
+        }
+    }
+
+    /// Generates the `TypeKind` that describes how the given fields are encoded
+    fn generate_kind_schema(fields: &syn::Fields, compact: bool) -> proc_macro2::TokenStream {
+        match fields {
+            syn::Fields::Named(_) => {
+                let fields = super::schema::generate_fields_schema(fields);
+                quote!(TypeKind::Struct { compact: #compact, fields: #fields })
+            }
+            syn::Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                let field = &unnamed.unnamed[0];
+                let options = super::field::parse_field_options(&field.attrs);
+                let typ = super::schema::schema_type(&field.ty, options.bytes);
+
+                quote!(TypeKind::Newtype { ty: #typ })
+            }
+            syn::Fields::Unnamed(_) => {
+                let fields = super::schema::generate_fields_schema(fields);
+                quote!(TypeKind::Struct { compact: true, fields: #fields })
+            }
+            syn::Fields::Unit => quote!(TypeKind::Unit),
+        }
+    }
+}
+
+/// Contains functions to generate the conversion of a single field from and into an
+/// `rmpv` value, shared by both `decode_packet()` and `encode_payload()` generation
+mod field {
+    use quote::quote;
+
+    /// Options that can be given to a field through the `#[packet(...)]` attribute
+    #[derive(Default)]
+    pub(crate) struct FieldOptions {
+        /// `#[packet(bytes)]`: the field is a byte buffer and is encoded as a msgpack `bin`
+        /// instead of an array of integers
+        pub bytes: bool,
+    }
+
+    pub(crate) fn parse_field_options(attrs: &[syn::Attribute]) -> FieldOptions {
+        let mut options = FieldOptions::default();
+
+        for nested in super::parse_packet_attributes(attrs) {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("bytes") => {
+                    options.bytes = true
+                }
+                _ => panic!("unknown option given to the #[packet(...)] attribute of a field"),
+            }
+        }
+
+        options
+    }
+
+    /// Generates an expression that turns `value` (an expression of `rmpv::ValueRef`) into the
+    /// type of the given field, returns `None` from the surrounding function if it fails.
+    pub(crate) fn generate_field_decode(
+        field: &syn::Field,
+        value: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let options = parse_field_options(&field.attrs);
+        let has_clone_attr = field
+            .attrs
+            .iter()
+            .any(|attr| attr.path.is_ident("from_cloned"));
+
+        let typ = &field.ty;
+
+        if options.bytes {
+            quote! {
+                match #value {
+                    rmpv::ValueRef::Binary(bytes) => <#typ as From<&[u8]>>::from(bytes),
+                    _ => None?,
+                }
+            }
+        } else {
+            generate_type_decode(typ, value, has_clone_attr)
+        }
+    }
+
+    /// Generates the conversion of `value` into `typ`, collections and options are converted
+    /// item by item with the same rules:
+    ///  - `Vec<T>` from an array
+    ///  - `HashMap<K, V>` and `BTreeMap<K, V>` from a map
+    ///  - `Option<T>` from nil or a `T`, and `Box<T>` from a `T`
+    ///  - `&str`, `String`, `bool`, integers and floats from their msgpack counterpart
+    ///
+    /// Anything else is converted through `TryFrom<rmpv::ValueRef>`, or `TryFrom<rmpv::Value>`
+    /// when `cloned` is given (`#[from_cloned]`).
+    fn generate_type_decode(
+        typ: &syn::Type,
+        value: proc_macro2::TokenStream,
+        cloned: bool,
+    ) -> proc_macro2::TokenStream {
+        if is_borrowed_str(typ) {
+            // rmpv doesn't provide a `TryFrom<ValueRef>` for `&str`, the string is borrowed
+            // directly from the payload
+            return quote! {
+                match #value {
+                    rmpv::ValueRef::String(string) => string.into_str()?,
+                    _ => None?,
+                }
+            };
+        }
+
+        if let Some((container, args)) = generic_arguments(typ) {
+            match (container.as_str(), args.as_slice()) {
+                ("Vec", [item]) => {
+                    let item = generate_type_decode(item, quote!(item), cloned);
+
+                    return quote! {
+                        match #value {
+                            rmpv::ValueRef::Array(items) => items
+                                .into_iter()
+                                .map(|item| Some(#item))
+                                .collect::<Option<#typ>>()?,
+                            _ => None?,
+                        }
+                    };
+                }
+                ("HashMap" | "BTreeMap", [key, val]) => {
+                    let key = generate_type_decode(key, quote!(key), cloned);
+                    let val = generate_type_decode(val, quote!(val), cloned);
+
+                    return quote! {
+                        match #value {
+                            rmpv::ValueRef::Map(entries) => entries
+                                .into_iter()
+                                .map(|(key, val)| Some((#key, #val)))
+                                .collect::<Option<#typ>>()?,
+                            _ => None?,
+                        }
+                    };
+                }
+                ("Option", [inner]) => {
+                    let inner = generate_type_decode(inner, quote!(value), cloned);
 
-                    let retrival =
-                        names
-                            .clone()
-                            .zip(unnamed.unnamed.into_iter())
-                            .map(|(name, field)| {
-                                let ty = field.ty;
-                                quote!(<rmpv::Value as From<#ty>>::from(#name))
-                            });
-
-                    quote! {
-                        #enum_name::#ident(#(#names),*) => {
-                            use rmpv::Value;
-                            let mut res = Vec::new();
-                            rmpv::encode::write_value(&mut res, &Value::Array(vec![
-                                #(#retrival),*
-                            ])).ok()?;
-                            res
+                    return quote! {
+                        match #value {
+                            rmpv::ValueRef::Nil => None,
+                            value => Some(#inner),
+                        }
+                    };
+                }
+                ("Box", [inner]) => {
+                    let inner = generate_type_decode(inner, value, cloned);
+                    return quote!(Box::new(#inner));
+                }
+                _ => (),
+            }
+        }
+
+        match type_ident(typ).as_deref() {
+            Some("String") => quote! {
+                match #value {
+                    rmpv::ValueRef::String(string) => string.into_str()?.to_owned(),
+                    _ => None?,
+                }
+            },
+            Some("bool") => quote! {
+                match #value {
+                    rmpv::ValueRef::Boolean(boolean) => boolean,
+                    _ => None?,
+                }
+            },
+            Some(
+                "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize",
+            ) => quote! {
+                match #value {
+                    rmpv::ValueRef::Integer(int) => int
+                        .as_i64()
+                        .and_then(|int| <#typ>::try_from(int).ok())
+                        .or_else(|| int.as_u64().and_then(|int| <#typ>::try_from(int).ok()))?,
+                    _ => None?,
+                }
+            },
+            // whole numbers are accepted as floats, some encoders write them as integers
+            Some("f32") => quote! {
+                match #value {
+                    rmpv::ValueRef::F32(float) => float,
+                    rmpv::ValueRef::F64(float) => float as f32,
+                    rmpv::ValueRef::Integer(int) => int.as_f64()? as f32,
+                    _ => None?,
+                }
+            },
+            Some("f64") => quote! {
+                match #value {
+                    rmpv::ValueRef::F32(float) => f64::from(float),
+                    rmpv::ValueRef::F64(float) => float,
+                    rmpv::ValueRef::Integer(int) => int.as_f64()?,
+                    _ => None?,
+                }
+            },
+            _ if cloned => quote! {
+                <#typ as std::convert::TryFrom<rmpv::Value>>::try_from(#value.to_owned()).ok()?
+            },
+            _ => quote!(<#typ as std::convert::TryFrom<rmpv::ValueRef>>::try_from(#value).ok()?),
+        }
+    }
+
+    /// Checks whether the given type is a `&str`
+    pub(crate) fn is_borrowed_str(typ: &syn::Type) -> bool {
+        let syn::Type::Reference(reference) = typ else {
+            return false;
+        };
+
+        matches!(&*reference.elem, syn::Type::Path(path) if path.path.is_ident("str"))
+    }
+
+    /// Checks whether the given type is an `Option<T>`, its field can then be left out of a map
+    pub(crate) fn is_option(typ: &syn::Type) -> bool {
+        matches!(generic_arguments(typ), Some((container, _)) if container == "Option")
+    }
+
+    /// Retrieves the name of a type without its path, like `String` for `std::string::String`
+    pub(crate) fn type_ident(typ: &syn::Type) -> Option<String> {
+        let syn::Type::Path(path) = typ else {
+            return None;
+        };
+
+        Some(path.path.segments.last()?.ident.to_string())
+    }
+
+    /// Retrieves the name of a generic type and its type arguments, like `Vec` and `[T]` for a
+    /// `Vec<T>`
+    pub(crate) fn generic_arguments(typ: &syn::Type) -> Option<(String, Vec<syn::Type>)> {
+        let syn::Type::Path(path) = typ else {
+            return None;
+        };
+
+        let segment = path.path.segments.last()?;
+        let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+            return None;
+        };
+
+        let args = args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(typ) => Some(typ.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Some((segment.ident.to_string(), args))
+    }
+
+    /// Generates an expression that turns `binding`, which holds the value of the given field,
+    /// into an `rmpv::Value`.
+    pub(crate) fn generate_field_encode(
+        field: &syn::Field,
+        binding: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let options = parse_field_options(&field.attrs);
+        let typ = &field.ty;
+
+        if options.bytes {
+            quote!(rmpv::Value::Binary(<Vec<u8> as From<#typ>>::from(#binding)))
+        } else {
+            generate_type_encode(typ, binding)
+        }
+    }
+
+    /// Generates the conversion of `binding` into an `rmpv::Value`, the counterpart of
+    /// [`generate_type_decode`]. Anything that isn't a collection, an option or a box is
+    /// converted through `From<T> for rmpv::Value`.
+    fn generate_type_encode(
+        typ: &syn::Type,
+        binding: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        if let Some((container, args)) = generic_arguments(typ) {
+            match (container.as_str(), args.as_slice()) {
+                ("Vec", [item]) => {
+                    let item = generate_type_encode(item, quote!(item));
+
+                    return quote! {
+                        rmpv::Value::Array(#binding.into_iter().map(|item| #item).collect())
+                    };
+                }
+                ("HashMap" | "BTreeMap", [key, val]) => {
+                    let key = generate_type_encode(key, quote!(key));
+                    let val = generate_type_encode(val, quote!(val));
+
+                    return quote! {
+                        rmpv::Value::Map(
+                            #binding.into_iter().map(|(key, val)| (#key, #val)).collect()
+                        )
+                    };
+                }
+                ("Option", [inner]) => {
+                    let inner = generate_type_encode(inner, quote!(value));
+
+                    return quote! {
+                        match #binding {
+                            Some(value) => #inner,
+                            None => rmpv::Value::Nil,
+                        }
+                    };
+                }
+                ("Box", [inner]) => return generate_type_encode(inner, quote!(*#binding)),
+                _ => (),
+            }
+        }
+
+        quote!(<rmpv::Value as From<#typ>>::from(#binding))
+    }
+}
+
+/// Contains functions to generate the validation of the decoded fields from the
+/// `#[validate(...)]` attributes
+mod validate {
+    use quote::quote;
+
+    /// A rule given through the `#[validate(...)]` attribute of a field
+    enum Rule {
+        /// `len(min = .., max = ..)`, the characters of a string or the items of a collection
+        Len {
+            min: Option<syn::LitInt>,
+            max: Option<syn::LitInt>,
+        },
+        /// `range(min = .., max = ..)`, inclusive bounds of a number
+        Range {
+            min: Option<syn::Lit>,
+            max: Option<syn::Lit>,
+        },
+        /// `regex = ".."`, a pattern that a string must match
+        Regex(syn::LitStr),
+    }
+
+    fn parse_rules(attrs: &[syn::Attribute]) -> Vec<Rule> {
+        let mut rules = Vec::new();
+
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("validate")) {
+            let Ok(syn::Meta::List(list)) = attr.parse_meta() else {
+                panic!("expected a list of rules in #[validate(...)]")
+            };
+
+            for nested in list.nested {
+                rules.push(match nested {
+                    syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("len") => {
+                        let (min, max) = parse_bounds(list);
+
+                        let int = |lit: syn::Lit| match lit {
+                            syn::Lit::Int(int) => int,
+                            _ => panic!("the bounds of len(...) must be integers"),
+                        };
+
+                        Rule::Len {
+                            min: min.map(int),
+                            max: max.map(int),
+                        }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("range") => {
+                        let (min, max) = parse_bounds(list);
+                        Rule::Range { min, max }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(pattern),
+                        ..
+                    })) if path.is_ident("regex") => {
+                        if let Err(err) = regex_syntax::parse(&pattern.value()) {
+                            panic!("invalid pattern given to #[validate(regex = ..)]: {err}")
+                        }
+
+                        Rule::Regex(pattern)
+                    }
+                    _ => panic!("unknown rule given to #[validate(...)]"),
+                });
+            }
+        }
+
+        rules
+    }
+
+    /// Parses `min = ..` and `max = ..` of a rule, at least one of them must be present
+    fn parse_bounds(list: syn::MetaList) -> (Option<syn::Lit>, Option<syn::Lit>) {
+        let mut min = None;
+        let mut max = None;
+
+        for nested in list.nested {
+            let syn::NestedMeta::Meta(syn::Meta::NameValue(bound)) = nested else {
+                panic!("expected `min = ..` or `max = ..` in a validation rule")
+            };
+
+            if bound.path.is_ident("min") {
+                min = Some(bound.lit);
+            } else if bound.path.is_ident("max") {
+                max = Some(bound.lit);
+            } else {
+                panic!("expected `min = ..` or `max = ..` in a validation rule")
+            }
+        }
+
+        if min.is_none() && max.is_none() {
+            panic!("a validation rule needs at least a `min` or a `max`")
+        }
+
+        (min, max)
+    }
+
+    /// Checks whether the given type is a `String` or a `&str`, their length is counted in
+    /// characters instead of bytes
+    fn is_string(typ: &syn::Type) -> bool {
+        super::field::is_borrowed_str(typ)
+            || super::field::type_ident(typ).is_some_and(|ident| ident == "String")
+    }
+
+    /// Generates the checks of a single rule on `binding`, a reference to the field's value
+    fn generate_rule_check(
+        rule: Rule,
+        binding: &syn::Ident,
+        typ: &syn::Type,
+        field_name: &str,
+    ) -> proc_macro2::TokenStream {
+        let fail = |error: proc_macro2::TokenStream| {
+            quote! {
+                return Err(PacketCategoryDecodeError::Validation {
+                    opcode,
+                    field: #field_name,
+                    error: #error,
+                })
+            }
+        };
+
+        match rule {
+            Rule::Len { min, max } => {
+                let len = match is_string(typ) {
+                    true => quote!(#binding.chars().count()),
+                    false => quote!(#binding.len()),
+                };
+
+                let min = min.map(|min| {
+                    let fail = fail(quote!(ValidationError::TooShort { min: #min }));
+                    quote!(if #len < #min { #fail; })
+                });
+                let max = max.map(|max| {
+                    let fail = fail(quote!(ValidationError::TooLong { max: #max }));
+                    quote!(if #len > #max { #fail; })
+                });
+
+                quote!(#min #max)
+            }
+            Rule::Range { min, max } => {
+                let fail = fail(quote!(ValidationError::OutOfRange));
+
+                let min = min.map(|min| quote!(if *#binding < #min { #fail; }));
+                let max = max.map(|max| quote!(if *#binding > #max { #fail; }));
+
+                quote!(#min #max)
+            }
+            Rule::Regex(pattern) => {
+                let fail = fail(quote!(ValidationError::PatternMismatch { pattern: #pattern }));
+
+                // compiled once on the first use
+                quote! {
+                    {
+                        static PATTERN: std::sync::OnceLock<regex::Regex> =
+                            std::sync::OnceLock::new();
+
+                        let pattern = PATTERN.get_or_init(|| regex::Regex::new(#pattern).unwrap());
+
+                        if !pattern.is_match(AsRef::<str>::as_ref(#binding)) {
+                            #fail;
                         }
                     }
                 }
-                _ => unreachable!(),
             }
         }
     }
+
+    /// Generates the validation of a decoded `packet`, returns an empty token stream if none
+    /// of the fields of any variants have a `#[validate(...)]` attribute
+    pub(crate) fn generate_validation(packets: &super::Packets) -> proc_macro2::TokenStream {
+        let arms = packets
+            .iter()
+            .filter_map(|packet| {
+                let ident = &packet.ident;
+
+                let mut bindings = Vec::new();
+                let mut checks = Vec::new();
+
+                for (idx, field) in packet.fields.iter().enumerate() {
+                    let rules = parse_rules(&field.attrs);
+
+                    let (binding, field_name) = match &field.ident {
+                        Some(ident) => (ident.clone(), ident.to_string()),
+                        None => (
+                            syn::Ident::new(&format!("p{}", idx), proc_macro2::Span::call_site()),
+                            idx.to_string(),
+                        ),
+                    };
+
+                    if rules.is_empty() {
+                        bindings.push(None);
+                        continue;
+                    }
+
+                    // the rules of an optional field apply to its value, when there's one
+                    let typ = match super::field::generic_arguments(&field.ty) {
+                        Some((container, args)) if container == "Option" => args[0].clone(),
+                        _ => field.ty.clone(),
+                    };
+
+                    let field_checks = rules
+                        .into_iter()
+                        .map(|rule| generate_rule_check(rule, &binding, &typ, &field_name));
+
+                    if super::field::is_option(&field.ty) {
+                        checks.push(quote!(if let Some(#binding) = #binding { #(#field_checks)* }));
+                    } else {
+                        checks.extend(field_checks);
+                    }
+
+                    bindings.push(Some(binding));
+                }
+
+                if checks.is_empty() {
+                    return None;
+                }
+
+                let pattern = match &packet.fields {
+                    syn::Fields::Named(_) => {
+                        let bindings = bindings.into_iter().flatten();
+                        quote!(Self::#ident { #(#bindings,)* .. })
+                    }
+                    _ => {
+                        let bindings = bindings
+                            .into_iter()
+                            .map(|binding| binding.map(|b| quote!(#b)).unwrap_or(quote!(_)));
+                        quote!(Self::#ident(#(#bindings),*))
+                    }
+                };
+
+                Some(quote!(#pattern => { #(#checks)* }))
+            })
+            .collect::<Vec<_>>();
+
+        if arms.is_empty() {
+            return proc_macro2::TokenStream::new();
+        }
+
+        quote! {
+            #[allow(unreachable_patterns)]
+            match &packet {
+                #(#arms)*
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Contains functions to generate the `PacketSchema` trait impl, a description of the variants
+/// that can be exported for other implementations of the protocol
+mod schema {
+    use quote::quote;
+
+    pub(crate) fn generate_schema_impl(
+        enum_name: &syn::Ident,
+        generics: &syn::Generics,
+        packets: super::Packets,
+    ) -> proc_macro2::TokenStream {
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+        let variants = packets.into_iter().map(|packet| {
+            let name = packet.ident.to_string();
+            let opcode = packet.opcode;
+            let compact = packet.compact;
+
+            let fields = generate_fields_schema(&packet.fields);
+
+            let responses = packet.responses.iter().map(|path| {
+                path.segments.last().unwrap().ident.to_string()
+            });
+
+            quote! {
+                VariantSchema {
+                    name: #name,
+                    opcode: #opcode,
+                    compact: #compact,
+                    fields: #fields,
+                    responses: &[#(#responses),*],
+                    event: #opcode >= *EVENT_OPCODES.start() && #opcode <= *EVENT_OPCODES.end(),
+                }
+            }
+        });
+
+        quote! {
+            impl #impl_generics PacketSchema for #enum_name #ty_generics #where_clause {
+                const VARIANTS: &'static [VariantSchema] = &[#(#variants),*];
+            }
+        }
+    }
+
+    /// Generates the `&[FieldSchema]` of the given fields, unnamed fields are named after their
+    /// position
+    pub(crate) fn generate_fields_schema(fields: &syn::Fields) -> proc_macro2::TokenStream {
+        let fields = fields.iter().enumerate().map(|(idx, field)| {
+            let name = field
+                .ident
+                .as_ref()
+                .map(|ident| ident.to_string())
+                .unwrap_or_else(|| idx.to_string());
+
+            let options = super::field::parse_field_options(&field.attrs);
+            let typ = schema_type(&field.ty, options.bytes);
+
+            quote!(FieldSchema { name: #name, ty: #typ })
+        });
+
+        quote!(&[#(#fields),*])
+    }
+
+    /// Turns a rust type into the type name used by the schema:
+    ///  - `str` for strings, `bin` for bytes, and `bool`
+    ///  - `u8`-`u64`, `i8`-`i64`, `f32`, and `f64` for numbers
+    ///  - `array<T>` for vectors, `map<K,V>` for maps, and `option<T>` for options
+    ///  - the name of the type for anything else
+    pub(crate) fn schema_type(typ: &syn::Type, bytes: bool) -> String {
+        if bytes {
+            return "bin".to_string();
+        }
+
+        if let Some((container, args)) = super::field::generic_arguments(typ) {
+            let args = args
+                .iter()
+                .map(|arg| schema_type(arg, false))
+                .collect::<Vec<_>>();
+
+            match (container.as_str(), args.as_slice()) {
+                ("Vec", [item]) => return format!("array<{item}>"),
+                ("HashMap" | "BTreeMap", [key, val]) => return format!("map<{key},{val}>"),
+                ("Option", [inner]) => return format!("option<{inner}>"),
+                ("Box", [inner]) => return inner.clone(),
+                _ => (),
+            }
+        }
+
+        match typ {
+            syn::Type::Reference(reference) => match &*reference.elem {
+                syn::Type::Path(path) if path.path.is_ident("str") => "str".to_string(),
+                syn::Type::Slice(slice) if is_ident(&slice.elem, "u8") => "bin".to_string(),
+                elem => schema_type(elem, false),
+            },
+            syn::Type::Path(path) => {
+                let ident = path.path.segments.last().unwrap().ident.to_string();
+
+                match ident.as_str() {
+                    "String" => "str".to_string(),
+                    _ => ident,
+                }
+            }
+            _ => quote!(#typ).to_string(),
+        }
+    }
+
+    fn is_ident(typ: &syn::Type, ident: &str) -> bool {
+        matches!(typ, syn::Type::Path(path) if path.path.is_ident(ident))
+    }
+}
+
+/// Contains functions to generate the `Request` trait impl from the `#[responds_with(...)]`
+/// attributes
+mod request {
+    use quote::quote;
+
+    /// Generates the `Request` impl, only if any of the variants declares its responses.
+    pub(crate) fn generate_request_impl(
+        enum_name: &syn::Ident,
+        generics: &syn::Generics,
+        packets: super::Packets,
+    ) -> proc_macro2::TokenStream {
+        if packets.iter().all(|packet| packet.responses.is_empty()) {
+            return proc_macro2::TokenStream::new();
+        }
+
+        let response_type = get_response_type(&packets);
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+        let arms = packets
+            .into_iter()
+            .filter(|packet| !packet.responses.is_empty())
+            .map(|packet| {
+                let ident = packet.ident;
+                let responses = packet.responses;
+
+                // a braced pattern matches unit, tuple, and named variants alike
+                quote! {
+                    Self::#ident { .. } => |response: &Self::Response| {
+                        matches!(response, #(#responses { .. })|*)
+                    }
+                }
+            });
+
+        quote! {
+            impl #impl_generics Request for #enum_name #ty_generics #where_clause {
+                type Response = #response_type;
+
+                fn response_check(&self) -> fn(&Self::Response) -> bool {
+                    #[allow(unreachable_patterns)]
+                    match self {
+                        #(#arms,)*
+                        _ => |_| false,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Retrieves the type of the responses, which is the path to a response variant without its
+    /// last segment. Every response of an enum must be a variant of the same type.
+    fn get_response_type(packets: &super::Packets) -> syn::Path {
+        let mut types = packets
+            .iter()
+            .flat_map(|packet| packet.responses.iter())
+            .map(|path| {
+                let mut path = path.clone();
+                let len = path.segments.len();
+                path.segments = path.segments.into_iter().take(len - 1).collect();
+
+                if path.segments.is_empty() {
+                    panic!("#[responds_with(...)] expects paths to variants, like `Enum::Variant`")
+                }
+
+                path
+            });
+
+        let response_type = types.next().unwrap();
+
+        let type_name = quote!(#response_type).to_string();
+
+        if types.any(|typ| quote!(#typ).to_string() != type_name) {
+            panic!("every #[responds_with(...)] of an enum must refer to variants of the same type")
+        }
+
+        response_type
+    }
 }
 
 fn generate_as_opcode_function(packets: Packets) -> proc_macro2::TokenStream {
     let variant_arms = packets
         .into_iter()
-        .map(|(ident, fields, opcode)| generate_variant_as_opcode(fields, ident, opcode));
+        .map(|packet| generate_variant_as_opcode(packet.fields, packet.ident, packet.opcode));
 
     quote! {
         fn as_opcode(&self) -> u16 {
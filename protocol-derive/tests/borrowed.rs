@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol<'a> {
+    #[opcode(0x0)]
+    VariantA,
+    #[opcode(0x1)]
+    Login { username: &'a str, password: &'a str },
+    #[opcode(0x2)]
+    Frame(u64, &'a [u8]),
+}
+
+#[test]
+fn borrowed_named_decode_test() {
+    // a payload of {"username": "lorem", "password": "ipsum"}
+    let payload: [u8; 31] = [
+        130, 168, 117, 115, 101, 114, 110, 97, 109, 101, 165, 108, 111, 114, 101, 109, 168, 112,
+        97, 115, 115, 119, 111, 114, 100, 165, 105, 112, 115, 117, 109,
+    ];
+
+    let ret = MyProtocol::decode_packet(0x1, &payload).unwrap();
+    assert_eq!(
+        ret,
+        MyProtocol::Login {
+            username: "lorem",
+            password: "ipsum"
+        }
+    );
+
+    // the decoded strings should point right into the payload buffer
+    let MyProtocol::Login { username, .. } = ret else {
+        unreachable!()
+    };
+    assert!(payload.as_ptr_range().contains(&username.as_ptr()));
+}
+
+#[test]
+fn borrowed_round_trip_test() {
+    let packet = MyProtocol::Login {
+        username: "lorem",
+        password: "ipsum",
+    };
+    let encoded = packet.encode_payload().unwrap();
+
+    let ret = MyProtocol::decode_packet(0x1, &encoded).unwrap();
+    assert_eq!(
+        ret,
+        MyProtocol::Login {
+            username: "lorem",
+            password: "ipsum"
+        }
+    );
+}
+
+#[test]
+fn borrowed_unnamed_bytes_decode_test() {
+    // a payload of [5, bin([255, 0])]
+    let payload: [u8; 6] = [146, 5, 196, 2, 255, 0];
+
+    let ret = MyProtocol::decode_packet(0x2, &payload).unwrap();
+    assert_eq!(ret, MyProtocol::Frame(5, &[255, 0]));
+}
+
+#[test]
+fn borrowed_unit_decode_test() {
+    let payload: [u8; 0] = [];
+    let ret = MyProtocol::decode_packet(0x0, &payload).unwrap();
+    assert_eq!(ret, MyProtocol::VariantA);
+}
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol {
+    #[opcode(0x0)]
+    Image {
+        #[packet(bytes)]
+        data: Vec<u8>,
+    },
+    #[opcode(0x1)]
+    Frame(u64, #[packet(bytes)] Vec<u8>),
+}
+
+#[test]
+fn named_bytes_encode_test() {
+    // a payload of {"data": bin([1, 2, 3])}
+    let expected: [u8; 11] = [129, 164, 100, 97, 116, 97, 196, 3, 1, 2, 3];
+
+    let packet = MyProtocol::Image {
+        data: vec![1, 2, 3],
+    };
+    let encoded = packet.encode_payload().unwrap();
+
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn named_bytes_decode_test() {
+    let payload: [u8; 11] = [129, 164, 100, 97, 116, 97, 196, 3, 1, 2, 3];
+
+    let ret = MyProtocol::decode_packet(0x0, &payload).unwrap();
+    assert_eq!(
+        ret,
+        MyProtocol::Image {
+            data: vec![1, 2, 3]
+        }
+    );
+}
+
+#[test]
+fn unnamed_bytes_round_trip_test() {
+    // a payload of [5, bin([255, 0])]
+    let expected: [u8; 6] = [146, 5, 196, 2, 255, 0];
+
+    let packet = MyProtocol::Frame(5, vec![255, 0]);
+    let encoded = packet.encode_payload().unwrap();
+    assert_eq!(encoded, expected);
+
+    let ret = MyProtocol::decode_packet(0x1, &encoded).unwrap();
+    assert_eq!(ret, MyProtocol::Frame(5, vec![255, 0]));
+}
+
+#[test]
+fn bytes_rejects_integer_array_test() {
+    // a payload of {"data": [1, 2, 3]}, byte fields must be a msgpack bin
+    let payload: [u8; 10] = [129, 164, 100, 97, 116, 97, 147, 1, 2, 3];
+
+    assert_eq!(
+        MyProtocol::decode_packet(0x0, &payload),
+        Err(PacketCategoryDecodeError::InvalidPayload { opcode: 0x0 })
+    );
+}
@@ -0,0 +1,99 @@
+//! Mirrors of the items from `dalang-protocol` that the code generated by the derive refers to
+
+#![allow(dead_code)]
+
+pub trait Packet<'a>
+where
+    Self: Sized,
+{
+    fn decode_packet(opcode: u16, payload: &'a [u8]) -> Result<Self, PacketCategoryDecodeError>;
+
+    fn as_opcode(&self) -> u16;
+    fn encode_payload(self) -> Option<Vec<u8>>;
+}
+
+pub trait Request {
+    type Response;
+
+    fn response_check(&self) -> fn(&Self::Response) -> bool;
+
+    fn declares_response(&self, response: &Self::Response) -> bool {
+        (self.response_check())(response)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PacketCategoryDecodeError {
+    UnknownOpcode {
+        opcode: u16,
+    },
+    InvalidPayload {
+        opcode: u16,
+    },
+    Validation {
+        opcode: u16,
+        field: &'static str,
+        error: ValidationError,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    TooShort { min: usize },
+    TooLong { max: usize },
+    OutOfRange,
+    PatternMismatch { pattern: &'static str },
+}
+
+pub trait PacketSchema {
+    const VARIANTS: &'static [VariantSchema];
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VariantSchema {
+    pub name: &'static str,
+    pub opcode: u16,
+    pub compact: bool,
+    pub fields: &'static [FieldSchema],
+    pub responses: &'static [&'static str],
+    pub event: bool,
+}
+
+pub const EVENT_OPCODES: std::ops::RangeInclusive<u16> = 0xfe00..=0xfeff;
+
+#[derive(Debug, PartialEq)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub ty: &'static str,
+}
+
+pub trait PayloadSchema {
+    const SCHEMA: TypeSchema;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TypeSchema {
+    pub name: &'static str,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TypeKind {
+    Struct {
+        compact: bool,
+        fields: &'static [FieldSchema],
+    },
+    Newtype {
+        ty: &'static str,
+    },
+    Unit,
+    Enum {
+        variants: &'static [TypeVariantSchema],
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TypeVariantSchema {
+    pub name: &'static str,
+    pub kind: TypeKind,
+}
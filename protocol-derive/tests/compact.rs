@@ -0,0 +1,92 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol {
+    #[opcode(0x0)]
+    Verbose { number: u64 },
+    #[opcode(0x1)]
+    #[packet(compact)]
+    Seek { time: u64, frame: u64 },
+}
+
+#[derive(Debug, PartialEq, Packet)]
+#[packet(compact)]
+enum MyCompactProtocol {
+    #[opcode(0x0)]
+    Transform {
+        #[from_cloned]
+        x: i64,
+        #[from_cloned]
+        y: i64,
+    },
+}
+
+#[test]
+fn compact_variant_encode_test() {
+    // a payload of [5, 7]
+    let expected: [u8; 3] = [146, 5, 7];
+
+    let packet = MyProtocol::Seek { time: 5, frame: 7 };
+    let encoded = packet.encode_payload().unwrap();
+
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn compact_enum_encode_test() {
+    // a payload of [-1, 2]
+    let expected: [u8; 3] = [146, 255, 2];
+
+    let packet = MyCompactProtocol::Transform { x: -1, y: 2 };
+    let encoded = packet.encode_payload().unwrap();
+
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn non_compact_variant_is_a_map_test() {
+    // a payload of {"number": 1}
+    let expected: [u8; 9] = [129, 166, 110, 117, 109, 98, 101, 114, 1];
+
+    let packet = MyProtocol::Verbose { number: 1 };
+    let encoded = packet.encode_payload().unwrap();
+
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn compact_variant_decodes_both_forms_test() {
+    // a payload of [5, 7]
+    let compact: [u8; 3] = [146, 5, 7];
+    // a payload of {"frame": 7, "time": 5}
+    let map: [u8; 14] = [130, 165, 102, 114, 97, 109, 101, 7, 164, 116, 105, 109, 101, 5];
+
+    let expected = MyProtocol::Seek { time: 5, frame: 7 };
+
+    assert_eq!(MyProtocol::decode_packet(0x1, &compact).unwrap(), expected);
+    assert_eq!(MyProtocol::decode_packet(0x1, &map).unwrap(), expected);
+}
+
+#[test]
+fn non_compact_variant_decodes_array_test() {
+    // a payload of [1]
+    let payload: [u8; 2] = [145, 1];
+
+    let ret = MyProtocol::decode_packet(0x0, &payload).unwrap();
+    assert_eq!(ret, MyProtocol::Verbose { number: 1 });
+}
+
+#[test]
+fn compact_short_array_decode_test() {
+    // a payload of [5], missing the frame
+    let payload: [u8; 2] = [145, 5];
+
+    assert_eq!(
+        MyProtocol::decode_packet(0x1, &payload),
+        Err(PacketCategoryDecodeError::InvalidPayload { opcode: 0x1 })
+    );
+}
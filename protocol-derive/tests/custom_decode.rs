@@ -1,15 +1,8 @@
 #[macro_use]
 extern crate protocol_derive;
 
-pub trait Packet
-where
-    Self: Sized,
-{
-    fn decode_packet(opcode: u16, payload: &[u8]) -> Option<Self>;
-
-    fn as_opcode(&self) -> u16;
-    fn encode_payload(self) -> Option<Vec<u8>>;
-}
+mod common;
+use common::*;
 
 #[derive(Debug, PartialEq, Packet)]
 enum MyProtocol {
@@ -22,10 +15,6 @@ enum MyProtocol {
     TuplePayload(#[from_cloned] MyDataStruct),
 }
 
-fn decode_my_data_struct(_val: rmpv::ValueRef) -> Option<MyDataStruct> {
-    todo!()
-}
-
 #[derive(Debug, PartialEq)]
 struct MyDataStruct {
     number: u32,
@@ -35,7 +24,14 @@ struct MyDataStruct {
 
 impl From<MyDataStruct> for rmpv::Value {
     fn from(value: MyDataStruct) -> Self {
-        todo!()
+        rmpv::Value::Map(vec![
+            ("number".into(), value.number.into()),
+            (
+                "numbers".into(),
+                rmpv::Value::Array(value.numbers.into_iter().map(Into::into).collect()),
+            ),
+            ("text".into(), value.text.into()),
+        ])
     }
 }
 
@@ -43,6 +39,54 @@ impl TryFrom<rmpv::Value> for MyDataStruct {
     type Error = std::io::Error;
 
     fn try_from(value: rmpv::Value) -> Result<Self, Self::Error> {
-        todo!()
+        let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidData);
+
+        let get = |key: &str| {
+            value
+                .as_map()
+                .and_then(|map| map.iter().find(|(k, _)| k.as_str() == Some(key)))
+                .map(|(_, v)| v)
+                .ok_or_else(invalid)
+        };
+
+        Ok(MyDataStruct {
+            number: get("number")?
+                .as_u64()
+                .and_then(|n| n.try_into().ok())
+                .ok_or_else(invalid)?,
+            numbers: get("numbers")?
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|n| n.as_u64().and_then(|n| n.try_into().ok()).ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            text: get("text")?.as_str().ok_or_else(invalid)?.to_string(),
+        })
+    }
+}
+
+fn my_data() -> MyDataStruct {
+    MyDataStruct {
+        number: 42,
+        numbers: vec![1, 2, 3],
+        text: "lorem".to_string(),
     }
 }
+
+#[test]
+fn custom_named_round_trip_test() {
+    let encoded = MyProtocol::Payload { data: my_data() }
+        .encode_payload()
+        .unwrap();
+
+    let ret = MyProtocol::decode_packet(0x00, &encoded).unwrap();
+    assert_eq!(ret, MyProtocol::Payload { data: my_data() });
+}
+
+#[test]
+fn custom_unnamed_round_trip_test() {
+    let encoded = MyProtocol::TuplePayload(my_data()).encode_payload().unwrap();
+
+    let ret = MyProtocol::decode_packet(0x01, &encoded).unwrap();
+    assert_eq!(ret, MyProtocol::TuplePayload(my_data()));
+}
@@ -0,0 +1,246 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+use rmpv::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Payload)]
+struct ElementId(u64);
+
+#[derive(Debug, Clone, PartialEq, Payload)]
+enum ElementKind {
+    Video { source: String, muted: bool },
+    Text(String),
+    Shape(u32, u32),
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, Payload)]
+struct Element {
+    id: ElementId,
+    kind: ElementKind,
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Payload)]
+#[packet(compact)]
+struct Keyframe<T> {
+    time: u64,
+    value: T,
+}
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol {
+    #[opcode(0x0)]
+    Elements { elements: Vec<Element> },
+    #[opcode(0x1)]
+    Properties { properties: HashMap<String, f64> },
+    #[opcode(0x2)]
+    Animate(ElementId, Vec<Keyframe<f32>>),
+}
+
+fn encode(value: Value) -> Vec<u8> {
+    let mut res = Vec::new();
+    rmpv::encode::write_value(&mut res, &value).unwrap();
+    res
+}
+
+#[test]
+fn newtype_is_transparent_test() {
+    assert_eq!(Value::from(ElementId(7)), Value::from(7));
+    assert_eq!(
+        ElementId::try_from(rmpv::ValueRef::from(7u64)),
+        Ok(ElementId(7))
+    );
+}
+
+#[test]
+fn tagged_enum_encode_test() {
+    // a payload of ["Text", "hi"]
+    let expected: [u8; 9] = [146, 164, 84, 101, 120, 116, 162, 104, 105];
+
+    let encoded = encode(ElementKind::Text("hi".to_string()).into());
+
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn tagged_enum_decode_test() {
+    let payload = encode(Value::Array(vec![
+        "Shape".into(),
+        Value::Array(vec![3.into(), 4.into()]),
+    ]));
+
+    let value = rmpv::decode::read_value_ref(&mut payload.as_slice()).unwrap();
+    assert_eq!(ElementKind::try_from(value), Ok(ElementKind::Shape(3, 4)));
+
+    let payload = encode(Value::Array(vec!["Empty".into(), Value::Nil]));
+
+    let value = rmpv::decode::read_value_ref(&mut payload.as_slice()).unwrap();
+    assert_eq!(ElementKind::try_from(value), Ok(ElementKind::Empty));
+
+    let payload = encode(Value::Array(vec!["Unknown".into(), Value::Nil]));
+
+    let value = rmpv::decode::read_value_ref(&mut payload.as_slice()).unwrap();
+    assert_eq!(ElementKind::try_from(value), Err(()));
+}
+
+#[test]
+fn vec_of_payloads_round_trip_test() {
+    let packet = MyProtocol::Elements {
+        elements: vec![
+            Element {
+                id: ElementId(1),
+                kind: ElementKind::Video {
+                    source: "intro.mp4".to_string(),
+                    muted: true,
+                },
+                name: Some("intro".to_string()),
+            },
+            Element {
+                id: ElementId(2),
+                kind: ElementKind::Empty,
+                name: None,
+            },
+        ],
+    };
+
+    let encoded = packet.encode_payload().unwrap();
+    let ret = MyProtocol::decode_packet(0x0, &encoded).unwrap();
+
+    assert_eq!(
+        ret,
+        MyProtocol::Elements {
+            elements: vec![
+                Element {
+                    id: ElementId(1),
+                    kind: ElementKind::Video {
+                        source: "intro.mp4".to_string(),
+                        muted: true,
+                    },
+                    name: Some("intro".to_string()),
+                },
+                Element {
+                    id: ElementId(2),
+                    kind: ElementKind::Empty,
+                    name: None,
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn optional_field_may_be_left_out_test() {
+    // {"elements": [{"id": 5, "kind": ["Empty", nil]}]}
+    let payload = encode(Value::Map(vec![(
+        "elements".into(),
+        Value::Array(vec![Value::Map(vec![
+            ("id".into(), 5.into()),
+            (
+                "kind".into(),
+                Value::Array(vec!["Empty".into(), Value::Nil]),
+            ),
+        ])]),
+    )]));
+
+    let ret = MyProtocol::decode_packet(0x0, &payload).unwrap();
+    assert_eq!(
+        ret,
+        MyProtocol::Elements {
+            elements: vec![Element {
+                id: ElementId(5),
+                kind: ElementKind::Empty,
+                name: None,
+            }],
+        }
+    );
+}
+
+#[test]
+fn map_round_trip_test() {
+    let properties = HashMap::from([("opacity".to_string(), 0.5), ("scale".to_string(), 2.0)]);
+
+    let packet = MyProtocol::Properties {
+        properties: properties.clone(),
+    };
+
+    let encoded = packet.encode_payload().unwrap();
+    let ret = MyProtocol::decode_packet(0x1, &encoded).unwrap();
+
+    assert_eq!(ret, MyProtocol::Properties { properties });
+}
+
+#[test]
+fn generic_payload_round_trip_test() {
+    let keyframes = vec![
+        Keyframe {
+            time: 0,
+            value: 0.0,
+        },
+        Keyframe {
+            time: 30,
+            value: 1.5,
+        },
+    ];
+
+    // keyframes are compact: [[0, 0.0], [30, 1.5]]
+    assert_eq!(
+        Value::from(keyframes[1].clone()),
+        Value::Array(vec![30.into(), 1.5f32.into()])
+    );
+
+    let packet = MyProtocol::Animate(ElementId(3), keyframes.clone());
+
+    let encoded = packet.encode_payload().unwrap();
+    let ret = MyProtocol::decode_packet(0x2, &encoded).unwrap();
+
+    assert_eq!(ret, MyProtocol::Animate(ElementId(3), keyframes));
+}
+
+#[test]
+fn payload_schema_test() {
+    assert_eq!(
+        ElementId::SCHEMA,
+        TypeSchema {
+            name: "ElementId",
+            kind: TypeKind::Newtype { ty: "u64" },
+        }
+    );
+
+    assert_eq!(
+        Element::SCHEMA.kind,
+        TypeKind::Struct {
+            compact: false,
+            fields: &[
+                FieldSchema {
+                    name: "id",
+                    ty: "ElementId",
+                },
+                FieldSchema {
+                    name: "kind",
+                    ty: "ElementKind",
+                },
+                FieldSchema {
+                    name: "name",
+                    ty: "option<str>",
+                },
+            ],
+        }
+    );
+
+    let TypeKind::Enum { variants } = ElementKind::SCHEMA.kind else {
+        panic!("expected an enum");
+    };
+
+    assert_eq!(
+        variants.iter().map(|v| v.name).collect::<Vec<_>>(),
+        ["Video", "Text", "Shape", "Empty"]
+    );
+    assert_eq!(variants[3].kind, TypeKind::Unit);
+
+    assert_eq!(MyProtocol::VARIANTS[1].fields[0].ty, "map<str,f64>");
+}
@@ -0,0 +1,60 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyRequest {
+    #[opcode(0x0)]
+    #[responds_with(MyResponse::Ok, MyResponse::Named)]
+    VariantA,
+    #[opcode(0x1)]
+    #[responds_with(MyResponse::Tuple)]
+    VariantB { number: u64 },
+    #[opcode(0x2)]
+    VariantC,
+}
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyResponse {
+    #[opcode(0x0)]
+    Ok,
+    #[opcode(0x1)]
+    Named {
+        #[from_cloned]
+        name: String,
+    },
+    #[opcode(0x2)]
+    Tuple(u64),
+}
+
+#[test]
+fn declared_responses_test() {
+    let named = MyResponse::Named {
+        name: String::new(),
+    };
+
+    assert!(MyRequest::VariantA.declares_response(&MyResponse::Ok));
+    assert!(MyRequest::VariantA.declares_response(&named));
+    assert!(!MyRequest::VariantA.declares_response(&MyResponse::Tuple(0)));
+
+    let b = MyRequest::VariantB { number: 0 };
+    assert!(b.declares_response(&MyResponse::Tuple(0)));
+    assert!(!b.declares_response(&MyResponse::Ok));
+}
+
+#[test]
+fn undeclared_responses_test() {
+    assert!(!MyRequest::VariantC.declares_response(&MyResponse::Ok));
+    assert!(!MyRequest::VariantC.declares_response(&MyResponse::Tuple(0)));
+}
+
+#[test]
+fn response_check_test() {
+    let check = MyRequest::VariantB { number: 0 }.response_check();
+
+    assert!(check(&MyResponse::Tuple(0)));
+    assert!(!check(&MyResponse::Ok));
+    assert!(!MyRequest::VariantC.response_check()(&MyResponse::Ok));
+}
@@ -0,0 +1,103 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol<'a> {
+    #[opcode(0x0)]
+    VariantA,
+    #[opcode(0x10)]
+    #[responds_with(MyResponse::Ok)]
+    Login { username: &'a str, count: u64 },
+    #[opcode(0x11)]
+    #[packet(compact)]
+    Seek { time: u64 },
+    #[opcode(0x12)]
+    Image(#[packet(bytes)] Vec<u8>, &'a [u8]),
+}
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyResponse {
+    #[opcode(0x0)]
+    Ok {
+        #[from_cloned]
+        name: String,
+    },
+    #[opcode(0xfe00)]
+    Changed,
+}
+
+#[test]
+fn variants_schema_test() {
+    assert_eq!(
+        MyProtocol::VARIANTS,
+        &[
+            VariantSchema {
+                name: "VariantA",
+                opcode: 0x0,
+                compact: false,
+                fields: &[],
+                responses: &[],
+                event: false,
+            },
+            VariantSchema {
+                name: "Login",
+                opcode: 0x10,
+                compact: false,
+                fields: &[
+                    FieldSchema {
+                        name: "username",
+                        ty: "str"
+                    },
+                    FieldSchema {
+                        name: "count",
+                        ty: "u64"
+                    },
+                ],
+                responses: &["Ok"],
+                event: false,
+            },
+            VariantSchema {
+                name: "Seek",
+                opcode: 0x11,
+                compact: true,
+                fields: &[FieldSchema {
+                    name: "time",
+                    ty: "u64"
+                }],
+                responses: &[],
+                event: false,
+            },
+            VariantSchema {
+                name: "Image",
+                opcode: 0x12,
+                compact: false,
+                fields: &[
+                    FieldSchema {
+                        name: "0",
+                        ty: "bin"
+                    },
+                    FieldSchema {
+                        name: "1",
+                        ty: "bin"
+                    },
+                ],
+                responses: &[],
+                event: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn event_schema_test() {
+    assert!(!MyResponse::VARIANTS[0].event);
+    assert!(MyResponse::VARIANTS[1].event);
+}
+
+#[test]
+fn owned_string_schema_test() {
+    assert_eq!(MyResponse::VARIANTS[0].fields[0].ty, "str");
+}
@@ -1,15 +1,8 @@
 #[macro_use]
 extern crate protocol_derive;
 
-pub trait Packet
-where
-    Self: Sized,
-{
-    fn decode_packet(opcode: u16, payload: &[u8]) -> Option<Self>;
-
-    fn as_opcode(&self) -> u16;
-    fn encode_payload(self) -> Option<Vec<u8>>;
-}
+mod common;
+use common::*;
 
 #[derive(Debug, PartialEq, Packet)]
 enum MyProtocol {
@@ -132,3 +125,14 @@ fn named_decode_u32_payload_test() {
 
     assert_eq!(ret, expected);
 }
+
+#[test]
+fn unknown_opcode_decode_test() {
+    let payload: [u8; 0] = [];
+    let ret = MyProtocol::decode_packet(0xff, &payload);
+
+    assert_eq!(
+        ret,
+        Err(PacketCategoryDecodeError::UnknownOpcode { opcode: 0xff })
+    );
+}
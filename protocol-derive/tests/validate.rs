@@ -0,0 +1,154 @@
+#[macro_use]
+extern crate protocol_derive;
+
+mod common;
+use common::*;
+
+#[derive(Debug, PartialEq, Packet)]
+enum MyProtocol<'a> {
+    #[opcode(0x0)]
+    Register {
+        #[validate(len(min = 3, max = 8), regex = "^[a-z]+$")]
+        username: &'a str,
+        password: &'a str,
+    },
+    #[opcode(0x1)]
+    Paged {
+        offset: u64,
+        #[validate(range(min = 1, max = 100))]
+        count: u64,
+    },
+    #[opcode(0x2)]
+    Tuple(u64, #[validate(len(max = 2))] &'a [u8]),
+    #[opcode(0x3)]
+    Rename {
+        #[validate(len(min = 2, max = 4))]
+        name: String,
+    },
+}
+
+fn register_payload(username: &str) -> Vec<u8> {
+    MyProtocol::Register {
+        username,
+        password: "ipsum",
+    }
+    .encode_payload()
+    .unwrap()
+}
+
+#[test]
+fn valid_packet_decode_test() {
+    let payload = register_payload("lorem");
+
+    assert_eq!(
+        MyProtocol::decode_packet(0x0, &payload),
+        Ok(MyProtocol::Register {
+            username: "lorem",
+            password: "ipsum"
+        })
+    );
+}
+
+#[test]
+fn len_validation_test() {
+    let payload = register_payload("lo");
+    assert_eq!(
+        MyProtocol::decode_packet(0x0, &payload),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x0,
+            field: "username",
+            error: ValidationError::TooShort { min: 3 }
+        })
+    );
+
+    let payload = register_payload("loremipsum");
+    assert_eq!(
+        MyProtocol::decode_packet(0x0, &payload),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x0,
+            field: "username",
+            error: ValidationError::TooLong { max: 8 }
+        })
+    );
+}
+
+#[test]
+fn regex_validation_test() {
+    let payload = register_payload("Lorem");
+
+    assert_eq!(
+        MyProtocol::decode_packet(0x0, &payload),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x0,
+            field: "username",
+            error: ValidationError::PatternMismatch {
+                pattern: "^[a-z]+$"
+            }
+        })
+    );
+}
+
+#[test]
+fn range_validation_test() {
+    let paged = |count| {
+        MyProtocol::Paged { offset: 0, count }
+            .encode_payload()
+            .unwrap()
+    };
+
+    assert!(MyProtocol::decode_packet(0x1, &paged(100)).is_ok());
+
+    for count in [0, 101] {
+        assert_eq!(
+            MyProtocol::decode_packet(0x1, &paged(count)),
+            Err(PacketCategoryDecodeError::Validation {
+                opcode: 0x1,
+                field: "count",
+                error: ValidationError::OutOfRange
+            })
+        );
+    }
+}
+
+#[test]
+fn unnamed_validation_test() {
+    let payload = MyProtocol::Tuple(0, &[1, 2, 3]).encode_payload().unwrap();
+
+    assert_eq!(
+        MyProtocol::decode_packet(0x2, &payload),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x2,
+            field: "1",
+            error: ValidationError::TooLong { max: 2 }
+        })
+    );
+}
+
+#[test]
+fn len_counts_characters_test() {
+    let rename = |name: &str| {
+        MyProtocol::Rename { name: name.to_string() }
+            .encode_payload()
+            .unwrap()
+    };
+
+    // 4 characters, 8 bytes
+    assert!(MyProtocol::decode_packet(0x3, &rename("ünïç")).is_ok());
+    // 1 character, 2 bytes
+    assert_eq!(
+        MyProtocol::decode_packet(0x3, &rename("é")),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x3,
+            field: "name",
+            error: ValidationError::TooShort { min: 2 }
+        })
+    );
+    assert_eq!(
+        MyProtocol::decode_packet(0x3, &rename("ünïçö")),
+        Err(PacketCategoryDecodeError::Validation {
+            opcode: 0x3,
+            field: "name",
+            error: ValidationError::TooLong { max: 4 }
+        })
+    );
+}
@@ -0,0 +1,204 @@
+//! Running the server in the background without a supervisor. The PID file is locked for as long
+//! as the server runs, so a PID file left behind by a server that crashed isn't mistaken for a
+//! running server.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long `dalang stop` waits for the server to stop
+const STOP_TIMEOUT: Duration = Duration::from_secs(35);
+
+#[derive(Debug)]
+pub enum DaemonError {
+    Io(io::Error),
+    /// Another server holds the PID file
+    AlreadyRunning { pid: Option<u32> },
+}
+
+impl From<io::Error> for DaemonError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// The locked PID file of the running server, removed once it's dropped
+pub struct PidFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Creates and locks the PID file, fails if another server holds it
+    pub fn lock(path: &Path) -> Result<PidFile, DaemonError> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        if !try_lock(&file, libc::LOCK_EX)? {
+            return Err(DaemonError::AlreadyRunning { pid: read_pid(&mut file) });
+        }
+
+        Ok(PidFile { file, path: path.to_path_buf() })
+    }
+
+    /// Writes the PID of the current process, once it's detached
+    pub fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", std::process::id())
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn try_lock(file: &File, operation: libc::c_int) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    match io::Error::last_os_error() {
+        err if err.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+        err => Err(err),
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+
+    content.trim().parse().ok()
+}
+
+/// The PID of the server holding the PID file, `None` if no server is running
+pub fn running_pid(path: &Path) -> io::Result<Option<u32>> {
+    let mut file = match File::open(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        file => file?,
+    };
+
+    // the lock is released as soon as the file is closed
+    if try_lock(&file, libc::LOCK_SH)? {
+        return Ok(None);
+    }
+
+    Ok(read_pid(&mut file))
+}
+
+/// Detaches the process from the terminal: forks twice so that it's not a session leader, and
+/// redirects its output to the log file. The working directory is kept, the paths of the
+/// configuration are relative to it.
+pub fn daemonize(log_file: &Path) -> io::Result<()> {
+    // opened before forking so that errors are still reported to the terminal
+    let log = OpenOptions::new().append(true).create(true).open(log_file)?;
+    let null = File::open("/dev/null")?;
+
+    fork_and_exit_parent()?;
+
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    fork_and_exit_parent()?;
+
+    unsafe {
+        libc::umask(0o027);
+    }
+
+    println!(
+        "dalang is running in the background (pid {}), its output is written to {}",
+        std::process::id(),
+        log_file.display()
+    );
+
+    for (file, fd) in [(&null, libc::STDIN_FILENO), (&log, libc::STDOUT_FILENO), (&log, libc::STDERR_FILENO)] {
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        // the parent's destructors would remove the PID file the child still holds
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/// Sends SIGTERM to the server holding the PID file and waits for it to stop, returns its PID
+/// or `None` if no server is running
+pub fn stop(path: &Path) -> io::Result<Option<u32>> {
+    let Some(pid) = running_pid(path)? else {
+        return Ok(None);
+    };
+
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let started = Instant::now();
+
+    while running_pid(path)?.is_some() {
+        if started.elapsed() > STOP_TIMEOUT {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, format!("the server (pid {pid}) is still running")));
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    Ok(Some(pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{running_pid, DaemonError, PidFile};
+
+    fn temp_pid_file() -> PathBuf {
+        std::env::temp_dir().join(format!("dalang-{:x}.pid", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn pid_file_is_locked_while_held() {
+        let path = temp_pid_file();
+        assert_eq!(running_pid(&path).unwrap(), None);
+
+        let mut pid_file = PidFile::lock(&path).unwrap();
+        pid_file.write_pid().unwrap();
+
+        let pid = std::process::id();
+        assert_eq!(running_pid(&path).unwrap(), Some(pid));
+        assert!(matches!(PidFile::lock(&path), Err(DaemonError::AlreadyRunning { pid: Some(p) }) if p == pid));
+
+        // the file is removed once the server stops
+        drop(pid_file);
+        assert!(!path.exists());
+        assert_eq!(running_pid(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn unlocked_pid_file_is_not_running() {
+        // left behind by a server that crashed
+        let path = temp_pid_file();
+        fs::write(&path, "4242\n").unwrap();
+
+        assert_eq!(running_pid(&path).unwrap(), None);
+
+        let pid_file = PidFile::lock(&path).unwrap();
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,186 @@
+//! `dalang db`, the version of the schema of the database in the configuration. The server
+//! upgrades its database when it starts, `migrate` does it beforehand to check that it works.
+
+use std::{fs, path::Path};
+
+use clap::{ArgMatches, Command};
+use dalang_server::{
+    config::{Config, StorageBackend},
+    migrations::{self, Migrated},
+};
+use r2d2_sqlite::rusqlite::{Connection, OpenFlags};
+
+pub fn command() -> Command {
+    Command::new("db")
+        .about("Manages the version of the schema of the database in the configuration")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("status")
+                .about("Print the version of the database, and the migrations it's missing")
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Upgrade the database to the version of this server, after backing it up")
+        )
+}
+
+/// Runs a `dalang db` subcommand against the database of the configuration
+pub fn run(config: &Config, matches: &ArgMatches) -> Result<(), String> {
+    if config.storage.backend == StorageBackend::Memory {
+        return Err("the data is kept in memory by the server, there's no database".to_string());
+    }
+
+    let database = config.storage.database_path();
+
+    match matches.subcommand() {
+        Some(("status", _)) => status(&database),
+        Some(("migrate", _)) => migrate(&database),
+        _ => unreachable!(),
+    }
+}
+
+fn status(database: &Path) -> Result<(), String> {
+    if !database.exists() {
+        println!("there's no database at {}, it's created when the server starts", database.display());
+        return Ok(());
+    }
+
+    let failed = |err| format!("failed to read {}: {err}", database.display());
+
+    let conn = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(failed)?;
+    let version = migrations::current_version(&conn).map_err(failed)?;
+    let applied = migrations::applied(&conn).map_err(failed)?;
+    let latest = migrations::latest_version();
+
+    println!("{}: version {version}, this server is at the version {latest}", database.display());
+
+    if version > latest {
+        println!("the database was upgraded by a newer server, this one can't open it");
+    }
+
+    println!();
+    println!("VERSION  STATUS   DESCRIPTION");
+
+    for migration in &applied {
+        println!("{:<7}  {:<7}  {}", migration.version, "applied", migration.description);
+    }
+
+    for migration in migrations::pending(version) {
+        println!("{:<7}  {:<7}  {}", migration.version, "pending", migration.description);
+    }
+
+    Ok(())
+}
+
+fn migrate(database: &Path) -> Result<(), String> {
+    if !upgrade(database)? {
+        println!("the database is up to date, at the version {}", migrations::latest_version());
+    }
+
+    Ok(())
+}
+
+/// Upgrades the database to the version of this server, creating it if it doesn't exist.
+/// Returns whether it changed.
+pub fn upgrade(database: &Path) -> Result<bool, String> {
+    // the database is created along with its directory, the server may have never run
+    if let Some(dir) = database.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    }
+
+    let mut conn = Connection::open(database).map_err(|err| format!("failed to open {}: {err}", database.display()))?;
+
+    let migrated = migrations::migrate(&mut conn, Some(database))
+        .map_err(|err| format!("{}: {err}", database.display()))?;
+
+    match migrated {
+        Migrated { from, to, .. } if from == to => return Ok(false),
+
+        Migrated { from, to, backup: Some(backup) } => {
+            println!("Upgraded the database from the version {from} to {to}, it was backed up to {}", backup.display());
+        }
+
+        Migrated { to, backup: None, .. } => println!("Created the database at the version {to}"),
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use dalang_server::{
+        config::{Config, StorageBackend},
+        migrations,
+    };
+    use r2d2_sqlite::rusqlite::Connection;
+
+    use super::{command, run, status, upgrade};
+
+    fn temp_database() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("dalang-db-{:x}", rand::random::<u64>()));
+
+        (dir.join("data").join("dalang.db"), dir)
+    }
+
+    #[test]
+    fn upgrade_creates_then_keeps_the_database() {
+        let (database, dir) = temp_database();
+
+        // there's nothing to read yet
+        status(&database).unwrap();
+        assert!(!database.exists());
+
+        assert_eq!(upgrade(&database), Ok(true));
+        assert_eq!(upgrade(&database), Ok(false));
+
+        let conn = Connection::open(&database).unwrap();
+        assert_eq!(migrations::current_version(&conn).unwrap(), migrations::latest_version());
+
+        status(&database).unwrap();
+
+        // only the migrations after the version of the database are applied, after a backup
+        conn.execute(r#"DELETE FROM schema_version WHERE version = ?1;"#, [migrations::latest_version()]).unwrap();
+        drop(conn);
+
+        assert_eq!(upgrade(&database), Ok(true));
+
+        let backups = fs::read_dir(database.parent().unwrap())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bak"))
+            .count();
+        assert_eq!(backups, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn upgrade_refuses_a_newer_database() {
+        let (database, dir) = temp_database();
+        upgrade(&database).unwrap();
+
+        let conn = Connection::open(&database).unwrap();
+        conn.execute(
+            r#"INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'from the future', 0);"#,
+            [migrations::latest_version() + 1],
+        ).unwrap();
+
+        // still readable
+        status(&database).unwrap();
+
+        let err = upgrade(&database).unwrap_err();
+        assert!(err.contains("this server only knows up to the version"), "{err}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn run_needs_a_database() {
+        let mut config = Config::default();
+        config.storage.backend = StorageBackend::Memory;
+
+        let matches = command().try_get_matches_from(["db", "status"]).unwrap();
+        assert!(run(&config, &matches).is_err());
+    }
+}
@@ -1,63 +1,560 @@
-use clap::{arg, Command, ArgAction, command};
-use dalang_server::components::auth::SQLiteAuthenticator;
+use std::{io, net::SocketAddr, path::{Path, PathBuf}, process::ExitCode};
 
-#[actix_web::main]
-async fn main() {
-    let matches = command!()
-        .subcommand(
-            Command::new("start")
-                .about("Start the server")
-                .args(&[
-                    arg!(-d --daemonize "Daemonize the process")
-                        .action(ArgAction::SetTrue)
-                        .group("background_start"),
+use clap::{arg, value_parser, Command, ArgAction, ArgMatches, command};
+use dalang_server::{
+    components::auth::SQLiteAuthenticator,
+    config::{self, Config, Overrides, StorageBackend},
+};
 
-                    arg!(-s --service "Create a new systemd service, enable it, and start it")
-                        .action(ArgAction::SetTrue)
-                        .group("background_start"),
+#[cfg(unix)]
+mod daemon;
+mod db;
+mod service;
+mod users;
 
-                    arg!(-f --"static-files" <PATH> "Specify a path to serve static files, dalang serves its own by default.")
-                        .action(ArgAction::Set),
+fn start_command() -> Command {
+    Command::new("start")
+        .about("Start the server, the options override the configuration")
+        .args(&[
+            arg!(-d --daemonize "Daemonize the process")
+                .action(ArgAction::SetTrue)
+                .group("background_start"),
 
-                    arg!(-w --"ws-only" --"dont-serve" "Make dalang to only run its websocket server")
-                        .action(ArgAction::SetTrue)
+            arg!(-s --service "Create a new systemd service, enable it, and start it")
+                .action(ArgAction::SetTrue)
+                .group("background_start"),
+
+            arg!(--user <NAME> "The user the systemd service runs as, created if it doesn't exist")
+                .action(ArgAction::Set)
+                .default_value("dalang")
+                .requires("service"),
+
+            arg!(--output <DIR> "Only write the systemd unit in this directory, without installing it")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .requires("service"),
+
+            arg!(--"dry-run" "Only print the systemd unit, without installing it")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("output")
+                .requires("service"),
+
+            arg!(-f --"static-files" <PATH> "Specify a path to serve static files, dalang serves its own by default.")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf)),
+
+            arg!(-w --"ws-only" "Make dalang to only run its websocket server")
+                .visible_alias("dont-serve")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("static-files"),
+
+            arg!(-b --bind <ADDRESS> "An address to listen on, may be given more than once")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(SocketAddr)),
+
+            arg!(-e --endpoint <PATH> "The path of the websocket endpoint")
+                .action(ArgAction::Set),
+
+            arg!(--database <FILE> "The SQLite database where the accounts are stored, created if it doesn't exist")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf)),
+
+            arg!(--"in-memory" "Keep the accounts in memory instead of a database file, they're lost once the server stops")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("database"),
+
+            arg!(--tcp <ADDRESS> "Also accept raw TCP connections on this address, may be given more than once")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(SocketAddr)),
+
+            #[cfg(unix)]
+            arg!(--unix <PATH> "Also accept raw connections on this Unix socket, may be given more than once")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf)),
+        ])
+}
+
+fn config_command() -> Command {
+    Command::new("config")
+        .about("Queries and sets configuration for the server")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("get")
+                .about("Print the value of a key, with the environment overrides")
+                .arg(arg!(<KEY> "A key such as `server.listen`"))
+        )
+        .subcommand(
+            Command::new("set")
+                .about("Set a key in the configuration file")
+                .args(&[
+                    arg!(<KEY> "A key such as `server.listen`"),
+                    arg!(<VALUE> "A TOML value, or a string"),
                 ])
         )
         .subcommand(
-            Command::new("config")
-                .about("Queries and sets configuration for the server"),
+            Command::new("list")
+                .about("Print every key that is set, with the environment overrides")
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the configuration file and the environment overrides")
+        )
+}
+
+// not async: the process has to be daemonized before the runtime starts its threads
+fn main() -> ExitCode {
+    let matches = command!()
+        .arg(
+            arg!(--"json-debug" "Let websocket clients exchange packets as JSON text frames, for debugging")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            arg!(-c --config <FILE> "The configuration file")
+                .value_parser(value_parser!(PathBuf))
+                .env("DALANG_CONFIG")
+                .default_value(config::DEFAULT_PATH)
+                .global(true)
+        )
+        .subcommand(start_command())
+        .subcommand(
+            Command::new("stop")
+                .about("Stop the server running in the background")
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Tell whether the server is running in the background")
+        )
+        .subcommand(
+            Command::new("uninstall-service")
+                .about("Stop, disable and remove the systemd service installed by `start --service`")
+                .args(&[
+                    arg!(--output <DIR> "Only remove the systemd unit from this directory")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf)),
+
+                    arg!(--"dry-run" "Only print what would be done")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("output"),
+                ])
         )
+        .subcommand(config_command())
+        .subcommand(users::command())
+        .subcommand(db::command())
         .get_matches();
 
+    let config_path = matches.get_one::<PathBuf>("config").expect("the config has a default");
+
     match matches.subcommand() {
-        Some(("config", _sub_matches)) => {
-            println!("Querying and setting configuration for the app");
+        Some(("config", sub_matches)) => run_config(config_path, sub_matches),
+
+        Some(("start", sub_matches)) => start(config_path, sub_matches, matches.get_flag("json-debug")),
+
+        Some((command @ ("stop" | "status"), _)) => run_daemon_command(config_path, command),
+
+        Some(("uninstall-service", sub_matches)) => uninstall_service(sub_matches),
+
+        Some(("user", sub_matches)) => run_user(config_path, sub_matches),
+
+        Some(("db", sub_matches)) => run_db(config_path, sub_matches),
+
+        // without a subcommand, the server is started with the default options
+        None => {
+            let sub_matches = start_command().get_matches_from(["start"]);
+            start(config_path, &sub_matches, matches.get_flag("json-debug"))
         }
 
-        Some(("start", sub_matches)) => {
-            let daemonize = sub_matches.get_flag("daemonize");
-            let service = sub_matches.get_flag("service");
+        _ => unreachable!()
+    }
+}
 
-            println!("Starting the server");
+fn run_config(path: &Path, matches: &ArgMatches) -> ExitCode {
+    let result = match matches.subcommand() {
+        Some(("get", sub_matches)) => {
+            let key = sub_matches.get_one::<String>("KEY").expect("the key is required");
 
-            if daemonize {
-                println!("Daemonizing the process");
-            } else if service {
-                println!("Creating a new systemd service, enabling it, and starting it");
+            Config::load(path).and_then(|config| config.get(key)).map(|value| match value {
+                // strings are printed as is, to be used by scripts
+                Some(toml::Value::String(value)) => println!("{value}"),
+                Some(value) => println!("{value}"),
+                None => println!(),
+            })
+        }
+
+        Some(("set", sub_matches)) => config::set(
+            path,
+            sub_matches.get_one::<String>("KEY").expect("the key is required"),
+            sub_matches.get_one::<String>("VALUE").expect("the value is required"),
+        ),
+
+        Some(("list", _)) => Config::load(path).and_then(|config| {
+            for key in config::KEYS {
+                if let Some(value) = config.get(key)? {
+                    println!("{key} = {value}");
+                }
             }
+
+            Ok(())
+        }),
+
+        Some(("validate", _)) => Config::load(path).map(|_| println!("{} is valid", path.display())),
+
+        _ => unreachable!()
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            ExitCode::FAILURE
         }
+    }
+}
 
-        None => {
-            println!("Starting the server in `/dalang` on 127.0.0.1:8080");
+fn run_user(config_path: &Path, matches: &ArgMatches) -> ExitCode {
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {err}", config_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
 
-            dalang_server::start(
-                Some("/dalang".to_string()),
-                None,
-                || SQLiteAuthenticator::new_in_memory(),
-                "127.0.0.1:8080"
-            ).await.expect("Failed to start the server");
+    match users::run(&config, matches) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
         }
+    }
+}
 
-        _ => unreachable!()
+fn run_db(config_path: &Path, matches: &ArgMatches) -> ExitCode {
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {err}", config_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match db::run(&config, matches) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn start(config_path: &Path, matches: &ArgMatches, json_debug: bool) -> ExitCode {
+    let mut config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {err}", config_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    apply_start_args(&mut config, matches, json_debug);
+
+    if let Err(err) = config.validate() {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+
+    // systemd starts the server itself, with the same options
+    if matches.get_flag("service") {
+        return match install_service(config_path, &config, matches, json_debug) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("failed to install the service: {err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    for addr in &config.server.listen {
+        println!("Starting the server in `{}` on {}", config.server.endpoint, addr);
+    }
+
+    match config.storage.backend {
+        StorageBackend::Sqlite => println!("Storing the accounts in {}", config.storage.database_path().display()),
+        StorageBackend::Memory => println!("Storing the accounts in memory, they will be lost once the server stops"),
     }
+
+    // a database the server can't upgrade isn't opened, rather than served with another schema
+    if config.storage.backend == StorageBackend::Sqlite {
+        if let Err(err) = db::upgrade(&config.storage.database_path()) {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // held until the server stops
+    #[cfg(unix)]
+    let _pid_file = match matches.get_flag("daemonize") {
+        true => match daemonize(&config) {
+            Ok(pid_file) => Some(pid_file),
+            Err(code) => return code,
+        },
+        false => None,
+    };
+
+    #[cfg(not(unix))]
+    if matches.get_flag("daemonize") {
+        println!("Daemonizing the process is only supported on unix, running in the foreground");
+    }
+
+    let started = actix_web::rt::System::new().block_on(
+        dalang_server::start(config, |config| match config.storage.backend {
+            StorageBackend::Sqlite => SQLiteAuthenticator::new(config.storage.database_path()),
+            StorageBackend::Memory => SQLiteAuthenticator::new_in_memory(),
+        })
+    );
+
+    match started {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("failed to start the server: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Locks the PID file, and detaches the process
+#[cfg(unix)]
+fn daemonize(config: &Config) -> Result<daemon::PidFile, ExitCode> {
+    let pid_path = config.daemon.pid_file_path(&config.storage);
+
+    let mut pid_file = match daemon::PidFile::lock(&pid_path) {
+        Ok(pid_file) => pid_file,
+
+        Err(daemon::DaemonError::AlreadyRunning { pid }) => {
+            eprintln!(
+                "dalang is already running{}, see {}",
+                pid.map(|pid| format!(" (pid {pid})")).unwrap_or_default(),
+                pid_path.display()
+            );
+            return Err(ExitCode::FAILURE);
+        }
+
+        Err(daemon::DaemonError::Io(err)) => {
+            eprintln!("{}: {err}", pid_path.display());
+            return Err(ExitCode::FAILURE);
+        }
+    };
+
+    let log_path = config.daemon.log_file_path(&config.storage);
+
+    if let Err(err) = daemon::daemonize(&log_path).and_then(|()| pid_file.write_pid()) {
+        eprintln!("failed to daemonize: {err}");
+        return Err(ExitCode::FAILURE);
+    }
+
+    Ok(pid_file)
+}
+
+/// `dalang stop` and `dalang status`, with the PID file of the configuration
+fn run_daemon_command(config_path: &Path, command: &str) -> ExitCode {
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {err}", config_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        let pid_path = config.daemon.pid_file_path(&config.storage);
+
+        let result = match command {
+            "stop" => daemon::stop(&pid_path).map(|pid| match pid {
+                Some(pid) => println!("dalang (pid {pid}) stopped"),
+                None => println!("dalang is not running"),
+            }),
+
+            _ => match daemon::running_pid(&pid_path) {
+                Ok(Some(pid)) => {
+                    println!("dalang is running (pid {pid})");
+                    Ok(())
+                }
+                Ok(None) => {
+                    println!("dalang is not running");
+                    // the exit code of a stopped service, as in the LSB init scripts
+                    return ExitCode::from(3);
+                }
+                Err(err) => Err(err),
+            },
+        };
+
+        match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{}: {err}", pid_path.display());
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (config, command);
+        eprintln!("dalang only runs in the background on unix");
+        ExitCode::FAILURE
+    }
+}
+
+/// Generates the systemd unit, and installs, enables and starts it unless it's a dry run
+fn install_service(config_path: &Path, config: &Config, matches: &ArgMatches, json_debug: bool) -> io::Result<()> {
+    let working_dir = std::env::current_dir()?;
+
+    let mut args = vec![
+        "--config".to_string(),
+        working_dir.join(config_path).display().to_string(),
+        "start".to_string(),
+    ];
+    args.extend(start_args(matches, json_debug));
+
+    let writable_paths = service::writable_paths(config, &working_dir);
+
+    let options = service::UnitOptions {
+        binary: std::env::current_exe()?,
+        args,
+        working_dir,
+        user: matches.get_one::<String>("user").expect("the user has a default").clone(),
+        privileged_ports: config.server.listen.iter().chain(&config.server.tcp).any(|addr| addr.port() < 1024),
+        writable_paths,
+    };
+
+    let unit = service::unit(&options);
+
+    if matches.get_flag("dry-run") {
+        print!("{unit}");
+        return Ok(());
+    }
+
+    if let Some(dir) = matches.get_one::<PathBuf>("output") {
+        let path = service::write_unit(dir, &unit)?;
+        println!("wrote {}", path.display());
+        return Ok(());
+    }
+
+    service::create_user(&options.user)?;
+
+    let path = service::write_unit(Path::new(service::UNIT_DIR), &unit)?;
+    println!("wrote {}", path.display());
+
+    if !service::has_systemctl() {
+        println!("systemctl is not available, the service has to be enabled by hand");
+        return Ok(());
+    }
+
+    service::systemctl(&["daemon-reload"])?;
+    service::systemctl(&["enable", "--now", service::UNIT_NAME])?;
+
+    for path in &options.writable_paths {
+        println!("`{}` has to be writable by the user `{}`", path.display(), options.user);
+    }
+
+    Ok(())
+}
+
+/// Removes the systemd unit installed by `start --service`, the user it runs as is kept
+fn uninstall_service(matches: &ArgMatches) -> ExitCode {
+    let result = (|| -> io::Result<()> {
+        let dir = matches.get_one::<PathBuf>("output").cloned().unwrap_or_else(|| PathBuf::from(service::UNIT_DIR));
+        let path = dir.join(service::UNIT_NAME);
+
+        if matches.get_flag("dry-run") {
+            println!("systemctl disable --now {}", service::UNIT_NAME);
+            println!("rm {}", path.display());
+            println!("systemctl daemon-reload");
+            return Ok(());
+        }
+
+        let systemctl = matches.get_one::<PathBuf>("output").is_none() && service::has_systemctl();
+
+        if systemctl {
+            service::systemctl(&["disable", "--now", service::UNIT_NAME])?;
+        }
+
+        match service::remove_unit(&dir)? {
+            true => println!("removed {}", path.display()),
+            false => println!("{} doesn't exist", path.display()),
+        }
+
+        if systemctl {
+            service::systemctl(&["daemon-reload"])?;
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("failed to uninstall the service: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The options given to `start` that override the configuration, to start the service with them
+fn start_args(matches: &ArgMatches, json_debug: bool) -> Vec<String> {
+    let mut args = Vec::new();
+
+    let mut values = |name: &str, flag: &str| {
+        for value in matches.get_raw(name).into_iter().flatten() {
+            args.push(flag.to_string());
+            args.push(value.to_string_lossy().into_owned());
+        }
+    };
+
+    values("bind", "--bind");
+    values("endpoint", "--endpoint");
+    values("static-files", "--static-files");
+    values("database", "--database");
+    values("tcp", "--tcp");
+    #[cfg(unix)]
+    values("unix", "--unix");
+
+    for (name, flag) in [("ws-only", "--ws-only"), ("in-memory", "--in-memory")] {
+        if matches.get_flag(name) {
+            args.push(flag.to_string());
+        }
+    }
+
+    if json_debug {
+        args.push("--json-debug".to_string());
+    }
+
+    args
+}
+
+/// Overrides the configuration with the options given to `start`
+fn apply_start_args(config: &mut Config, matches: &ArgMatches, json_debug: bool) {
+    let static_dir = match matches.get_one::<PathBuf>("static-files") {
+        Some(static_files) => Some(Some(static_files.clone())),
+        None if matches.get_flag("ws-only") => Some(None),
+        None => None,
+    };
+
+    #[cfg(unix)]
+    let unix = matches.get_many::<PathBuf>("unix").map(|unix| unix.cloned().collect());
+
+    #[cfg(not(unix))]
+    let unix = None;
+
+    config.apply_overrides(Overrides {
+        listen: matches.get_many::<SocketAddr>("bind").map(|binds| binds.copied().collect()),
+        endpoint: matches.get_one::<String>("endpoint").cloned(),
+        static_dir,
+        database: matches.get_one::<PathBuf>("database").cloned(),
+        in_memory: matches.get_flag("in-memory"),
+        tcp: matches.get_many::<SocketAddr>("tcp").map(|tcp| tcp.copied().collect()),
+        unix,
+        json_debug,
+    });
 }
@@ -0,0 +1,266 @@
+//! Installing the server as a systemd service, see `dalang start --service`. The service runs
+//! the server in the foreground as a dedicated user, systemd supervises and restarts it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use dalang_server::config::{Config, StorageBackend};
+
+/// The name of the unit
+pub const UNIT_NAME: &str = "dalang.service";
+
+/// Where the unit is installed if no other directory is given
+pub const UNIT_DIR: &str = "/etc/systemd/system";
+
+/// What the unit is generated from
+#[derive(Debug, Clone)]
+pub struct UnitOptions {
+    /// The `dalang` executable
+    pub binary: PathBuf,
+    /// The arguments given to `dalang`, such as `--config <path> start`
+    pub args: Vec<String>,
+    /// The paths of the configuration are relative to it
+    pub working_dir: PathBuf,
+    /// The user and group the server runs as
+    pub user: String,
+    /// The only paths the server may write to, such as the data directory
+    pub writable_paths: Vec<PathBuf>,
+    /// Whether the server listens on a port below 1024
+    pub privileged_ports: bool,
+}
+
+/// The paths the server writes to: the data directory, the directory of the database (which
+/// may be outside of it) and the directories of the unix sockets
+pub fn writable_paths(config: &Config, working_dir: &Path) -> Vec<PathBuf> {
+    let database = config.storage.database_path();
+    let database_dir = database.parent().filter(|_| config.storage.backend == StorageBackend::Sqlite);
+
+    let mut writable_paths: Vec<PathBuf> = Vec::new();
+
+    let paths = std::iter::once(config.storage.data_dir.as_path())
+        .chain(database_dir)
+        .chain(config.server.unix.iter().filter_map(|path| path.parent()))
+        // `components()` leaves out the `.` of `./data`
+        .map(|path| working_dir.join(path).components().collect::<PathBuf>());
+
+    for path in paths {
+        if !writable_paths.contains(&path) {
+            writable_paths.push(path);
+        }
+    }
+
+    writable_paths
+}
+
+/// Generates the unit file
+pub fn unit(options: &UnitOptions) -> String {
+    let exec_start = std::iter::once(options.binary.display().to_string())
+        .chain(options.args.iter().cloned())
+        .map(|arg| quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let writable_paths = options
+        .writable_paths
+        .iter()
+        .map(|path| quote(&path.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // binding to the ports below 1024 is the only capability the server needs
+    let capabilities = match options.privileged_ports {
+        true => "CAP_NET_BIND_SERVICE",
+        false => "",
+    };
+
+    format!(
+        "# Generated by `dalang start --service`, remove it with `dalang uninstall-service`
+[Unit]
+Description=Dalang, a server-based video editor
+Documentation=https://github.com/Iyxan23/dalang
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=simple
+User={user}
+Group={user}
+WorkingDirectory={working_dir}
+ExecStart={exec_start}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+RestartSec=5s
+TimeoutStopSec=45s
+
+# hardening
+UMask=0027
+NoNewPrivileges=yes
+CapabilityBoundingSet={capabilities}
+AmbientCapabilities={capabilities}
+ProtectSystem=strict
+ReadWritePaths={writable_paths}
+ProtectHome=read-only
+PrivateTmp=yes
+PrivateDevices=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectKernelLogs=yes
+ProtectControlGroups=yes
+ProtectClock=yes
+ProtectHostname=yes
+RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX
+RestrictNamespaces=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+SystemCallArchitectures=native
+
+[Install]
+WantedBy=multi-user.target
+",
+        user = options.user,
+        working_dir = quote(&options.working_dir.display().to_string()),
+    )
+}
+
+/// Quotes an argument of a unit file, `%` and `$` would be expanded by systemd
+fn quote(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+
+    if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\' || c == '\'') {
+        return escaped;
+    }
+
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Whether `systemctl` can be run
+pub fn has_systemctl() -> bool {
+    Command::new("systemctl")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+pub fn systemctl(args: &[&str]) -> io::Result<()> {
+    println!("systemctl {}", args.join(" "));
+
+    let status = Command::new("systemctl").args(args).status()?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("`systemctl {}` failed with {status}", args.join(" ")))),
+    }
+}
+
+/// Creates the system user the service runs as, if it doesn't exist
+pub fn create_user(user: &str) -> io::Result<()> {
+    let exists = Command::new("id")
+        .args(["-u", user])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if exists {
+        return Ok(());
+    }
+
+    println!("creating the system user `{user}`");
+
+    let status = Command::new("useradd")
+        .args(["--system", "--no-create-home", "--shell", "/usr/sbin/nologin", user])
+        .status()?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("`useradd` failed with {status}"))),
+    }
+}
+
+/// Writes the unit in the directory, returns its path
+pub fn write_unit(dir: &Path, unit: &str) -> io::Result<PathBuf> {
+    let path = dir.join(UNIT_NAME);
+    fs::write(&path, unit)?;
+
+    Ok(path)
+}
+
+/// Removes the unit from the directory, returns whether it was there
+pub fn remove_unit(dir: &Path) -> io::Result<bool> {
+    match fs::remove_file(dir.join(UNIT_NAME)) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use dalang_server::config::Config;
+
+    use super::{quote, unit, writable_paths, UnitOptions};
+
+    #[test]
+    fn quotes_arguments() {
+        assert_eq!(quote("/usr/bin/dalang"), "/usr/bin/dalang");
+        assert_eq!(quote(""), "\"\"");
+        assert_eq!(quote("my config.toml"), "\"my config.toml\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote("C:\\dalang"), "\"C:\\\\dalang\"");
+
+        // expanded by systemd otherwise
+        assert_eq!(quote("100%"), "100%%");
+        assert_eq!(quote("$HOME"), "$$HOME");
+    }
+
+    #[test]
+    fn unit_runs_the_server() {
+        let options = UnitOptions {
+            binary: PathBuf::from("/usr/bin/dalang"),
+            args: vec!["--config".to_string(), "/srv/my dalang/dalang.toml".to_string(), "start".to_string()],
+            working_dir: PathBuf::from("/srv/my dalang"),
+            user: "dalang".to_string(),
+            writable_paths: vec![PathBuf::from("/srv/my dalang/data"), PathBuf::from("/run/dalang")],
+            privileged_ports: false,
+        };
+
+        let text = unit(&options);
+
+        assert!(text.contains("\nUser=dalang\nGroup=dalang\n"));
+        assert!(text.contains("\nWorkingDirectory=\"/srv/my dalang\"\n"));
+        assert!(text.contains("\nExecStart=/usr/bin/dalang --config \"/srv/my dalang/dalang.toml\" start\n"));
+        assert!(text.contains("\nReadWritePaths=\"/srv/my dalang/data\" /run/dalang\n"));
+        assert!(text.contains("\nCapabilityBoundingSet=\n"));
+
+        let text = unit(&UnitOptions { privileged_ports: true, ..options });
+        assert!(text.contains("\nCapabilityBoundingSet=CAP_NET_BIND_SERVICE\n"));
+        assert!(text.contains("\nAmbientCapabilities=CAP_NET_BIND_SERVICE\n"));
+    }
+
+    #[test]
+    fn writable_paths_include_the_database_and_sockets() {
+        let mut config = Config::default();
+        config.storage.data_dir = PathBuf::from("./data");
+        config.storage.database = PathBuf::from("/var/lib/dalang/accounts.db");
+        config.server.unix = vec![PathBuf::from("/run/dalang/dalang.sock"), PathBuf::from("data/dalang.sock")];
+
+        assert_eq!(
+            writable_paths(&config, Path::new("/srv/dalang")),
+            [Path::new("/srv/dalang/data"), Path::new("/var/lib/dalang"), Path::new("/run/dalang")],
+        );
+
+        // the database is in the data directory by default
+        let mut config = Config::default();
+        config.storage.data_dir = PathBuf::from("data");
+
+        assert_eq!(writable_paths(&config, Path::new("/srv/dalang")), [Path::new("/srv/dalang/data")]);
+    }
+}
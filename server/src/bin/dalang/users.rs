@@ -0,0 +1,288 @@
+//! `dalang user`, managing the accounts stored in the database of the configuration. It works
+//! whether the server is running or not, and is the only way to create accounts when the
+//! registration is closed. Invite codes for `auth.registration = "invite"` are created here too.
+
+use std::{fs, io::{self, BufRead}};
+
+use actix::Actor;
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use dalang_server::{
+    components::auth::{messages as auth_msg, AuthError, Role, SQLiteAuthenticator, User},
+    config::{Config, StorageBackend},
+    registration,
+};
+
+pub fn command() -> Command {
+    let username = || arg!(<USERNAME> "The name of the user");
+    let password_stdin = || arg!(--"password-stdin" "Read the password from the first line of the standard input, instead of prompting for it")
+        .action(ArgAction::SetTrue);
+
+    Command::new("user")
+        .about("Manages the users stored in the database of the configuration")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("add")
+                .about("Create a user")
+                .args(&[
+                    username(),
+                    arg!(--role <ROLE> "What the user is allowed to do, `user` or `admin`")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(Role))
+                        .default_value("user"),
+                    password_stdin(),
+                ])
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Remove a user")
+                .arg(username())
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List the users")
+        )
+        .subcommand(
+            Command::new("passwd")
+                .about("Change the password of a user")
+                .args(&[username(), password_stdin()])
+        )
+        .subcommand(
+            Command::new("disable")
+                .about("Prevent a user from logging in, without removing it")
+                .arg(username())
+        )
+        .subcommand(
+            Command::new("enable")
+                .about("Let a disabled user log in again")
+                .arg(username())
+        )
+        .subcommand(
+            Command::new("invite")
+                .about("Create single-use invite codes to register with, when the registration is by invitation")
+                .arg(
+                    arg!(-n --count <COUNT> "How many codes to create")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(u32).range(1..=1000))
+                        .default_value("1")
+                )
+        )
+        .subcommand(
+            Command::new("set-role")
+                .about("Change what a user is allowed to do")
+                .args(&[
+                    username(),
+                    arg!(<ROLE> "`user` or `admin`")
+                        .value_parser(value_parser!(Role)),
+                ])
+        )
+}
+
+/// Runs a `dalang user` subcommand against the database of the configuration
+pub fn run(config: &Config, matches: &ArgMatches) -> Result<(), String> {
+    if config.storage.backend == StorageBackend::Memory {
+        return Err("the accounts are kept in memory by the server, there are no users to manage".to_string());
+    }
+
+    let (command, sub_matches) = matches.subcommand().expect("a subcommand is required");
+    let username = sub_matches.try_get_one::<String>("USERNAME").ok().flatten().cloned();
+
+    // only new usernames are validated, reserved usernames may be taken by admins. Users that
+    // were added before the rules changed can still be managed
+    if let ("add", Some(username)) = (command, &username) {
+        registration::validate_username(username).map_err(|err| err.to_string())?;
+    }
+
+    // read before the runtime starts, the prompt blocks
+    let password = match command {
+        "add" | "passwd" => Some(read_password(sub_matches.get_flag("password-stdin"))?),
+        _ => None,
+    };
+
+    let database = config.storage.database_path();
+
+    // the database is created along with its directory, the server may have never run
+    if let Some(dir) = database.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    }
+
+    actix_web::rt::System::new().block_on(async move {
+        let addr = SQLiteAuthenticator::new(database).start();
+        let username = || username.clone().expect("the username is required");
+
+        let found = match command {
+            "add" => {
+                let role = *sub_matches.get_one::<Role>("role").expect("the role has a default");
+
+                let register = auth_msg::Register {
+                    username: username(),
+                    password: password.expect("read above"),
+                    role,
+                    invite: None,
+                };
+
+                addr.send(register)
+                    .await
+                    .map_err(|err| err.to_string())?
+                    .map_err(|err| match err {
+                        AuthError::UsernameTaken => format!("the username `{}` is taken", username()),
+                        err => format!("failed to add `{}`: {err}", username()),
+                    })?;
+
+                println!("added `{}` ({role})", username());
+                return Ok(());
+            }
+
+            "list" => {
+                let users = addr.send(auth_msg::ListUsers)
+                    .await
+                    .map_err(|err| err.to_string())?
+                    .map_err(|err| format!("failed to list the users: {err}"))?;
+
+                print_users(&users);
+                return Ok(());
+            }
+
+            "invite" => {
+                for _ in 0..*sub_matches.get_one::<u32>("count").expect("the count has a default") {
+                    let code = addr.send(auth_msg::CreateInvite)
+                        .await
+                        .map_err(|err| err.to_string())?
+                        .map_err(|err| format!("failed to create an invite code: {err}"))?;
+
+                    println!("{code}");
+                }
+
+                return Ok(());
+            }
+
+            "remove" => addr.send(auth_msg::RemoveUser { username: username() }).await,
+
+            "passwd" => addr
+                .send(auth_msg::SetPassword { username: username(), password: password.expect("read above") })
+                .await,
+
+            "disable" | "enable" => addr
+                .send(auth_msg::SetDisabled { username: username(), disabled: command == "disable" })
+                .await,
+
+            "set-role" => {
+                let role = *sub_matches.get_one::<Role>("ROLE").expect("the role is required");
+                addr.send(auth_msg::SetRole { username: username(), role }).await
+            }
+
+            _ => unreachable!()
+        };
+
+        match found.map_err(|err| err.to_string())? {
+            Ok(()) => {
+                println!("updated `{}`", username());
+                Ok(())
+            }
+            Err(AuthError::UserNotFound) => Err(format!("there's no user named `{}`", username())),
+            Err(err) => Err(format!("failed to update `{}`: {err}", username())),
+        }
+    })
+}
+
+fn print_users(users: &[User]) {
+    if users.is_empty() {
+        println!("there are no users");
+        return;
+    }
+
+    let width = users.iter().map(|user| user.username.chars().count()).max().unwrap_or(0).max("USERNAME".len());
+
+    println!("{:<width$}  {:<5}  {:<8}  UID", "USERNAME", "ROLE", "STATUS");
+
+    for user in users {
+        let status = match user.disabled {
+            true => "disabled",
+            false => "active",
+        };
+
+        println!("{:<width$}  {:<5}  {:<8}  {}", user.username, user.role, status, user.uid);
+    }
+}
+
+/// Reads the password from the first line of the standard input, or prompts for it twice
+fn read_password(from_stdin: bool) -> Result<String, String> {
+    let password = match from_stdin {
+        true => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line).map_err(|err| format!("failed to read the password: {err}"))?;
+
+            line.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(&line).to_string()
+        }
+
+        false => {
+            let password = rpassword::prompt_password("Password: ")
+                .map_err(|err| format!("failed to read the password: {err}"))?;
+
+            let again = rpassword::prompt_password("Retype the password: ")
+                .map_err(|err| format!("failed to read the password: {err}"))?;
+
+            if password != again {
+                return Err("the passwords don't match".to_string());
+            }
+
+            password
+        }
+    };
+
+    check_password(&password)?;
+
+    Ok(password)
+}
+
+/// The same rules as the passwords of the `Register` packet, which counts characters
+fn check_password(password: &str) -> Result<(), String> {
+    match (8..=128).contains(&password.chars().count()) {
+        true => Ok(()),
+        false => Err("a password has between 8 and 128 characters".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use dalang_server::config::Config;
+
+    use super::{check_password, command, run};
+
+    fn temp_config() -> (Config, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("dalang-users-{:x}", rand::random::<u64>()));
+
+        let mut config = Config::default();
+        config.storage.data_dir = dir.clone();
+
+        (config, dir)
+    }
+
+    fn user(config: &Config, args: &[&str]) -> Result<(), String> {
+        let matches = command().try_get_matches_from(std::iter::once("user").chain(args.iter().copied())).unwrap();
+
+        run(config, &matches)
+    }
+
+    #[test]
+    fn passwords_are_counted_in_characters() {
+        assert!(check_password("1234567").is_err());
+        assert!(check_password("12345678").is_ok());
+        // 8 characters, but 16 bytes
+        assert!(check_password("éééééééé").is_ok());
+        assert!(check_password(&"é".repeat(128)).is_ok());
+        assert!(check_password(&"é".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn only_new_usernames_are_validated() {
+        let (config, dir) = temp_config();
+
+        assert_eq!(user(&config, &["add", "a b"]), Err("a username only has letters, digits, `_`, `.` and `-`".to_string()));
+        assert_eq!(user(&config, &["remove", "a b"]), Err("there's no user named `a b`".to_string()));
+        assert_eq!(user(&config, &["disable", "a"]), Err("there's no user named `a`".to_string()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,40 +1,221 @@
+use std::{fmt, str::FromStr};
+
 use actix::{Actor, Handler, Context};
+use dalang_protocol::user::UserProfile;
 
 pub mod sqlite;
 
-// An authenticator is an actor that handles Login and Register messages
+// An authenticator is an actor that handles Login and Register messages, along with the
+// messages to manage the users
 pub trait Authenticator:
     Actor<Context = Context<Self>>
     + Handler<messages::Login>
     + Handler<messages::Register>
     + Handler<messages::GetUser>
+    + Handler<messages::UpdateProfile>
+
+    + Handler<messages::ListUsers>
+    + Handler<messages::RemoveUser>
+    + Handler<messages::SetPassword>
+    + Handler<messages::SetDisabled>
+    + Handler<messages::SetRole>
+    + Handler<messages::UsernameExists>
+    + Handler<messages::CreateInvite>
 
     + Send + Sync
 {}
 
+/// What a user is allowed to do on the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    User,
+    /// Manages the server and its users
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role `{role}`, expected `user` or `admin`")),
+        }
+    }
+}
+
+/// Why a message to an [`Authenticator`] failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// There's no user with the username or uid
+    UserNotFound,
+    WrongPassword,
+    /// Another user has the username, regardless of its case
+    UsernameTaken,
+    /// The user was disabled by an admin, it can't login
+    Disabled,
+    /// The invite code is unknown or was already used
+    InvalidInvite,
+    /// The storage of the users failed, such as a database that can't be opened
+    Backend(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::UserNotFound => f.write_str("there's no such user"),
+            AuthError::WrongPassword => f.write_str("wrong password"),
+            AuthError::UsernameTaken => f.write_str("the username is taken"),
+            AuthError::Disabled => f.write_str("the user is disabled"),
+            AuthError::InvalidInvite => f.write_str("the invite code is unknown or was already used"),
+            AuthError::Backend(err) => write!(f, "the authenticator failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// A user along with its profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub uid: u64,
+    pub username: String,
+    /// Shown instead of the username, when it's set
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    /// The blob of the avatar picture
+    pub avatar: Option<u64>,
+    pub role: Role,
+    /// In seconds since the unix epoch, users that predate it count as created when the
+    /// database was upgraded
+    pub created_at: u64,
+    /// In seconds since the unix epoch, none if the user never logged in
+    pub last_login_at: Option<u64>,
+    /// Disabled users can't login
+    pub disabled: bool,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        UserProfile {
+            uid: user.uid,
+            username: user.username,
+            display_name: user.display_name,
+            email: user.email,
+            avatar: user.avatar,
+            role: user.role.to_string(),
+            created: user.created_at,
+            last_login: user.last_login_at,
+        }
+    }
+}
+
 pub mod messages {
     use actix::Message;
 
+    use super::{AuthError, Role, User};
+
     // Login message, results in the UID of the user
     #[derive(Message)]
-    #[rtype("Result<u64, ()>")]
+    #[rtype("Result<u64, AuthError>")]
     pub struct Login {
         pub username: String,
         pub password: String,
     }
 
-    // Register message, returns the UID of the new user
+    // Register message, returns the UID of the new user. The invite code is used up along with
+    // the registration, none is needed when it's `None`
     #[derive(Message)]
-    #[rtype("Result<u64, ()>")]
+    #[rtype("Result<u64, AuthError>")]
     pub struct Register {
         pub username: String,
-        pub password: String
+        pub password: String,
+        pub role: Role,
+        pub invite: Option<String>,
     }
 
-    // Retrieves a user data, currently it only returns the username
+    // Retrieves a user along with its profile
     #[derive(Message)]
-    #[rtype("Result<String, ()>")]
+    #[rtype("Result<User, AuthError>")]
     pub struct GetUser {
         pub uid: u64
     }
-}
\ No newline at end of file
+
+    // Replaces the profile of a user, the fields that are `None` are cleared. Results in the
+    // updated user
+    #[derive(Message)]
+    #[rtype("Result<User, AuthError>")]
+    pub struct UpdateProfile {
+        pub uid: u64,
+        pub display_name: Option<String>,
+        pub email: Option<String>,
+        pub avatar: Option<u64>,
+    }
+
+    // Checks whether a user has the username, regardless of its case
+    #[derive(Message)]
+    #[rtype("Result<bool, AuthError>")]
+    pub struct UsernameExists {
+        pub username: String,
+    }
+
+    // Creates a single-use invite code to register with
+    #[derive(Message)]
+    #[rtype("Result<String, AuthError>")]
+    pub struct CreateInvite;
+
+    // Lists every user, ordered by their username
+    #[derive(Message)]
+    #[rtype("Result<Vec<User>, AuthError>")]
+    pub struct ListUsers;
+
+    // The messages below fail with `AuthError::UserNotFound` if there's no user with the username
+
+    // Removes a user
+    #[derive(Message)]
+    #[rtype("Result<(), AuthError>")]
+    pub struct RemoveUser {
+        pub username: String,
+    }
+
+    // Changes the password of a user
+    #[derive(Message)]
+    #[rtype("Result<(), AuthError>")]
+    pub struct SetPassword {
+        pub username: String,
+        pub password: String,
+    }
+
+    // Disables or enables a user, disabled users can't login
+    #[derive(Message)]
+    #[rtype("Result<(), AuthError>")]
+    pub struct SetDisabled {
+        pub username: String,
+        pub disabled: bool,
+    }
+
+    // Changes the role of a user
+    #[derive(Message)]
+    #[rtype("Result<(), AuthError>")]
+    pub struct SetRole {
+        pub username: String,
+        pub role: Role,
+    }
+}
@@ -1,18 +1,22 @@
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use actix::Handler;
 use actix::{Actor, Context};
 use pwhash::bcrypt::{hash, verify};
-use r2d2::Pool;
+use r2d2::{ManageConnection, Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use r2d2_sqlite::rusqlite::{OptionalExtension, params};
+use r2d2_sqlite::rusqlite::{self, params, Connection, ErrorCode, OptionalExtension, TransactionBehavior};
 
-use super::Authenticator;
+use crate::migrations::{self, MigrationError};
+
+use super::{AuthError, Authenticator, User};
 use super::messages as auth_msg;
 
 /// An authenticator with SQLite as backend
 pub struct SQLiteAuthenticator {
     db_file: Option<PathBuf>,
+    /// `None` if the database couldn't be opened, every message fails then
     pool: Option<Pool<SqliteConnectionManager>>,
 }
 
@@ -33,14 +37,17 @@ impl SQLiteAuthenticator {
             pool: None,
         }
     }
-}
 
-impl Authenticator for SQLiteAuthenticator {}
+    fn connection(&self) -> Result<PooledConnection<SqliteConnectionManager>, AuthError> {
+        let pool = self.pool
+            .as_ref()
+            .ok_or_else(|| AuthError::Backend("the database couldn't be opened".to_string()))?;
 
-impl Actor for SQLiteAuthenticator {
-    type Context = Context<Self>;
+        Ok(pool.get()?)
+    }
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    /// Opens the database, and creates its tables if they don't exist
+    fn open(&self) -> Result<Pool<SqliteConnectionManager>, AuthError> {
         let manager = if let Some(db_file) = &self.db_file {
             // open connection to the database if the db file is present
             SqliteConnectionManager::file(db_file)
@@ -49,117 +56,311 @@ impl Actor for SQLiteAuthenticator {
             SqliteConnectionManager::memory()
         };
 
-        // todo: replace these expect statements to be an error enum
-        let pool = r2d2::Pool::new(manager)
-            .expect("failed to create connection pool");        
+        // a database that can't be opened fails right away, the pool would retry until its
+        // timeout
+        manager.connect()?;
+
+        // every connection to `:memory:` opens a database of its own
+        let max_size = if self.db_file.is_some() { 10 } else { 1 };
+        let pool = r2d2::Pool::builder().max_size(max_size).build(manager)?;
+
+        // upgrade the database, or create its tables if it's new
+        let mut conn = pool.get()?;
+        let migrated = migrations::migrate(&mut conn, self.db_file.as_deref())?;
+
+        if migrated.from != migrated.to {
+            match &migrated.backup {
+                Some(backup) => println!(
+                    "upgraded the database from the version {} to {}, it was backed up to {}",
+                    migrated.from, migrated.to, backup.display()
+                ),
+                None => println!("created the database at the version {}", migrated.to),
+            }
+        }
 
-        // create a new table if it doesnt exist
-        let conn = pool.get().expect("failed to retrieve connection");
-        conn.execute(QUERY_USERS_CREATE, []).expect("failed to create table");
+        Ok(pool)
+    }
+}
 
-        self.pool = Some(pool);
+impl From<rusqlite::Error> for AuthError {
+    fn from(value: rusqlite::Error) -> Self {
+        AuthError::Backend(value.to_string())
     }
 }
 
-const QUERY_USERS_CREATE: &str = r#"
-CREATE TABLE IF NOT EXISTS "users" (
-	"uid"	sqlite3_uint64 NOT NULL UNIQUE,
-	"username"	TEXT NOT NULL,
-	"password"	TEXT NOT NULL,
-	PRIMARY KEY("uid")
-)"#;
+impl From<MigrationError> for AuthError {
+    fn from(value: MigrationError) -> Self {
+        AuthError::Backend(value.to_string())
+    }
+}
+
+impl From<r2d2::Error> for AuthError {
+    fn from(value: r2d2::Error) -> Self {
+        AuthError::Backend(value.to_string())
+    }
+}
+
+impl From<pwhash::error::Error> for AuthError {
+    fn from(value: pwhash::error::Error) -> Self {
+        AuthError::Backend(format!("failed to hash the password: {value}"))
+    }
+}
+
+impl Authenticator for SQLiteAuthenticator {}
+
+impl Actor for SQLiteAuthenticator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        // the messages fail until the server is restarted, instead of stopping the actor
+        match self.open() {
+            Ok(pool) => self.pool = Some(pool),
+            Err(err) => println!("failed to open the database of the users: {err}"),
+        }
+    }
+}
 
 const QUERY_UID_GET: &str = r#"SELECT * FROM users WHERE uid = ?1;"#;
-const QUERY_USERNAME_GET: &str = r#"SELECT * FROM users WHERE username = ?1;"#;
+// usernames are unique regardless of their case, they're looked up the same way
+const QUERY_USERNAME_GET: &str = r#"SELECT * FROM users WHERE username = ?1 COLLATE NOCASE;"#;
+const QUERY_USERNAME_TAKEN: &str = r#"SELECT uid FROM users WHERE username = ?1 COLLATE NOCASE;"#;
+
+const QUERY_USERS_LIST: &str = r#"SELECT * FROM users ORDER BY username;"#;
+
+const QUERY_USER_INSERT: &str =
+    r#"INSERT INTO users (uid, username, password, role, created_at) VALUES (?1, ?2, ?3, ?4, ?5);"#;
+const QUERY_USER_DELETE: &str = r#"DELETE FROM users WHERE username = ?1 COLLATE NOCASE;"#;
+
+const QUERY_PASSWORD_SET: &str = r#"UPDATE users SET password = ?2 WHERE username = ?1 COLLATE NOCASE;"#;
+const QUERY_DISABLED_SET: &str = r#"UPDATE users SET disabled = ?2 WHERE username = ?1 COLLATE NOCASE;"#;
+const QUERY_ROLE_SET: &str = r#"UPDATE users SET role = ?2 WHERE username = ?1 COLLATE NOCASE;"#;
+
+const QUERY_PROFILE_SET: &str = r#"UPDATE users SET display_name = ?2, email = ?3, avatar = ?4 WHERE uid = ?1;"#;
+const QUERY_LAST_LOGIN_SET: &str = r#"UPDATE users SET last_login_at = ?2 WHERE uid = ?1;"#;
 
-const QUERY_USER_INSERT: &str = r#"INSERT INTO users (uid, username, password) VALUES (?1, ?2, ?3);"#;
+const QUERY_INVITE_INSERT: &str = r#"INSERT INTO invites (code, created_at) VALUES (?1, ?2);"#;
+const QUERY_INVITE_USE: &str = r#"UPDATE invites SET used_by = ?2, used_at = ?3 WHERE code = ?1 AND used_by IS NULL;"#;
+
+/// The seconds since the unix epoch
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// Reads a user from a row of `users`
+fn user_from_row(row: &rusqlite::Row) -> rusqlite::Result<User> {
+    Ok(User {
+        uid: row.get("uid")?,
+        username: row.get("username")?,
+        display_name: row.get("display_name")?,
+        email: row.get("email")?,
+        avatar: row.get("avatar")?,
+        // roles unknown to this version are not given more permissions
+        role: row.get::<_, String>("role")?.parse().unwrap_or_default(),
+        created_at: row.get("created_at")?,
+        last_login_at: row.get("last_login_at")?,
+        disabled: row.get("disabled")?,
+    })
+}
+
+/// Fails with [`AuthError::UserNotFound`] if no row was changed
+fn changed_user(changed: usize) -> Result<(), AuthError> {
+    match changed {
+        0 => Err(AuthError::UserNotFound),
+        _ => Ok(()),
+    }
+}
 
 impl Handler<auth_msg::Login> for SQLiteAuthenticator {
-    type Result = Result<u64, ()>;
+    type Result = Result<u64, AuthError>;
 
     fn handle(&mut self, msg: auth_msg::Login, _ctx: &mut Self::Context) -> Self::Result {
-        let conn =
-            self.pool
-                .as_ref().expect("pool not initialized")
-                .clone()
-                .get().expect("failed to retrieve connection");
-        
+        let conn = self.connection()?;
+
         // retrieve the user
-        // todo: replace these expect statements to be an error enum
-        if let Some((uid, password_hash)) = 
+        let (uid, password_hash, disabled) =
             conn.query_row(
                     QUERY_USERNAME_GET,
                     params![&msg.username],
                     |row| Ok((
                         row.get::<_, u64>("uid")?,
-                        row.get::<_, String>("password")?))
+                        row.get::<_, String>("password")?,
+                        row.get::<_, bool>("disabled")?))
                 )
-                .optional()
-                .expect("failed to retrieve user") {
-
-            // check if the password is correct
-            // returns `Ok(uid)` if password is correct
-            verify(&msg.password, &password_hash)
-                .then_some(uid)
-                .ok_or(())
-        } else {
-            // user doesn't exists
-            // todo: return a descriptive error
-            Err(())
+                .optional()?
+                .ok_or(AuthError::UserNotFound)?;
+
+        // disabled users can't login, even with the right password
+        if disabled {
+            return Err(AuthError::Disabled);
         }
+
+        // check if the password is correct
+        if !verify(&msg.password, &password_hash) {
+            return Err(AuthError::WrongPassword);
+        }
+
+        conn.execute(QUERY_LAST_LOGIN_SET, params![uid, unix_time()])?;
+
+        Ok(uid)
     }
 }
 
-impl Handler<auth_msg::Register> for SQLiteAuthenticator {
-    type Result = Result<u64, ()>;
+/// How many uids are drawn before giving up, a collision is already unlikely with 63 bits
+const UID_ATTEMPTS: usize = 8;
 
-    fn handle(&mut self, msg: auth_msg::Register, _ctx: &mut Self::Context) -> Self::Result {
-        let conn =
-            self.pool
-                .as_ref().expect("pool not initialized")
-                .clone()
-                .get().expect("failed to retrieve connection");
-
-        // check if user already exists
-        let changed = conn.execute(QUERY_USERNAME_GET, params![&msg.username])
-            .expect("failed to execute query");
-
-        // a user with the same username already exists
-        if changed != 0 {
-            Err(())?
+/// Registers a user in a transaction that holds the lock for writing, other connections to the
+/// database can't register the same username in between
+fn register(conn: &mut Connection, msg: &auth_msg::Register, password_hash: &str) -> Result<u64, AuthError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    // check if user already exists
+    let existing = tx.query_row(QUERY_USERNAME_TAKEN, params![&msg.username], |row| row.get::<_, u64>("uid"))
+        .optional()?;
+
+    // a user with the same username already exists
+    if existing.is_some() {
+        return Err(AuthError::UsernameTaken);
+    }
+
+    // we can then insert our new user, sqlite integers are signed 64-bit so the uid must
+    // fit in an i64. A uid that is taken is drawn again.
+    let mut uids = std::iter::repeat_with(|| rand::random::<u64>() >> 1).take(UID_ATTEMPTS);
+
+    let uid = loop {
+        let Some(uid) = uids.next() else {
+            return Err(AuthError::Backend("failed to draw a uid that isn't taken".to_string()));
+        };
+
+        if tx.query_row(QUERY_UID_GET, params![uid], |_| Ok(())).optional()?.is_none() {
+            break uid;
         }
+    };
 
-        // we can then insert our new user
-        let uid = rand::random();
+    if let Some(invite) = &msg.invite {
+        if tx.execute(QUERY_INVITE_USE, params![invite, uid, unix_time()])? == 0 {
+            return Err(AuthError::InvalidInvite);
+        }
+    }
 
-        if let Ok(changed) = conn.execute(
-            QUERY_USER_INSERT,
-            params![uid, &msg.username, hash(&msg.password).map_err(|_| ())?]
-        ) {
-            assert_eq!(changed, 1);
-            Ok(uid)
-        } else {
-            Err(())
+    match tx.execute(QUERY_USER_INSERT, params![uid, &msg.username, password_hash, msg.role.as_str(), unix_time()]) {
+        // the unique index is the last guard against duplicates
+        Err(err) if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+            return Err(AuthError::UsernameTaken);
         }
+        result => result?,
+    };
+
+    tx.commit()?;
+
+    Ok(uid)
+}
+
+impl Handler<auth_msg::Register> for SQLiteAuthenticator {
+    type Result = Result<u64, AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::Register, _ctx: &mut Self::Context) -> Self::Result {
+        let mut conn = self.connection()?;
+
+        // hashed before the database is locked, it's slow on purpose
+        let password_hash = hash(&msg.password)?;
+
+        register(&mut conn, &msg, &password_hash)
     }
 }
 
 impl Handler<auth_msg::GetUser> for SQLiteAuthenticator {
-    type Result = Result<String, ()>;
+    type Result = Result<User, AuthError>;
 
     fn handle(&mut self, msg: auth_msg::GetUser, _ctx: &mut Self::Context) -> Self::Result {
-        let conn =
-            self.pool
-                .as_ref().expect("pool not initialized")
-                .clone()
-                .get().expect("failed to retrieve connection");
-        
-        // retrieve the username (for later, we'll have a User model)
-        conn.query_row(
-            QUERY_UID_GET,
-            params![msg.uid],
-            |row| Ok(row.get::<_, String>("username")?)
-        ).map_err(|_| ())
+        self.connection()?
+            .query_row(QUERY_UID_GET, params![msg.uid], user_from_row)
+            .optional()?
+            .ok_or(AuthError::UserNotFound)
+    }
+}
+
+impl Handler<auth_msg::UpdateProfile> for SQLiteAuthenticator {
+    type Result = Result<User, AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::UpdateProfile, _ctx: &mut Self::Context) -> Self::Result {
+        let conn = self.connection()?;
+
+        changed_user(conn.execute(QUERY_PROFILE_SET, params![msg.uid, msg.display_name, msg.email, msg.avatar])?)?;
+
+        Ok(conn.query_row(QUERY_UID_GET, params![msg.uid], user_from_row)?)
+    }
+}
+
+impl Handler<auth_msg::UsernameExists> for SQLiteAuthenticator {
+    type Result = Result<bool, AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::UsernameExists, _ctx: &mut Self::Context) -> Self::Result {
+        let uid = self.connection()?
+            .query_row(QUERY_USERNAME_TAKEN, params![&msg.username], |row| row.get::<_, u64>("uid"))
+            .optional()?;
+
+        Ok(uid.is_some())
+    }
+}
+
+impl Handler<auth_msg::CreateInvite> for SQLiteAuthenticator {
+    type Result = Result<String, AuthError>;
+
+    fn handle(&mut self, _msg: auth_msg::CreateInvite, _ctx: &mut Self::Context) -> Self::Result {
+        let code = format!("{:032x}", rand::random::<u128>());
+
+        self.connection()?.execute(QUERY_INVITE_INSERT, params![&code, unix_time()])?;
+
+        Ok(code)
+    }
+}
+
+impl Handler<auth_msg::ListUsers> for SQLiteAuthenticator {
+    type Result = Result<Vec<User>, AuthError>;
+
+    fn handle(&mut self, _msg: auth_msg::ListUsers, _ctx: &mut Self::Context) -> Self::Result {
+        let conn = self.connection()?;
+        let mut statement = conn.prepare(QUERY_USERS_LIST)?;
+
+        let users = statement
+            .query_map([], user_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(users)
+    }
+}
+
+impl Handler<auth_msg::RemoveUser> for SQLiteAuthenticator {
+    type Result = Result<(), AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::RemoveUser, _ctx: &mut Self::Context) -> Self::Result {
+        changed_user(self.connection()?.execute(QUERY_USER_DELETE, params![&msg.username])?)
+    }
+}
+
+impl Handler<auth_msg::SetPassword> for SQLiteAuthenticator {
+    type Result = Result<(), AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::SetPassword, _ctx: &mut Self::Context) -> Self::Result {
+        let password_hash = hash(&msg.password)?;
+
+        changed_user(self.connection()?.execute(QUERY_PASSWORD_SET, params![&msg.username, password_hash])?)
+    }
+}
+
+impl Handler<auth_msg::SetDisabled> for SQLiteAuthenticator {
+    type Result = Result<(), AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::SetDisabled, _ctx: &mut Self::Context) -> Self::Result {
+        changed_user(self.connection()?.execute(QUERY_DISABLED_SET, params![&msg.username, msg.disabled])?)
+    }
+}
+
+impl Handler<auth_msg::SetRole> for SQLiteAuthenticator {
+    type Result = Result<(), AuthError>;
+
+    fn handle(&mut self, msg: auth_msg::SetRole, _ctx: &mut Self::Context) -> Self::Result {
+        changed_user(self.connection()?.execute(QUERY_ROLE_SET, params![&msg.username, msg.role.as_str()])?)
     }
 }
 
@@ -167,8 +368,10 @@ impl Handler<auth_msg::GetUser> for SQLiteAuthenticator {
 mod tests {
     use actix::Actor;
 
-    use super::SQLiteAuthenticator;
-    use super::super::messages as auth_msg;
+    use r2d2_sqlite::rusqlite::{self, Connection};
+
+    use super::{register, SQLiteAuthenticator};
+    use super::super::{messages as auth_msg, AuthError, Role};
 
     #[actix_rt::test]
     async fn sqlite_simple_auth_test_0() {
@@ -177,6 +380,8 @@ mod tests {
         let register_uid = addr.send(auth_msg::Register {
             username: "loremipsum".to_string(),
             password: "1234567890".to_string(),
+            role: Role::User,
+            invite: None,
         }).await.expect("failed send register msg").expect("failed to register");
 
         let login_uid = addr.send(auth_msg::Login {
@@ -186,10 +391,215 @@ mod tests {
 
         assert_eq!(register_uid, login_uid);
 
-        let username = addr.send(auth_msg::GetUser {
+        let user = addr.send(auth_msg::GetUser {
             uid: register_uid,
-        }).await.expect("failed to retrieve username").expect("failed to retrieve username");
+        }).await.expect("failed to retrieve user").expect("failed to retrieve user");
+
+        assert_eq!(user.username, "loremipsum".to_string());
+        assert!(user.last_login_at.is_some_and(|last_login| last_login >= user.created_at));
+    }
+
+    #[actix_rt::test]
+    async fn sqlite_update_profile() {
+        let addr = SQLiteAuthenticator::new_in_memory().start();
+
+        let uid = addr.send(auth_msg::Register {
+            username: "lorem".to_string(),
+            password: "1234567890".to_string(),
+            role: Role::User,
+            invite: None,
+        }).await.unwrap().expect("failed to register");
+
+        let user = addr.send(auth_msg::GetUser { uid }).await.unwrap().unwrap();
+
+        assert_eq!((user.display_name, user.email, user.avatar, user.last_login_at), (None, None, None, None));
+
+        let update = |display_name: Option<&str>, email: Option<&str>, avatar| auth_msg::UpdateProfile {
+            uid,
+            display_name: display_name.map(str::to_string),
+            email: email.map(str::to_string),
+            avatar,
+        };
+
+        let updated = addr.send(update(Some("Lorem Ipsum"), Some("lorem@ipsum.dolor"), Some(7))).await.unwrap().unwrap();
+
+        assert_eq!(updated.display_name.as_deref(), Some("Lorem Ipsum"));
+        assert_eq!(updated.email.as_deref(), Some("lorem@ipsum.dolor"));
+        assert_eq!(updated.avatar, Some(7));
+        assert_eq!(addr.send(auth_msg::GetUser { uid }).await.unwrap(), Ok(updated));
+
+        // the fields left out are cleared
+        let cleared = addr.send(update(Some("Lorem"), None, None)).await.unwrap().unwrap();
+        assert_eq!((cleared.display_name.as_deref(), cleared.email, cleared.avatar), (Some("Lorem"), None, None));
+
+        assert_eq!(
+            addr.send(auth_msg::UpdateProfile { uid: uid ^ 1, display_name: None, email: None, avatar: None }).await.unwrap(),
+            Err(AuthError::UserNotFound)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn sqlite_manage_users() {
+        let addr = SQLiteAuthenticator::new_in_memory().start();
+
+        for username in ["lorem", "ipsum"] {
+            addr.send(auth_msg::Register {
+                username: username.to_string(),
+                password: "1234567890".to_string(),
+                role: Role::User,
+                invite: None,
+            }).await.unwrap().expect("failed to register");
+        }
+
+        let login = |password: &str| addr.send(auth_msg::Login {
+            username: "lorem".to_string(),
+            password: password.to_string(),
+        });
+
+        // the usernames are taken, regardless of their case
+        assert_eq!(addr.send(auth_msg::Register {
+            username: "LOREM".to_string(),
+            password: "1234567890".to_string(),
+            role: Role::Admin,
+            invite: None,
+        }).await.unwrap(), Err(AuthError::UsernameTaken));
+
+        // disabled users can't login
+        assert_eq!(addr.send(auth_msg::SetDisabled {
+            username: "lorem".to_string(),
+            disabled: true,
+        }).await.unwrap(), Ok(()));
+
+        assert_eq!(login("1234567890").await.unwrap(), Err(AuthError::Disabled));
+
+        addr.send(auth_msg::SetDisabled {
+            username: "lorem".to_string(),
+            disabled: false,
+        }).await.unwrap().unwrap();
+
+        assert_eq!(addr.send(auth_msg::SetPassword {
+            username: "lorem".to_string(),
+            password: "0987654321".to_string(),
+        }).await.unwrap(), Ok(()));
+
+        assert_eq!(login("1234567890").await.unwrap(), Err(AuthError::WrongPassword));
+        assert!(login("0987654321").await.unwrap().is_ok());
+
+        assert_eq!(addr.send(auth_msg::SetRole {
+            username: "ipsum".to_string(),
+            role: Role::Admin,
+        }).await.unwrap(), Ok(()));
+
+        let users = addr.send(auth_msg::ListUsers).await.unwrap().unwrap();
+
+        assert_eq!(
+            users.iter().map(|user| (user.username.as_str(), user.role)).collect::<Vec<_>>(),
+            [("ipsum", Role::Admin), ("lorem", Role::User)]
+        );
+
+        assert_eq!(addr.send(auth_msg::RemoveUser { username: "ipsum".to_string() }).await.unwrap(), Ok(()));
+        assert_eq!(addr.send(auth_msg::RemoveUser { username: "ipsum".to_string() }).await.unwrap(), Err(AuthError::UserNotFound));
+
+        assert_eq!(addr.send(auth_msg::ListUsers).await.unwrap().unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn sqlite_usernames_are_case_insensitive() {
+        let addr = SQLiteAuthenticator::new_in_memory().start();
+
+        let uid = addr.send(auth_msg::Register {
+            username: "Lorem".to_string(),
+            password: "1234567890".to_string(),
+            role: Role::User,
+            invite: None,
+        }).await.unwrap().expect("failed to register");
+
+        let login = |username: &str| addr.send(auth_msg::Login {
+            username: username.to_string(),
+            password: "1234567890".to_string(),
+        });
+
+        assert_eq!(login("lorem").await.unwrap(), Ok(uid));
+        assert_eq!(login("LOREM").await.unwrap(), Ok(uid));
+
+        // the username is kept as it was registered
+        assert_eq!(addr.send(auth_msg::GetUser { uid }).await.unwrap().unwrap().username, "Lorem");
+
+        assert_eq!(addr.send(auth_msg::SetRole {
+            username: "lOrEm".to_string(),
+            role: Role::Admin,
+        }).await.unwrap(), Ok(()));
 
-        assert_eq!(username, "loremipsum".to_string());
+        assert_eq!(addr.send(auth_msg::RemoveUser { username: "LOREM".to_string() }).await.unwrap(), Ok(()));
+        assert_eq!(login("Lorem").await.unwrap(), Err(AuthError::UserNotFound));
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn sqlite_fails_without_database() {
+        // the directory of the database doesn't exist
+        let addr = SQLiteAuthenticator::new("/nonexistent/dalang/dalang.db".into()).start();
+
+        let login = addr.send(auth_msg::Login {
+            username: "lorem".to_string(),
+            password: "1234567890".to_string(),
+        }).await.expect("the authenticator stopped");
+
+        assert!(matches!(login, Err(AuthError::Backend(_))));
+
+        // and keeps answering
+        let users = addr.send(auth_msg::ListUsers).await.expect("the authenticator stopped");
+        assert!(matches!(users, Err(AuthError::Backend(_))));
+
+        let unknown = SQLiteAuthenticator::new_in_memory().start().send(auth_msg::GetUser { uid: 1 }).await.unwrap();
+        assert_eq!(unknown, Err(AuthError::UserNotFound));
+    }
+
+    #[test]
+    fn sqlite_registers_a_username_once() {
+        let path = std::env::temp_dir().join(format!("dalang-users-{:x}.db", rand::random::<u64>()));
+        drop(SQLiteAuthenticator::new(path.clone()).open().expect("failed to create the database"));
+
+        // every thread has a connection of its own, as separate servers sharing the database would
+        let threads = ["lorem", "LOREM", "Lorem", "loREM", "lorem", "LoReM", "lOrEm", "lorem"]
+            .map(|username| {
+                let path = path.clone();
+
+                std::thread::spawn(move || {
+                    let mut conn = Connection::open(&path).unwrap();
+
+                    register(&mut conn, &auth_msg::Register {
+                        username: username.to_string(),
+                        password: "1234567890".to_string(),
+                        role: Role::User,
+                        invite: None,
+                    }, "")
+                })
+            });
+
+        let results = threads.map(|thread| thread.join().unwrap());
+
+        let conn = Connection::open(&path).unwrap();
+        let count = conn.query_row(r#"SELECT count(*) FROM users;"#, [], |row| row.get::<_, u64>(0)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results.iter().all(|result| matches!(result, Ok(_) | Err(AuthError::UsernameTaken))));
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn sqlite_usernames_are_unique() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+
+        let insert = |uid: u64, username: &str| conn.execute(
+            super::QUERY_USER_INSERT,
+            rusqlite::params![uid, username, "", "user", 0]
+        );
+
+        // even when the checks of `register` are bypassed
+        insert(1, "lorem").unwrap();
+        assert!(insert(2, "LOREM").is_err());
+        assert!(insert(3, "ipsum").is_ok());
+    }
+}
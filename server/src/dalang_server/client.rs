@@ -0,0 +1,236 @@
+//! The processing of the packets that is the same whether the client is connected through a
+//! websocket ([`crate::session::Session`]) or a raw stream
+//! ([`crate::stream_session::StreamSession`]), only the way the answers are sent differs.
+
+use std::fmt::Debug;
+
+use actix::{
+    dev::ToEnvelope, fut, Actor, ActorFutureExt, Addr, AsyncContext, ContextFutureSpawner, Handler, MailboxError,
+    Message, WrapFuture,
+};
+use dalang_protocol::{
+    authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+    user::{ClientUserPacket, ServerUserPacket},
+    response_allowed, Packet, Request, ServerPacket,
+};
+
+use crate::{auth::{self, AuthError}, server::{self, DalangServer}, sessions::SessionState};
+
+pub(crate) trait ClientSession<A: auth::Authenticator>: Actor {
+    /// The unique ID of the session
+    fn id(&self) -> usize;
+
+    fn server(&self) -> &Addr<DalangServer<A>>;
+
+    /// What the client is logged in as
+    fn state(&mut self) -> &mut SessionState;
+
+    /// Sends a packet to the client, encoded with the extensions it accepted
+    fn send_packet(&mut self, packet: ServerPacket, ctx: &mut Self::Context);
+
+    fn handle_authentication(&mut self, packet: ClientAuthenticationPacket, ctx: &mut Self::Context)
+    where
+        Self::Context: AsyncContext<Self>,
+    {
+        let check = packet.response_check();
+
+        match packet {
+            ClientAuthenticationPacket::Register { username, password, invite } => {
+                let register = server::RegisterUser {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                    invite: invite.map(str::to_string),
+                };
+
+                self.request(check, register, ctx, |result| match result {
+                    Ok(Ok(_)) => ServerAuthenticationPacket::SuccessResp,
+                    Ok(Err(err)) => err.response(),
+                    Err(_) => ServerAuthenticationPacket::ErrorInternal,
+                });
+            }
+
+            ClientAuthenticationPacket::RegisterCheckEnabled => {
+                self.request(check, server::GetRegistrationMode, ctx, |result| match result {
+                    Ok(mode) => mode.response(),
+                    Err(_) => ServerAuthenticationPacket::ErrorInternal,
+                });
+            }
+
+            ClientAuthenticationPacket::UsernameCheckExists { username } => {
+                let exists = server::CheckUsername { username: username.to_string() };
+
+                self.request(check, exists, ctx, |result| match result {
+                    Ok(Ok(())) => ServerAuthenticationPacket::SuccessResp,
+                    Ok(Err(err)) => err.response(),
+                    Err(_) => ServerAuthenticationPacket::ErrorInternal,
+                });
+            }
+
+            ClientAuthenticationPacket::Login { .. } | ClientAuthenticationPacket::LoginWithToken { .. }
+                if self.state().uid.is_some() =>
+            {
+                self.respond(check, ServerAuthenticationPacket::ErrorAlreadyLoggedIn, ctx);
+            }
+
+            ClientAuthenticationPacket::Login { username, password } => {
+                let login = server::LoginUser { username: username.to_string(), password: password.to_string() };
+
+                self.server()
+                    .send(login)
+                    .into_actor(self)
+                    .then(move |result, session, ctx| {
+                        let response = match result {
+                            Ok(Ok(user)) => {
+                                *session.state() = SessionState {
+                                    uid: Some(user.uid),
+                                    username: Some(user.username),
+                                    ..Default::default()
+                                };
+
+                                // login tokens are not issued yet, see `LoginWithToken` below
+                                ServerAuthenticationPacket::LoginSuccess { token: String::new() }
+                            }
+
+                            // disabled users aren't told apart from unknown ones
+                            Ok(Err(AuthError::UserNotFound | AuthError::WrongPassword | AuthError::Disabled)) => {
+                                ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+                            }
+
+                            Ok(Err(_)) | Err(_) => ServerAuthenticationPacket::ErrorInternal,
+                        };
+
+                        session.respond(check, response, ctx);
+
+                        fut::ready(())
+                    })
+                    .wait(ctx);
+            }
+
+            ClientAuthenticationPacket::Logout => {
+                *self.state() = SessionState::default();
+
+                self.respond(check, ServerAuthenticationPacket::SuccessResp, ctx);
+            }
+
+            // no token was issued yet, so none can be valid
+            ClientAuthenticationPacket::LoginWithToken { .. } => {
+                self.respond(check, ServerAuthenticationPacket::LoginFailedTokenExpired, ctx);
+            }
+
+            // the acknowledgements of the client aren't requests
+            ClientAuthenticationPacket::SuccessResp => (),
+        }
+    }
+
+    fn handle_user(&mut self, packet: ClientUserPacket, ctx: &mut Self::Context)
+    where
+        Self::Context: AsyncContext<Self>,
+    {
+        let check = packet.response_check();
+
+        // the acknowledgements of the client aren't requests
+        if matches!(packet, ClientUserPacket::SuccessResp) {
+            return;
+        }
+
+        let Some(uid) = self.state().uid else {
+            self.respond(check, ServerUserPacket::ErrorNotAuthenticated, ctx);
+            return;
+        };
+
+        let profile = |result: Result<Result<auth::User, AuthError>, MailboxError>| match result {
+            Ok(Ok(user)) => ServerUserPacket::ProfileResp { profile: user.into() },
+            // the user was removed since the client logged in
+            Ok(Err(AuthError::UserNotFound)) => ServerUserPacket::ErrorNotAuthenticated,
+            Ok(Err(_)) | Err(_) => ServerUserPacket::ErrorInternal,
+        };
+
+        match packet {
+            ClientUserPacket::GetUsername => {
+                let username = self.state().username.clone().unwrap_or_default();
+
+                self.respond(check, ServerUserPacket::UsernameResp { username }, ctx);
+            }
+
+            ClientUserPacket::GetProfile => self.request(check, server::GetProfile { uid }, ctx, profile),
+
+            ClientUserPacket::UpdateProfile { display_name, email, avatar } => {
+                let update = server::UpdateProfile { uid, display_name, email, avatar };
+
+                self.request(check, update, ctx, profile);
+            }
+
+            // the projects are not stored yet, the client is still answered so that it doesn't
+            // wait for a response forever
+            _ => self.respond(check, ServerUserPacket::ErrorInternal, ctx),
+        }
+    }
+
+    /// Sends a request to the server, and answers the client with the packet made from its
+    /// result. The packets sent by the client afterwards wait for the answer.
+    ///
+    /// `check` is the [`Request::response_check`] of the client's packet, the answer must be
+    /// one of the responses it declares or an error.
+    fn request<M, R, F>(&mut self, check: fn(&R) -> bool, msg: M, ctx: &mut Self::Context, respond: F)
+    where
+        Self::Context: AsyncContext<Self>,
+        M: Message + Send + 'static,
+        M::Result: Send,
+        DalangServer<A>: Handler<M>,
+        <DalangServer<A> as Actor>::Context: ToEnvelope<DalangServer<A>, M>,
+        R: for<'a> Packet<'a> + Into<ServerPacket> + Debug + 'static,
+        F: FnOnce(Result<M::Result, MailboxError>) -> R + 'static,
+    {
+        self.server()
+            .send(msg)
+            .into_actor(self)
+            .then(move |result, session, ctx| {
+                session.respond(check, respond(result), ctx);
+
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// Answers a request of the client, `check` is its [`Request::response_check`]
+    fn respond<R>(&mut self, check: fn(&R) -> bool, response: R, ctx: &mut Self::Context)
+    where
+        R: for<'a> Packet<'a> + Into<ServerPacket> + Debug,
+    {
+        let allowed = check_response(self.id(), check, &response);
+        debug_assert!(allowed, "{response:?} isn't allowed as the response of the request");
+
+        self.send_packet(response.into(), ctx);
+    }
+}
+
+/// Checks that a response is allowed for the request it answers, logging it when it isn't: the
+/// client may not expect it.
+fn check_response<R: for<'a> Packet<'a> + Debug>(id: usize, check: fn(&R) -> bool, response: &R) -> bool {
+    let allowed = response_allowed(check, response);
+
+    if !allowed {
+        println!("[id:{id}] answering a request with {response:?}, which it doesn't declare");
+    }
+
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use dalang_protocol::{
+        authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+        Request,
+    };
+
+    use super::check_response;
+
+    #[test]
+    fn checks_the_declared_responses() {
+        let check = ClientAuthenticationPacket::RegisterCheckEnabled.response_check();
+
+        assert!(check_response(0, check, &ServerAuthenticationPacket::RegisterInviteOnly));
+        assert!(check_response(0, check, &ServerAuthenticationPacket::ErrorInternal));
+        assert!(!check_response(0, check, &ServerAuthenticationPacket::LoginFailedTokenExpired));
+    }
+}
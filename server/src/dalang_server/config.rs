@@ -0,0 +1,640 @@
+//! The configuration of the server, loaded from a TOML file:
+//!
+//! ```toml
+//! [server]
+//! listen = ["0.0.0.0:8080"]
+//! endpoint = "/dalang"
+//!
+//! [storage]
+//! data_dir = "/var/lib/dalang"
+//! ```
+//!
+//! Keys are named after their section and field, such as `server.listen`. Every key may also be
+//! overridden by an environment variable, `DALANG_` followed by the key in uppercase where the
+//! dot is replaced by two underscores: `DALANG_SERVER__LISTEN='["0.0.0.0:80"]'`. Values are read
+//! as TOML, and as a string if they aren't valid TOML.
+
+use std::{
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use dalang_protocol::DecodeLimits;
+use serde::{Deserialize, Serialize};
+use toml_edit::DocumentMut;
+
+use crate::{
+    registration::{RegistrationMode, RegistrationPolicy},
+    sessions::ResumeOptions,
+    RawListener,
+};
+
+/// Where the configuration is read from if no other path is given
+pub const DEFAULT_PATH: &str = "dalang.toml";
+
+/// The prefix of the environment variables that override the configuration
+pub const ENV_PREFIX: &str = "DALANG_";
+
+/// Every key of the configuration, in the order they are listed
+pub const KEYS: &[&str] = &[
+    "server.listen",
+    "server.endpoint",
+    "server.static_dir",
+    "server.tcp",
+    "server.unix",
+    "server.json_debug",
+    "storage.backend",
+    "storage.data_dir",
+    "storage.database",
+    "auth.registration",
+    "auth.reserved_usernames",
+    "auth.token_lifetime",
+    "sessions.grace_period",
+    "sessions.max_events",
+    "limits.max_size",
+    "limits.max_depth",
+    "limits.max_collection_len",
+    "limits.max_string_len",
+    "render.melt",
+    "render.workers",
+    "daemon.pid_file",
+    "daemon.log_file",
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// The file is not valid TOML
+    Parse(toml_edit::TomlError),
+    /// A value has the wrong type, or a key is unknown
+    Invalid(toml::de::Error),
+    /// Not one of [`KEYS`]
+    UnknownKey(String),
+    /// A value is of the right type, but can't be used
+    Validation { key: &'static str, reason: &'static str },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{err}"),
+            ConfigError::Parse(err) => write!(f, "invalid TOML: {err}"),
+            ConfigError::Invalid(err) => write!(f, "invalid configuration: {err}"),
+            ConfigError::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            ConfigError::Validation { key, reason } => write!(f, "`{key}` {reason}"),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<toml_edit::TomlError> for ConfigError {
+    fn from(value: toml_edit::TomlError) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub auth: AuthConfig,
+    pub sessions: SessionsConfig,
+    pub limits: LimitsConfig,
+    pub render: RenderConfig,
+    pub daemon: DaemonConfig,
+    /// The file the configuration was loaded from, it is loaded again on SIGHUP
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// The options of the command line applied over the file, applied again when it's reloaded
+    #[serde(skip)]
+    pub overrides: Overrides,
+}
+
+/// The options of `dalang start` that override the configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub listen: Option<Vec<SocketAddr>>,
+    pub endpoint: Option<String>,
+    /// `Some(None)` to serve no static files
+    pub static_dir: Option<Option<PathBuf>>,
+    /// A database to use instead of the one of the configuration, with the SQLite backend
+    pub database: Option<PathBuf>,
+    pub in_memory: bool,
+    pub tcp: Option<Vec<SocketAddr>>,
+    pub unix: Option<Vec<PathBuf>>,
+    pub json_debug: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The addresses the websocket server (and the static files) listen on
+    pub listen: Vec<SocketAddr>,
+    /// The path of the websocket endpoint
+    pub endpoint: String,
+    /// A directory of static files to serve, such as the frontend
+    pub static_dir: Option<PathBuf>,
+    /// Addresses to accept raw TCP connections on
+    pub tcp: Vec<SocketAddr>,
+    /// Unix sockets to accept raw connections on
+    pub unix: Vec<PathBuf>,
+    /// Offer the `json-debug` extension to websocket clients
+    pub json_debug: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: vec![SocketAddr::from(([127, 0, 0, 1], 8080))],
+            endpoint: "/dalang".to_string(),
+            static_dir: None,
+            tcp: vec![],
+            unix: vec![],
+            json_debug: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The listeners of raw connections, TCP then Unix sockets
+    pub fn raw_listeners(&self) -> Vec<RawListener> {
+        #[allow(unused_mut)] // unix sockets are only added on unix
+        let mut listeners: Vec<RawListener> = self.tcp.iter().copied().map(RawListener::Tcp).collect();
+
+        #[cfg(unix)]
+        listeners.extend(self.unix.iter().cloned().map(RawListener::Unix));
+
+        listeners
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// A SQLite database in the data directory
+    #[default]
+    Sqlite,
+    /// Everything is kept in memory, and lost once the server stops
+    Memory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Where the data of the server is stored
+    pub data_dir: PathBuf,
+    /// The SQLite database, relative to the data directory
+    pub database: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Sqlite,
+            data_dir: PathBuf::from("."),
+            database: PathBuf::from("dalang.db"),
+        }
+    }
+}
+
+impl StorageConfig {
+    /// The path of the SQLite database
+    pub fn database_path(&self) -> PathBuf {
+        self.data_dir.join(&self.database)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Who may register: `open`, `closed` or `invite`
+    pub registration: RegistrationMode,
+    /// The usernames that can't be registered, regardless of their case
+    pub reserved_usernames: Vec<String>,
+    /// How long a login token stays valid, in seconds
+    pub token_lifetime: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        let policy = RegistrationPolicy::default();
+
+        AuthConfig {
+            registration: policy.mode,
+            reserved_usernames: policy.reserved_usernames,
+            token_lifetime: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn registration_policy(&self) -> RegistrationPolicy {
+        RegistrationPolicy {
+            mode: self.registration,
+            reserved_usernames: self.reserved_usernames.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionsConfig {
+    /// How long a session can be resumed after its client disconnected, in seconds
+    pub grace_period: u64,
+    /// How many events of a session are kept to be replayed
+    pub max_events: usize,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        let options = ResumeOptions::default();
+
+        SessionsConfig {
+            grace_period: options.grace_period.as_secs(),
+            max_events: options.max_events,
+        }
+    }
+}
+
+impl SessionsConfig {
+    pub(crate) fn resume_options(&self) -> ResumeOptions {
+        ResumeOptions {
+            grace_period: std::time::Duration::from_secs(self.grace_period),
+            max_events: self.max_events,
+        }
+    }
+}
+
+/// The limits of the packets sent by the clients, see [`DecodeLimits`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_size: usize,
+    pub max_depth: usize,
+    pub max_collection_len: usize,
+    pub max_string_len: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let limits = DecodeLimits::default();
+
+        LimitsConfig {
+            max_size: limits.max_size,
+            max_depth: limits.max_depth,
+            max_collection_len: limits.max_collection_len,
+            max_string_len: limits.max_string_len,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn decode_limits(&self) -> DecodeLimits {
+        DecodeLimits {
+            max_size: self.max_size,
+            max_depth: self.max_depth,
+            max_collection_len: self.max_collection_len,
+            max_string_len: self.max_string_len,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    /// The MLT `melt` executable used to render
+    pub melt: PathBuf,
+    /// How many renders run at the same time, `0` for one per CPU core
+    pub workers: usize,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            melt: PathBuf::from("melt"),
+            workers: 0,
+        }
+    }
+}
+
+/// Used once the server runs in the background, see `dalang start --daemonize`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Holds the PID of the server, relative to the data directory
+    pub pid_file: PathBuf,
+    /// Where the output of the server is written, relative to the data directory
+    pub log_file: PathBuf,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            pid_file: PathBuf::from("dalang.pid"),
+            log_file: PathBuf::from("dalang.log"),
+        }
+    }
+}
+
+impl DaemonConfig {
+    pub fn pid_file_path(&self, storage: &StorageConfig) -> PathBuf {
+        storage.data_dir.join(&self.pid_file)
+    }
+
+    pub fn log_file_path(&self, storage: &StorageConfig) -> PathBuf {
+        storage.data_dir.join(&self.log_file)
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, overridden by the environment variables. A file that
+    /// doesn't exist is an empty configuration, where every key has its default value.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let mut config = Config::from_sources(&read_file(path)?, std::env::vars())?;
+        config.source = Some(path.to_path_buf());
+
+        Ok(config)
+    }
+
+    /// Reads the configuration from the content of a file, overridden by the given environment
+    /// variables. Variables that don't start with [`ENV_PREFIX`], or without a section, are
+    /// ignored.
+    pub fn from_sources(
+        text: &str,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, ConfigError> {
+        let mut document: DocumentMut = text.parse()?;
+
+        for (var, value) in env {
+            if let Some(key) = env_key(&var) {
+                set_in(&mut document, &key, &value).map_err(|err| match err {
+                    ConfigError::UnknownKey(_) => ConfigError::UnknownKey(var),
+                    err => err,
+                })?;
+            }
+        }
+
+        let config: Config = toml::from_str(&document.to_string())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks the values that can't be checked by their type alone
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let limits = [
+            ("limits.max_size", self.limits.max_size),
+            ("limits.max_depth", self.limits.max_depth),
+            ("limits.max_collection_len", self.limits.max_collection_len),
+            ("limits.max_string_len", self.limits.max_string_len),
+        ];
+
+        let invalid = |key, reason| Err(ConfigError::Validation { key, reason });
+
+        if self.server.listen.is_empty() {
+            return invalid("server.listen", "needs at least one address");
+        }
+
+        if !self.server.endpoint.starts_with('/') {
+            return invalid("server.endpoint", "must start with `/`");
+        }
+
+        #[cfg(not(unix))]
+        if !self.server.unix.is_empty() {
+            return invalid("server.unix", "is only supported on unix");
+        }
+
+        if self.auth.token_lifetime == 0 {
+            return invalid("auth.token_lifetime", "must be more than 0");
+        }
+
+        if let Some((key, _)) = limits.iter().find(|(_, limit)| *limit == 0) {
+            return invalid(key, "must be more than 0");
+        }
+
+        Ok(())
+    }
+
+    /// Applies the options of the command line over the configuration, they're kept to apply
+    /// them again over the configuration once it's reloaded
+    pub fn apply_overrides(&mut self, overrides: Overrides) {
+        if let Some(listen) = &overrides.listen {
+            self.server.listen = listen.clone();
+        }
+
+        if let Some(endpoint) = &overrides.endpoint {
+            self.server.endpoint = endpoint.clone();
+        }
+
+        if let Some(static_dir) = &overrides.static_dir {
+            self.server.static_dir = static_dir.clone();
+        }
+
+        if let Some(database) = &overrides.database {
+            self.storage.backend = StorageBackend::Sqlite;
+            self.storage.database = database.clone();
+        } else if overrides.in_memory {
+            self.storage.backend = StorageBackend::Memory;
+        }
+
+        if let Some(tcp) = &overrides.tcp {
+            self.server.tcp = tcp.clone();
+        }
+
+        if let Some(unix) = &overrides.unix {
+            self.server.unix = unix.clone();
+        }
+
+        self.server.json_debug |= overrides.json_debug;
+        self.overrides = overrides;
+    }
+
+    /// Retrieves the value of a key, `None` if it isn't set
+    pub fn get(&self, key: &str) -> Result<Option<toml::Value>, ConfigError> {
+        let (section, field) = split_key(key)?;
+        let config = toml::Value::try_from(self).expect("the configuration is always valid TOML");
+
+        Ok(config.get(section).and_then(|section| section.get(field)).cloned())
+    }
+}
+
+/// Sets a key of a configuration file, the file is only written if the configuration stays
+/// valid. It is created if it doesn't exist, and its formatting and comments are kept.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<(), ConfigError> {
+    let mut document: DocumentMut = read_file(path)?.parse()?;
+    set_in(&mut document, key, value)?;
+
+    let text = document.to_string();
+    toml::from_str::<Config>(&text)?.validate()?;
+
+    // written next to the file first so that it's never left half written
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, text)?;
+    fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<String, ConfigError> {
+    match fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        result => Ok(result?),
+    }
+}
+
+/// `server.listen` -> `("server", "listen")`, the key must be one of [`KEYS`]
+fn split_key(key: &str) -> Result<(&str, &str), ConfigError> {
+    KEYS.iter()
+        .find(|known| **known == key)
+        .and_then(|key| key.split_once('.'))
+        .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))
+}
+
+/// `DALANG_AUTH__TOKEN_LIFETIME` -> `auth.token_lifetime`
+fn env_key(var: &str) -> Option<String> {
+    let key = var.strip_prefix(ENV_PREFIX)?;
+
+    key.contains("__")
+        .then(|| key.to_ascii_lowercase().replace("__", "."))
+}
+
+fn set_in(document: &mut DocumentMut, key: &str, value: &str) -> Result<(), ConfigError> {
+    let (section, field) = split_key(key)?;
+
+    // `0.0.0.0:8080` isn't valid TOML, but is obviously meant as a string
+    let value = value.parse::<toml_edit::Value>().unwrap_or_else(|_| value.into());
+
+    let table = document
+        .entry(section)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or(ConfigError::Validation {
+            key: KEYS.iter().find(|known| known.starts_with(section)).expect("the section is known"),
+            reason: "is in a section that is not a table",
+        })?;
+
+    table.insert(field, toml_edit::Item::Value(value.decorated(" ", "")));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, path::PathBuf};
+
+    use super::{Config, ConfigError, StorageBackend, KEYS};
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(var, value)| (var.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn keys_cover_every_field() {
+        let mut config = Config::default();
+        config.server.static_dir = Some(PathBuf::from("dist"));
+
+        let toml::Value::Table(sections) = toml::Value::try_from(&config).unwrap() else {
+            panic!("the configuration is not a table");
+        };
+
+        let keys: BTreeSet<String> = sections
+            .iter()
+            .flat_map(|(section, fields)| {
+                let fields = fields.as_table().expect("sections are tables");
+                fields.keys().map(move |field| format!("{section}.{field}"))
+            })
+            .collect();
+
+        assert_eq!(keys, KEYS.iter().map(|key| key.to_string()).collect());
+    }
+
+    #[test]
+    fn config_from_file_and_env() {
+        let file = r#"
+            [server]
+            listen = ["0.0.0.0:80", "[::]:80"]
+            static_dir = "dist"
+
+            [storage]
+            backend = "memory"
+        "#;
+
+        let config = Config::from_sources(
+            file,
+            env(&[
+                ("DALANG_SERVER__ENDPOINT", "/ws"),
+                ("DALANG_AUTH__TOKEN_LIFETIME", "3600"),
+                ("DALANG_CONFIG", "ignored.toml"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.server.listen.len(), 2);
+        assert_eq!(config.server.endpoint, "/ws");
+        assert_eq!(config.server.static_dir, Some(PathBuf::from("dist")));
+        assert_eq!(config.storage.backend, StorageBackend::Memory);
+        assert_eq!(config.auth.token_lifetime, 3600);
+        assert_eq!(config.limits, Default::default());
+    }
+
+    #[test]
+    fn config_rejects_invalid_values() {
+        assert!(matches!(
+            Config::from_sources("[server]\nlisten = \"not an address\"", []),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            Config::from_sources("[server]\nport = 80", []),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            Config::from_sources("", env(&[("DALANG_SERVER__PORT", "80")])),
+            Err(ConfigError::UnknownKey(var)) if var == "DALANG_SERVER__PORT"
+        ));
+
+        assert!(matches!(
+            Config::from_sources("[limits]\nmax_size = 0", []),
+            Err(ConfigError::Validation { key: "limits.max_size", .. })
+        ));
+    }
+
+    #[test]
+    fn set_keeps_comments_and_rejects_invalid_values() {
+        let path = std::env::temp_dir().join(format!("dalang-config-{:x}.toml", rand::random::<u64>()));
+        std::fs::write(&path, "# the address of the server\n[server]\nlisten = [\"127.0.0.1:8080\"]\n").unwrap();
+
+        super::set(&path, "server.endpoint", "/ws").unwrap();
+        super::set(&path, "render.workers", "4").unwrap();
+
+        assert!(matches!(super::set(&path, "render.workers", "four"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(super::set(&path, "server.port", "80"), Err(ConfigError::UnknownKey(_))));
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(text.starts_with("# the address of the server\n"));
+
+        let config = Config::from_sources(&text, []).unwrap();
+        assert_eq!(config.server.endpoint, "/ws");
+        assert_eq!(config.render.workers, 4);
+        assert_eq!(config.get("server.endpoint").unwrap(), Some(toml::Value::from("/ws")));
+        assert_eq!(config.get("server.static_dir").unwrap(), None);
+    }
+}
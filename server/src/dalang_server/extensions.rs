@@ -0,0 +1,107 @@
+//! The extensions of the protocol a client accepted, negotiated with
+//! `ClientProtocolPacket::AcceptExtensions` after the protocol version packet.
+
+use dalang_protocol::{json, Compression, ServerPacket, EXTENSIONS};
+use rmp::encode::ValueWriteError;
+
+/// The extensions used by a session, none of them are used until the client accepts them
+#[derive(Debug, Clone, Default)]
+pub struct Extensions {
+    /// Whether the `json-debug` extension is offered to the client, it is off by default
+    pub json_debug_offered: bool,
+    /// How the packets sent to the client are compressed, if it accepted `compression`
+    pub compression: Option<Compression>,
+    /// Whether packets are sent as JSON text frames, if the client accepted `json-debug`
+    pub json: bool,
+}
+
+impl Extensions {
+    pub fn new(json_debug_offered: bool) -> Self {
+        Extensions {
+            json_debug_offered,
+            ..Default::default()
+        }
+    }
+
+    /// The extensions offered to the client with the protocol version packet
+    pub fn offered(&self) -> Vec<&'static str> {
+        let mut offered = EXTENSIONS.to_vec();
+
+        if self.json_debug_offered {
+            offered.push(json::EXTENSION);
+        }
+
+        offered
+    }
+
+    /// Uses the extensions the client accepted, returns the ones that are offered by the
+    /// server. The extensions that were used before and aren't accepted anymore are dropped.
+    pub fn accept(&mut self, accepted: &[String]) -> Vec<String> {
+        let accepted = self
+            .offered()
+            .into_iter()
+            .filter(|extension| accepted.iter().any(|accepted| accepted == *extension))
+            .map(|extension| extension.to_string())
+            .collect::<Vec<_>>();
+
+        self.compression = accepted
+            .iter()
+            .any(|extension| extension == "compression")
+            .then(Compression::default);
+
+        self.json = accepted.iter().any(|extension| extension == json::EXTENSION);
+
+        accepted
+    }
+
+    /// Encodes a packet to be sent to the client as binary, the JSON form is left to the
+    /// sessions that can send text
+    pub fn encode(&self, packet: ServerPacket) -> Result<Vec<u8>, ValueWriteError> {
+        match &self.compression {
+            Some(compression) => packet.encode_compressed(compression),
+            None => packet.try_into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dalang_protocol::{user::ServerUserPacket, ServerPacket};
+
+    use super::Extensions;
+
+    #[test]
+    fn accept_only_supported_extensions() {
+        let mut extensions = Extensions::default();
+
+        let accepted = extensions.accept(&["unknown".to_string(), "compression".to_string()]);
+
+        assert_eq!(accepted, ["compression"]);
+        assert!(extensions.compression.is_some());
+
+        assert!(extensions.accept(&[]).is_empty());
+        assert!(extensions.compression.is_none());
+    }
+
+    #[test]
+    fn json_debug_only_when_offered() {
+        let requested = ["json-debug".to_string()];
+
+        let mut extensions = Extensions::default();
+        assert!(extensions.accept(&requested).is_empty());
+        assert!(!extensions.json);
+
+        let mut extensions = Extensions::new(true);
+        assert_eq!(extensions.offered(), ["compression", "json-debug"]);
+        assert_eq!(extensions.accept(&requested), ["json-debug"]);
+        assert!(extensions.json);
+    }
+
+    #[test]
+    fn encode_without_extensions() {
+        let packet = ServerPacket::User(ServerUserPacket::ProjectsTotalResp { total: 3 });
+        let expected: Vec<u8> = packet.clone().try_into().unwrap();
+
+        assert_eq!(Extensions::default().encode(packet).unwrap(), expected);
+    }
+}
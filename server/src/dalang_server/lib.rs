@@ -1,15 +1,25 @@
-use std::{path::PathBuf, collections::HashMap, net::ToSocketAddrs};
+use std::{path::PathBuf, collections::HashMap, net::SocketAddr, sync::{Arc, RwLock}};
 
 use actix::{Actor, Addr};
 use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer, middleware};
 use actix_web_actors::ws;
+use dalang_protocol::DecodeLimits;
 use server::DalangServer;
 
 mod auth;
+mod client;
+mod extensions;
 mod session;
+mod sessions;
+#[cfg(unix)]
+mod signals;
 mod storage;
+mod stream_session;
 
 pub mod components;
+pub mod config;
+pub mod migrations;
+pub mod registration;
 
 async fn ws_endpoint<AuthActor: auth::Authenticator>(
     req: HttpRequest,
@@ -19,91 +29,186 @@ async fn ws_endpoint<AuthActor: auth::Authenticator>(
         return HttpResponse::InternalServerError().await;
     };
 
+    let settings = *server.settings.read().expect("the settings lock is poisoned");
+
     // todo: share the ThreadRng for a bit better performance. But it's a bit tricky since
     //       ThreadRng is !Send and !Sync. Interior mutability might work but that might lead
     //       to some messy code.
     ws::WsResponseBuilder::new(
         session::Session {
             id: rand::random(),
-            server: server.server.clone()
+            server: server.server.clone(),
+            decode_limits: settings.decode_limits,
+            extensions: extensions::Extensions::new(settings.json_debug),
+            token: None,
+            state: sessions::SessionState::default(),
         },
         &req, stream
-    ).protocols(&["dalang"]).start()
+    )
+        .protocols(&["dalang"])
+        // frames larger than a packet could be are rejected before they're buffered
+        .frame_size(settings.decode_limits.max_size)
+        .start()
 }
 
 struct ServerState<AuthActor: auth::Authenticator> {
     server: Addr<DalangServer<AuthActor>>,
+    settings: SharedSettings,
+}
+
+/// The settings that take effect without a restart when the configuration is reloaded, they're
+/// read as connections are accepted
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Settings {
+    pub decode_limits: DecodeLimits,
+    pub json_debug: bool,
+}
+
+impl Settings {
+    fn from_config(config: &config::Config) -> Self {
+        Settings {
+            decode_limits: config.limits.decode_limits(),
+            json_debug: config.server.json_debug,
+        }
+    }
+}
+
+pub(crate) type SharedSettings = Arc<RwLock<Settings>>;
+
+/// A listener of raw connections, where packets are framed by the length-prefixed codec of
+/// `dalang_protocol::codec` instead of websocket messages.
+#[derive(Debug, Clone)]
+pub enum RawListener {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
 }
 
 /// Starts the dalang server.
 /// 
 /// # Arguments
 /// 
-/// * `endpoint` - An endpoint where the websocket server will be run. Will use `/` if not specified.
-/// * `serve_static` - Tell the library to serve static files in this directory. Will not serve any static files if not specified.
-/// * `create_auth` - The function to construct an Authenticator of the given `AuthActor` type parameter.
-pub async fn start<AuthActor, CreateAuthFn, S: ToSocketAddrs>(
-    endpoint: Option<String>,
-    serve_static: Option<PathBuf>,
+/// * `config` - The configuration of the server, see [`config::Config`].
+/// * `create_auth` - The function to construct an Authenticator of the given `AuthActor` type parameter, from the configuration.
+///
+/// On unix, SIGTERM and SIGINT stop the server gracefully, and SIGHUP loads the configuration
+/// again from [`config::Config::source`].
+pub async fn start<AuthActor, CreateAuthFn>(
+    config: config::Config,
     create_auth: CreateAuthFn,
-    addr: S
 ) -> std::io::Result<()>
 
 where
     AuthActor: auth::Authenticator,
-    CreateAuthFn: FnOnce() -> AuthActor
+    CreateAuthFn: FnOnce(&config::Config) -> AuthActor
 {
-    let authenticator = create_auth();
+    let authenticator = create_auth(&config);
     let auth_addr = authenticator.start();
 
     let server =
         DalangServer::<AuthActor> {
             authenticator: auth_addr,
             storages: HashMap::new(),
+            sessions: sessions::Sessions::new(config.sessions.resume_options()),
+            registration: config.auth.registration_policy(),
         };
 
     let server_addr = server.start();
+    let settings = Arc::new(RwLock::new(Settings::from_config(&config)));
 
-    let server =
-        web::Data::new(ServerState::<AuthActor> { server: server_addr });
+    let raw_listeners = config.server.raw_listeners();
 
-    HttpServer::new(move || {
+    for listener in raw_listeners.iter().cloned() {
+        stream_session::listen(listener, server_addr.clone(), settings.clone()).await?;
+    }
+
+    let state = web::Data::new(ServerState::<AuthActor> {
+        server: server_addr.clone(),
+        settings: settings.clone(),
+    });
+
+    let endpoint = config.server.endpoint.clone();
+    let serve_static = config.server.static_dir.clone();
+
+    #[allow(unused_mut)] // signals are only handled by us on unix
+    let mut http = HttpServer::new(move || {
         let mut app = App::new()
-            .app_data(server.clone())
+            .app_data(state.clone())
             .wrap(middleware::Logger::default())
-            .route(
-                endpoint.clone().unwrap_or("/".to_string()).as_str(),
-                web::get().to(ws_endpoint::<AuthActor>)
-            );
+            .route(endpoint.as_str(), web::get().to(ws_endpoint::<AuthActor>));
 
         if let Some(static_files) = &serve_static {
             app = app.service(actix_files::Files::new("/", static_files));
         }
 
         app
-    })
-    .bind(addr)?
-    .run()
-    .await
+    });
+
+    // the clients are told that the server is stopping before it stops
+    #[cfg(unix)]
+    {
+        http = http.disable_signals();
+    }
+
+    let http = http.bind(&config.server.listen[..])?.run();
+
+    #[cfg(unix)]
+    actix::spawn(signals::handle(config, http.handle(), server_addr, settings));
+
+    #[cfg(not(unix))]
+    let _ = (config, server_addr, settings);
+
+    let result = http.await;
+
+    // the files of the unix sockets stay otherwise
+    #[cfg(unix)]
+    for listener in &raw_listeners {
+        if let RawListener::Unix(path) = listener {
+            stream_session::remove_socket(path);
+        }
+    }
+
+    result
 }
 
 mod server {
-    use std::{collections::HashMap, marker::PhantomData};
+    use std::{collections::HashMap, marker::PhantomData, time::{Duration, Instant}};
+
+    use actix::{Addr, Actor, AsyncContext, Context, MailboxError, Message, MessageResult, Handler, Recipient, ResponseFuture};
+    use dalang_protocol::ServerPacket;
 
-    use actix::{Addr, Actor, Context, Message, Handler};
+    use dalang_protocol::protocol::ServerProtocolPacket;
 
-    use crate::auth::Authenticator;
+    use crate::{
+        auth::{messages as auth_msg, AuthError, Authenticator, Role, User},
+        registration::{RegistrationError, RegistrationMode, RegistrationPolicy},
+        session::messages::Event,
+        sessions::{ResumeError, ResumeOptions, SessionState, Sessions},
+    };
 
     use super::storage::Storage;
 
-    #[derive(Debug, Clone)]
+    /// How often the sessions whose grace period ended are forgotten
+    const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+    #[derive(Debug)]
     pub struct DalangServer<AuthActor: Authenticator> {
         pub authenticator: Addr<AuthActor>,
+        #[allow(dead_code)] // todo: storages are not implemented yet
         pub storages: HashMap<u64, Addr<Storage>>,
+        /// The sessions of the clients, the websocket ones are kept for a while after they
+        /// disconnect
+        pub sessions: Sessions,
+        /// Who may register through the `Register` packet
+        pub registration: RegistrationPolicy,
     }
 
     impl<A: Authenticator> Actor for DalangServer<A> {
         type Context = Context<Self>;
+
+        fn started(&mut self, ctx: &mut Self::Context) {
+            ctx.run_interval(EXPIRE_INTERVAL, |server, _ctx| server.sessions.expire(Instant::now()));
+        }
     }
 
     #[derive(Debug)]
@@ -120,4 +225,328 @@ mod server {
             self.authenticator.clone()
         }
     }
-}
\ No newline at end of file
+
+    /// Registers the session of a new connection, results in the token to resume it
+    #[derive(Debug)]
+    pub struct Register(pub Recipient<Event>);
+
+    impl Message for Register {
+        type Result = String;
+    }
+
+    impl<A: Authenticator> Handler<Register> for DalangServer<A> {
+        type Result = String;
+
+        fn handle(&mut self, Register(recipient): Register, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.register(recipient)
+        }
+    }
+
+    /// Pushes an event to a session, events are pushed through the server so they're replayed
+    /// when the session is resumed. Results in `false` if there's no such session, or if the
+    /// packet isn't an event.
+    #[derive(Debug)]
+    pub struct PushEvent {
+        pub token: String,
+        pub packet: ServerPacket,
+    }
+
+    impl Message for PushEvent {
+        type Result = bool;
+    }
+
+    impl<A: Authenticator> Handler<PushEvent> for DalangServer<A> {
+        type Result = bool;
+
+        fn handle(&mut self, msg: PushEvent, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.push(&msg.token, msg.packet)
+        }
+    }
+
+    /// The client of a session disconnected, its state is kept until the session expires
+    #[derive(Debug)]
+    pub struct Disconnect {
+        pub token: String,
+        pub state: SessionState,
+    }
+
+    impl Message for Disconnect {
+        type Result = ();
+    }
+
+    impl<A: Authenticator> Handler<Disconnect> for DalangServer<A> {
+        type Result = ();
+
+        fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.disconnect(&msg.token, msg.state, Instant::now());
+        }
+    }
+
+    /// Hands a disconnected session to a new connection, results in its state and the events
+    /// the client missed
+    #[derive(Debug)]
+    pub struct Resume {
+        pub token: String,
+        pub last_event: u64,
+        pub recipient: Recipient<Event>,
+    }
+
+    impl Message for Resume {
+        type Result = Result<(SessionState, Vec<ServerPacket>), ResumeError>;
+    }
+
+    impl<A: Authenticator> Handler<Resume> for DalangServer<A> {
+        type Result = Result<(SessionState, Vec<ServerPacket>), ResumeError>;
+
+        fn handle(&mut self, msg: Resume, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.resume(&msg.token, msg.last_event, msg.recipient, Instant::now())
+        }
+    }
+
+    /// Forgets a session, such as the one a connection started with before resuming another
+    #[derive(Debug)]
+    pub struct Forget(pub String);
+
+    impl Message for Forget {
+        type Result = ();
+    }
+
+    impl<A: Authenticator> Handler<Forget> for DalangServer<A> {
+        type Result = ();
+
+        fn handle(&mut self, Forget(token): Forget, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.remove(&token);
+        }
+    }
+
+    /// Changes how long sessions are kept and how many of their events, once the configuration
+    /// is reloaded
+    #[derive(Debug)]
+    pub struct SetResumeOptions(pub ResumeOptions);
+
+    impl Message for SetResumeOptions {
+        type Result = ();
+    }
+
+    impl<A: Authenticator> Handler<SetResumeOptions> for DalangServer<A> {
+        type Result = ();
+
+        fn handle(&mut self, SetResumeOptions(options): SetResumeOptions, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.options = options;
+        }
+    }
+
+    /// Tells every connected client that its session is terminating, they are disconnected
+    /// once they're told
+    #[derive(Debug)]
+    pub struct Shutdown {
+        pub reason: String,
+    }
+
+    impl Message for Shutdown {
+        type Result = ();
+    }
+
+    impl<A: Authenticator> Handler<Shutdown> for DalangServer<A> {
+        type Result = ();
+
+        fn handle(&mut self, msg: Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+            self.sessions.broadcast(ServerPacket::Protocol(ServerProtocolPacket::SessionTerminating {
+                reason: msg.reason,
+            }));
+        }
+    }
+
+    /// Registers a user if the registration policy allows it, results in its uid
+    #[derive(Debug)]
+    pub struct RegisterUser {
+        pub username: String,
+        pub password: String,
+        pub invite: Option<String>,
+    }
+
+    impl Message for RegisterUser {
+        type Result = Result<u64, RegistrationError>;
+    }
+
+    impl<A: Authenticator> Handler<RegisterUser> for DalangServer<A> {
+        type Result = ResponseFuture<Result<u64, RegistrationError>>;
+
+        fn handle(&mut self, msg: RegisterUser, _ctx: &mut Self::Context) -> Self::Result {
+            let allowed = match self.registration.mode {
+                RegistrationMode::Closed => Err(RegistrationError::Disabled),
+                RegistrationMode::Invite if msg.invite.is_none() => Err(RegistrationError::InvalidInvite),
+                _ => self.registration.check_username(&msg.username).map_err(RegistrationError::InvalidUsername),
+            };
+
+            let register = auth_msg::Register {
+                username: msg.username,
+                password: msg.password,
+                role: Role::User,
+                // an open registration doesn't use up the codes
+                invite: msg.invite.filter(|_| self.registration.mode == RegistrationMode::Invite),
+            };
+
+            let authenticator = self.authenticator.clone();
+
+            Box::pin(async move {
+                allowed?;
+
+                match authenticator.send(register).await {
+                    Ok(result) => result.map_err(Into::into),
+                    Err(_) => Err(RegistrationError::Failed),
+                }
+            })
+        }
+    }
+
+    /// Results in who may register
+    #[derive(Debug)]
+    pub struct GetRegistrationMode;
+
+    impl Message for GetRegistrationMode {
+        type Result = RegistrationMode;
+    }
+
+    impl<A: Authenticator> Handler<GetRegistrationMode> for DalangServer<A> {
+        type Result = MessageResult<GetRegistrationMode>;
+
+        fn handle(&mut self, _msg: GetRegistrationMode, _ctx: &mut Self::Context) -> Self::Result {
+            MessageResult(self.registration.mode)
+        }
+    }
+
+    /// Checks whether a username can be registered. Whether a user has it isn't told while the
+    /// registration is closed.
+    #[derive(Debug)]
+    pub struct CheckUsername {
+        pub username: String,
+    }
+
+    impl Message for CheckUsername {
+        type Result = Result<(), RegistrationError>;
+    }
+
+    impl<A: Authenticator> Handler<CheckUsername> for DalangServer<A> {
+        type Result = ResponseFuture<Result<(), RegistrationError>>;
+
+        fn handle(&mut self, msg: CheckUsername, _ctx: &mut Self::Context) -> Self::Result {
+            let allowed = match self.registration.mode {
+                RegistrationMode::Closed => Err(RegistrationError::Disabled),
+                _ => self.registration.check_username(&msg.username).map_err(RegistrationError::InvalidUsername),
+            };
+
+            let authenticator = self.authenticator.clone();
+
+            Box::pin(async move {
+                allowed?;
+
+                match authenticator.send(auth_msg::UsernameExists { username: msg.username }).await {
+                    Ok(Ok(false)) => Ok(()),
+                    Ok(Ok(true)) => Err(RegistrationError::UsernameTaken),
+                    _ => Err(RegistrationError::Failed),
+                }
+            })
+        }
+    }
+
+    /// Logs a user in with its password, results in the user
+    #[derive(Debug)]
+    pub struct LoginUser {
+        pub username: String,
+        pub password: String,
+    }
+
+    impl Message for LoginUser {
+        type Result = Result<User, AuthError>;
+    }
+
+    impl<A: Authenticator> Handler<LoginUser> for DalangServer<A> {
+        type Result = ResponseFuture<Result<User, AuthError>>;
+
+        fn handle(&mut self, msg: LoginUser, _ctx: &mut Self::Context) -> Self::Result {
+            let login = auth_msg::Login { username: msg.username, password: msg.password };
+            let authenticator = self.authenticator.clone();
+
+            Box::pin(async move {
+                let uid = authenticator.send(login).await.map_err(unreachable_authenticator)??;
+
+                authenticator.send(auth_msg::GetUser { uid }).await.map_err(unreachable_authenticator)?
+            })
+        }
+    }
+
+    /// Results in the user with its profile
+    #[derive(Debug)]
+    pub struct GetProfile {
+        pub uid: u64,
+    }
+
+    impl Message for GetProfile {
+        type Result = Result<User, AuthError>;
+    }
+
+    impl<A: Authenticator> Handler<GetProfile> for DalangServer<A> {
+        type Result = ResponseFuture<Result<User, AuthError>>;
+
+        fn handle(&mut self, msg: GetProfile, _ctx: &mut Self::Context) -> Self::Result {
+            let authenticator = self.authenticator.clone();
+
+            Box::pin(async move {
+                authenticator.send(auth_msg::GetUser { uid: msg.uid }).await.map_err(unreachable_authenticator)?
+            })
+        }
+    }
+
+    /// Replaces the profile of a user, results in the updated user
+    #[derive(Debug)]
+    pub struct UpdateProfile {
+        pub uid: u64,
+        pub display_name: Option<String>,
+        pub email: Option<String>,
+        pub avatar: Option<u64>,
+    }
+
+    impl Message for UpdateProfile {
+        type Result = Result<User, AuthError>;
+    }
+
+    impl<A: Authenticator> Handler<UpdateProfile> for DalangServer<A> {
+        type Result = ResponseFuture<Result<User, AuthError>>;
+
+        fn handle(&mut self, msg: UpdateProfile, _ctx: &mut Self::Context) -> Self::Result {
+            let update = auth_msg::UpdateProfile {
+                uid: msg.uid,
+                display_name: msg.display_name,
+                email: msg.email,
+                avatar: msg.avatar,
+            };
+
+            let authenticator = self.authenticator.clone();
+
+            Box::pin(async move {
+                authenticator.send(update).await.map_err(unreachable_authenticator)?
+            })
+        }
+    }
+
+    fn unreachable_authenticator(err: MailboxError) -> AuthError {
+        AuthError::Backend(format!("failed to reach the authenticator: {err}"))
+    }
+
+    /// Changes who may register, once the configuration is reloaded
+    #[derive(Debug)]
+    pub struct SetRegistration(pub RegistrationPolicy);
+
+    impl Message for SetRegistration {
+        type Result = ();
+    }
+
+    impl<A: Authenticator> Handler<SetRegistration> for DalangServer<A> {
+        type Result = ();
+
+        fn handle(&mut self, SetRegistration(policy): SetRegistration, _ctx: &mut Self::Context) -> Self::Result {
+            self.registration = policy;
+        }
+    }
+}
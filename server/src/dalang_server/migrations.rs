@@ -0,0 +1,380 @@
+//! The versions of the schema of the server database. Each migration moves a database from the
+//! version before it to its own, and is applied once, in order: the versions that were applied
+//! are recorded in the `schema_version` table. Databases are upgraded when they're opened, after
+//! a copy of them is made next to them.
+//!
+//! Migrations are never changed once released, the schema changes with a new one at the end of
+//! [`MIGRATIONS`]. The first ones may run on databases that predate this table, so they're
+//! written to be a no-op on the tables those databases already have.
+
+use std::{fmt, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use r2d2_sqlite::rusqlite::{self, params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create the users",
+        up: |tx| tx.execute_batch(include_str!("migrations/0001_users.sql")),
+    },
+    // not a script: SQLite can't add a column only if it doesn't exist, and the databases that
+    // predate the migrations may have these columns already
+    Migration {
+        version: 2,
+        description: "add the roles of the users and let them be disabled",
+        up: |tx| add_missing_columns(tx, "users", USERS_ADDED_COLUMNS),
+    },
+    Migration {
+        version: 3,
+        description: "create the invite codes",
+        up: |tx| tx.execute_batch(include_str!("migrations/0003_invites.sql")),
+    },
+    Migration {
+        version: 4,
+        description: "add the profiles of the users",
+        up: |tx| tx.execute_batch(include_str!("migrations/0004_user_profiles.sql")),
+    },
+    Migration {
+        version: 5,
+        description: "make the usernames unique regardless of their case",
+        up: |tx| {
+            check_unique_usernames(tx)?;
+            tx.execute_batch(include_str!("migrations/0005_unique_usernames.sql"))
+        },
+    },
+];
+
+/// The version of the schema this server uses
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// The columns of `users` added by the version 2, databases created before the migrations
+/// may already have them
+const USERS_ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("role", r#""role" TEXT NOT NULL DEFAULT 'user'"#),
+    ("disabled", r#""disabled" INTEGER NOT NULL DEFAULT 0"#),
+];
+
+fn add_missing_columns(conn: &Connection, table: &str, added: &[(&str, &str)]) -> rusqlite::Result<()> {
+    let columns = conn
+        .prepare(&format!(r#"PRAGMA table_info("{table}");"#))?
+        .query_map([], |row| row.get::<_, String>("name"))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (name, definition) in added {
+        if !columns.iter().any(|column| column == name) {
+            conn.execute(&format!(r#"ALTER TABLE "{table}" ADD COLUMN {definition};"#), [])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails if usernames only differ by their case, older versions let them be registered. The
+/// database isn't upgraded until an admin renames or removes all but one of each, the users
+/// would log in as one another otherwise.
+fn check_unique_usernames(conn: &Connection) -> rusqlite::Result<()> {
+    let duplicates = conn
+        .prepare(QUERY_USERNAME_DUPLICATES)?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE),
+        Some(format!(
+            "usernames are unique regardless of their case, but these are taken by more than one user: {}. \
+            Rename or remove all but one of each in the `users` table",
+            duplicates.join("; ")
+        )),
+    ))
+}
+
+const QUERY_USERNAME_DUPLICATES: &str =
+    r#"SELECT group_concat(username || ' (uid ' || uid || ')', ', ') FROM users GROUP BY username COLLATE NOCASE HAVING count(*) > 1;"#;
+
+const QUERY_SCHEMA_VERSION_CREATE: &str = r#"
+CREATE TABLE IF NOT EXISTS "schema_version" (
+	"version"	INTEGER NOT NULL UNIQUE,
+	"description"	TEXT NOT NULL,
+	"applied_at"	INTEGER NOT NULL,
+	PRIMARY KEY("version")
+)"#;
+
+const QUERY_SCHEMA_VERSION_EXISTS: &str =
+    r#"SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version';"#;
+const QUERY_VERSION_GET: &str = r#"SELECT max(version) FROM schema_version;"#;
+const QUERY_VERSIONS_LIST: &str = r#"SELECT version, description, applied_at FROM schema_version ORDER BY version;"#;
+const QUERY_VERSION_INSERT: &str =
+    r#"INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3);"#;
+const QUERY_TABLES_COUNT: &str = r#"SELECT count(*) FROM sqlite_master WHERE type = 'table';"#;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was upgraded by a newer server, this one doesn't know its schema
+    Newer { version: u32, latest: u32 },
+    /// The copy of the database couldn't be made, it wasn't upgraded
+    Backup { path: PathBuf, err: rusqlite::Error },
+    /// A migration failed, the database stays at the version before it
+    Failed { version: u32, err: rusqlite::Error },
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Newer { version, latest } => write!(
+                f,
+                "the database is at the version {version} of the schema, this server only knows up to the version {latest}"
+            ),
+            MigrationError::Backup { path, err } => write!(f, "failed to back up the database to {}: {err}", path.display()),
+            MigrationError::Failed { version, err } => write!(f, "failed to migrate the database to the version {version}: {err}"),
+            MigrationError::Sqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(value: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(value)
+    }
+}
+
+/// A version of the schema that was applied to a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub description: String,
+    /// In seconds since the unix epoch
+    pub applied_at: u64,
+}
+
+/// What [`migrate`] did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migrated {
+    pub from: u32,
+    pub to: u32,
+    /// Where the database was copied before it was upgraded
+    pub backup: Option<PathBuf>,
+}
+
+/// The version of the schema of a database, 0 if it predates the migrations or is empty
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    if conn.query_row(QUERY_SCHEMA_VERSION_EXISTS, [], |_| Ok(())).optional()?.is_none() {
+        return Ok(0);
+    }
+
+    Ok(conn.query_row(QUERY_VERSION_GET, [], |row| row.get::<_, Option<u32>>(0))?.unwrap_or(0))
+}
+
+/// The versions that were applied to a database, in order
+pub fn applied(conn: &Connection) -> rusqlite::Result<Vec<AppliedMigration>> {
+    if conn.query_row(QUERY_SCHEMA_VERSION_EXISTS, [], |_| Ok(())).optional()?.is_none() {
+        return Ok(Vec::new());
+    }
+
+    conn.prepare(QUERY_VERSIONS_LIST)?
+        .query_map([], |row| Ok(AppliedMigration {
+            version: row.get(0)?,
+            description: row.get(1)?,
+            applied_at: row.get(2)?,
+        }))?
+        .collect()
+}
+
+/// The migrations a database at `version` is missing
+pub fn pending(version: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |migration| migration.version > version)
+}
+
+/// Upgrades a database to the latest version. Unless it's empty, a database stored in
+/// `db_file` is copied next to it beforehand. Each migration is applied in a transaction of its
+/// own, along with its version: a database that another server is upgrading at the same time
+/// skips the versions it applied.
+pub fn migrate(conn: &mut Connection, db_file: Option<&Path>) -> Result<Migrated, MigrationError> {
+    let from = current_version(conn)?;
+    let latest = latest_version();
+
+    if from > latest {
+        return Err(MigrationError::Newer { version: from, latest });
+    }
+
+    if from == latest {
+        return Ok(Migrated { from, to: from, backup: None });
+    }
+
+    // there's nothing to lose in a database without tables
+    let tables = conn.query_row(QUERY_TABLES_COUNT, [], |row| row.get::<_, u32>(0))?;
+
+    let backup = match db_file {
+        Some(db_file) if tables > 0 => Some(backup(conn, db_file, from)?),
+        _ => None,
+    };
+
+    conn.execute(QUERY_SCHEMA_VERSION_CREATE, [])?;
+
+    for migration in pending(from) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if current_version(&tx)? >= migration.version {
+            continue;
+        }
+
+        (migration.up)(&tx)
+            .and_then(|()| tx.execute(QUERY_VERSION_INSERT, params![migration.version, migration.description, unix_time()]))
+            .and_then(|_| tx.commit())
+            .map_err(|err| MigrationError::Failed { version: migration.version, err })?;
+    }
+
+    Ok(Migrated { from, to: current_version(conn)?, backup })
+}
+
+/// Copies the database to `<file>.v<version>-<unix time>.bak`, next to it
+fn backup(conn: &Connection, db_file: &Path, version: u32) -> Result<PathBuf, MigrationError> {
+    let mut name = db_file.file_name().unwrap_or(db_file.as_os_str()).to_os_string();
+    name.push(format!(".v{version}-{}.bak", unix_time()));
+
+    let path = db_file.with_file_name(name);
+
+    conn.execute(r#"VACUUM INTO ?1;"#, params![path.to_string_lossy()])
+        .map_err(|err| MigrationError::Backup { path: path.clone(), err })?;
+
+    Ok(path)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use r2d2_sqlite::rusqlite::{params, Connection};
+
+    use super::{applied, current_version, latest_version, migrate, MigrationError, Migrated, MIGRATIONS};
+
+    #[test]
+    fn migrations_are_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version + 1 == pair[1].version));
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[test]
+    fn migrates_an_empty_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(
+            migrate(&mut conn, None).unwrap(),
+            Migrated { from: 0, to: latest_version(), backup: None }
+        );
+
+        assert_eq!(applied(&conn).unwrap().len(), MIGRATIONS.len());
+
+        // and it's a no-op afterwards
+        assert_eq!(
+            migrate(&mut conn, None).unwrap(),
+            Migrated { from: latest_version(), to: latest_version(), backup: None }
+        );
+    }
+
+    #[test]
+    fn migrates_a_database_older_than_the_migrations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        // the table as created before users had a role
+        conn.execute(r#"CREATE TABLE "users" (
+            "uid" sqlite3_uint64 NOT NULL UNIQUE,
+            "username" TEXT NOT NULL,
+            "password" TEXT NOT NULL,
+            PRIMARY KEY("uid")
+        )"#, []).unwrap();
+
+        conn.execute(r#"INSERT INTO users (uid, username, password) VALUES (1, 'lorem', '');"#, []).unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let (role, disabled, created_at) = conn.query_row(
+            r#"SELECT role, disabled, created_at FROM users WHERE uid = 1;"#,
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?, row.get::<_, u64>(2)?))
+        ).unwrap();
+
+        assert_eq!((role.as_str(), disabled), ("user", false));
+        // counted as created when the database was upgraded
+        assert!(created_at > 0);
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn refuses_a_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+
+        conn.execute(
+            r#"INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'from the future', 0);"#,
+            [latest_version() + 1]
+        ).unwrap();
+
+        assert!(matches!(migrate(&mut conn, None), Err(MigrationError::Newer { .. })));
+    }
+
+    #[test]
+    fn refuses_colliding_usernames() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        conn.execute_batch(include_str!("migrations/0001_users.sql")).unwrap();
+
+        for (uid, username) in [(1, "lorem"), (2, "Lorem"), (3, "LOREM"), (4, "ipsum")] {
+            conn.execute(r#"INSERT INTO users (uid, username, password) VALUES (?1, ?2, '');"#, params![uid, username]).unwrap();
+        }
+
+        let Err(MigrationError::Failed { version: 5, err }) = migrate(&mut conn, None) else {
+            panic!("the colliding usernames were accepted");
+        };
+
+        let err = err.to_string();
+        assert!(err.contains("lorem (uid 1), Lorem (uid 2), LOREM (uid 3)"), "{err}");
+        assert!(!err.contains("ipsum"), "{err}");
+        assert_eq!(current_version(&conn).unwrap(), 4);
+
+        // upgraded once an admin removed them
+        conn.execute(r#"DELETE FROM users WHERE uid IN (2, 3);"#, []).unwrap();
+        migrate(&mut conn, None).unwrap();
+
+        let insert = conn.execute(r#"INSERT INTO users (uid, username, password) VALUES (5, 'LOREM', '');"#, []);
+        assert!(insert.is_err());
+    }
+
+    #[test]
+    fn backs_up_before_migrating() {
+        let path = std::env::temp_dir().join(format!("dalang-migrations-{:x}.db", rand::random::<u64>()));
+        let mut conn = Connection::open(&path).unwrap();
+
+        conn.execute_batch(include_str!("migrations/0001_users.sql")).unwrap();
+        conn.execute(r#"INSERT INTO users (uid, username, password) VALUES (1, 'lorem', '');"#, []).unwrap();
+
+        let migrated = migrate(&mut conn, Some(&path)).unwrap();
+        let backup = migrated.backup.expect("the database wasn't backed up");
+
+        let copy = Connection::open(&backup).unwrap();
+        let copied = copy.query_row(r#"SELECT username FROM users WHERE uid = 1;"#, [], |row| row.get::<_, String>(0));
+        let copy_version = current_version(&copy);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+
+        assert_eq!(copied.unwrap(), "lorem");
+        assert_eq!(copy_version.unwrap(), 0);
+    }
+}
@@ -0,0 +1,7 @@
+-- the users, along with the bcrypt hash of their password
+CREATE TABLE IF NOT EXISTS "users" (
+	"uid"	sqlite3_uint64 NOT NULL UNIQUE,
+	"username"	TEXT NOT NULL,
+	"password"	TEXT NOT NULL,
+	PRIMARY KEY("uid")
+);
@@ -0,0 +1,8 @@
+-- single-use invite codes, `used_by` is the uid of the user who registered with it
+CREATE TABLE IF NOT EXISTS "invites" (
+	"code"	TEXT NOT NULL UNIQUE,
+	"created_at"	INTEGER NOT NULL,
+	"used_by"	sqlite3_uint64,
+	"used_at"	INTEGER,
+	PRIMARY KEY("code")
+);
@@ -0,0 +1,8 @@
+-- the profiles of the users, the ones that already exist count as created now
+ALTER TABLE "users" ADD COLUMN "display_name" TEXT;
+ALTER TABLE "users" ADD COLUMN "email" TEXT;
+ALTER TABLE "users" ADD COLUMN "avatar" sqlite3_uint64;
+ALTER TABLE "users" ADD COLUMN "created_at" INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE "users" ADD COLUMN "last_login_at" INTEGER;
+
+UPDATE "users" SET "created_at" = CAST(strftime('%s', 'now') AS INTEGER);
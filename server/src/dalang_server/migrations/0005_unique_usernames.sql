@@ -0,0 +1,2 @@
+-- usernames are unique regardless of their case, the users that collide are checked beforehand
+CREATE UNIQUE INDEX IF NOT EXISTS "users_username" ON "users" ("username" COLLATE NOCASE);
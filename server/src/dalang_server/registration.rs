@@ -0,0 +1,160 @@
+//! Who may register an account through the `Register` packet, and which usernames they may
+//! take. Accounts created with `dalang user add` only follow the rules of the usernames, admins
+//! may take the reserved ones.
+
+use std::fmt;
+
+use dalang_protocol::authentication::ServerAuthenticationPacket;
+use serde::{Deserialize, Serialize};
+
+use crate::auth;
+
+/// The usernames that can't be registered if no others are configured, they could be mistaken
+/// for the server or its staff
+pub const DEFAULT_RESERVED_USERNAMES: &[&str] =
+    &["admin", "administrator", "dalang", "moderator", "root", "server", "staff", "support", "system"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Anyone may register
+    #[default]
+    Open,
+    /// Accounts are only created by admins
+    Closed,
+    /// Registering needs a single-use invite code, created by admins with `dalang user invite`
+    Invite,
+}
+
+impl RegistrationMode {
+    /// The answer to `RegisterCheckEnabled`
+    pub(crate) fn response(&self) -> ServerAuthenticationPacket {
+        match self {
+            RegistrationMode::Open => ServerAuthenticationPacket::SuccessResp,
+            RegistrationMode::Closed => ServerAuthenticationPacket::RegisterFailedFeatureDisabled,
+            RegistrationMode::Invite => ServerAuthenticationPacket::RegisterInviteOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    /// Compared regardless of their case
+    pub reserved_usernames: Vec<String>,
+}
+
+impl Default for RegistrationPolicy {
+    fn default() -> Self {
+        RegistrationPolicy {
+            mode: RegistrationMode::default(),
+            reserved_usernames: DEFAULT_RESERVED_USERNAMES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl RegistrationPolicy {
+    /// Checks that a username follows the rules and isn't reserved, it may still be taken
+    pub fn check_username(&self, username: &str) -> Result<(), UsernameError> {
+        validate_username(username)?;
+
+        if self.reserved_usernames.iter().any(|reserved| reserved.eq_ignore_ascii_case(username)) {
+            return Err(UsernameError::Reserved);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsernameError {
+    /// Not between 3 and 32 characters
+    Length,
+    /// Not only letters, digits, `_`, `.` and `-`
+    Characters,
+    Reserved,
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::Length => f.write_str("a username has between 3 and 32 characters"),
+            UsernameError::Characters => f.write_str("a username only has letters, digits, `_`, `.` and `-`"),
+            UsernameError::Reserved => f.write_str("the username is reserved"),
+        }
+    }
+}
+
+/// The rules of the usernames, the same as the ones the `Register` packet is validated with.
+/// Usernames are unique regardless of their case.
+pub fn validate_username(username: &str) -> Result<(), UsernameError> {
+    if !(3..=32).contains(&username.len()) {
+        return Err(UsernameError::Length);
+    }
+
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')) {
+        return Err(UsernameError::Characters);
+    }
+
+    Ok(())
+}
+
+/// Why a client can't register, or can't take a username
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationError {
+    /// The registration is closed
+    Disabled,
+    /// The invite code is missing, unknown or was already used
+    InvalidInvite,
+    InvalidUsername(UsernameError),
+    UsernameTaken,
+    /// The authenticator failed, or couldn't be reached
+    Failed,
+}
+
+impl From<auth::AuthError> for RegistrationError {
+    fn from(err: auth::AuthError) -> Self {
+        match err {
+            auth::AuthError::UsernameTaken => RegistrationError::UsernameTaken,
+            auth::AuthError::InvalidInvite => RegistrationError::InvalidInvite,
+            _ => RegistrationError::Failed,
+        }
+    }
+}
+
+impl RegistrationError {
+    /// The packet the client is answered with
+    pub(crate) fn response(&self) -> ServerAuthenticationPacket {
+        match self {
+            RegistrationError::Disabled => ServerAuthenticationPacket::RegisterFailedFeatureDisabled,
+            RegistrationError::InvalidInvite => ServerAuthenticationPacket::RegisterFailedInvalidInvite,
+            RegistrationError::InvalidUsername(_) => ServerAuthenticationPacket::RegisterFailedUsernameInvalid,
+            RegistrationError::UsernameTaken => ServerAuthenticationPacket::RegisterFailedUsernameTaken,
+            RegistrationError::Failed => ServerAuthenticationPacket::ErrorInternal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_username, RegistrationPolicy, UsernameError};
+
+    #[test]
+    fn usernames_follow_the_rules() {
+        assert_eq!(validate_username("lorem_ipsum.2-b"), Ok(()));
+        assert_eq!(validate_username("lo"), Err(UsernameError::Length));
+        assert_eq!(validate_username(&"a".repeat(33)), Err(UsernameError::Length));
+        assert_eq!(validate_username("lorem ipsum"), Err(UsernameError::Characters));
+        // only ASCII letters
+        assert_eq!(validate_username("lôrem"), Err(UsernameError::Characters));
+    }
+
+    #[test]
+    fn reserved_usernames_ignore_case() {
+        let policy = RegistrationPolicy::default();
+
+        assert_eq!(policy.check_username("Admin"), Err(UsernameError::Reserved));
+        assert_eq!(policy.check_username("ROOT"), Err(UsernameError::Reserved));
+        assert_eq!(policy.check_username("admins"), Ok(()));
+    }
+}
@@ -1,13 +1,166 @@
-use actix::{Actor, ActorContext, StreamHandler, Handler, Addr};
+use actix::{fut, Actor, ActorContext, ActorFutureExt, AsyncContext, ContextFutureSpawner, StreamHandler, Handler, Addr, WrapFuture};
 use actix_web_actors::ws;
+use dalang_protocol::{
+    json,
+    protocol::{ClientProtocolPacket, ServerProtocolPacket},
+    ClientPacket, DecodeLimits, ServerPacket,
+};
 
-use crate::{server::DalangServer, auth};
+use crate::{
+    server::{self, DalangServer},
+    auth,
+    client::ClientSession,
+    extensions::Extensions,
+    sessions::SessionState,
+};
 
 /// Represents a WebSocket session
 pub struct Session<AuthActor: auth::Authenticator> {
     /// A unique ID
     pub id: usize,
-    pub server: Addr<DalangServer<AuthActor>>
+    pub server: Addr<DalangServer<AuthActor>>,
+    /// The limits of the packets sent by the client
+    pub decode_limits: DecodeLimits,
+    /// The extensions of the protocol accepted by the client
+    pub extensions: Extensions,
+    /// The token of the session, set once it's registered by the server
+    pub token: Option<String>,
+    /// What is kept for the client to resume the session once it disconnects
+    pub state: SessionState,
+}
+
+impl<A: auth::Authenticator> Session<A> {
+    /// Processes a packet sent by the client
+    fn handle_packet(&mut self, packet: ClientPacket, ctx: &mut <Self as Actor>::Context) {
+        match packet {
+            ClientPacket::Protocol(ClientProtocolPacket::AcceptExtensions { extensions }) => {
+                let extensions = self.extensions.accept(&extensions);
+
+                self.send(ServerPacket::Protocol(ServerProtocolPacket::ExtensionsAccepted { extensions }), ctx);
+            }
+
+            ClientPacket::Protocol(ClientProtocolPacket::ResumeSession { token, last_event }) => {
+                self.resume(token, last_event, ctx);
+            }
+
+            ClientPacket::Authentication(packet) => self.handle_authentication(packet, ctx),
+
+            ClientPacket::User(packet) => self.handle_user(packet, ctx),
+
+            // the other categories are not processed yet
+            _ => (),
+        }
+    }
+
+    /// Takes back a session the client was disconnected from, and replays the events it missed
+    fn resume(&mut self, token: String, last_event: u64, ctx: &mut <Self as Actor>::Context) {
+        let resume = server::Resume {
+            token: token.clone(),
+            last_event,
+            recipient: ctx.address().recipient(),
+        };
+
+        // other packets wait for the session to be resumed
+        self.server
+            .send(resume)
+            .into_actor(self)
+            .then(move |result, session, ctx| {
+                match result {
+                    Ok(Ok((state, missed))) => {
+                        println!("[id:{}] resumed a session, replaying {} events", session.id, missed.len());
+
+                        // the session the connection started with is not needed anymore
+                        if let Some(started) = session.token.replace(token) {
+                            session.server.do_send(server::Forget(started));
+                        }
+
+                        session.state = state;
+                        session.send(ServerPacket::Protocol(ServerProtocolPacket::SessionResumed), ctx);
+
+                        for packet in missed {
+                            session.send(packet, ctx);
+                        }
+                    }
+
+                    Ok(Err(err)) => {
+                        println!("[id:{}] failed to resume a session: {:?}", session.id, err);
+
+                        session.send(ServerPacket::Protocol(ServerProtocolPacket::ResumeFailed), ctx);
+                    }
+
+                    Err(err) => {
+                        println!("[id:{}] failed to reach the server: {:?}", session.id, err);
+
+                        session.send(ServerPacket::Protocol(ServerProtocolPacket::ResumeFailed), ctx);
+                    }
+                }
+
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// Decodes a packet sent by the client, and processes it
+    fn receive(&mut self, bin: &[u8], ctx: &mut <Self as Actor>::Context) {
+        match ClientPacket::decode_with_limits(bin, &self.decode_limits) {
+            Ok(packet) => self.handle_packet(packet, ctx),
+
+            Err(err) if err.is_limit_exceeded() => {
+                println!("[id:{}] packet exceeds the limits: {:?}, disconnecting", self.id, err);
+
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Size,
+                    description: Some(String::from("packet exceeds the limits")),
+                }));
+
+                ctx.stop();
+            }
+
+            Err(err) => {
+                println!("[id:{}] failed to decode a packet: {:?}", self.id, err);
+            }
+        }
+    }
+
+    /// Sends a packet to the client, encoded with the extensions it accepted
+    fn send(&self, packet: ServerPacket, ctx: &mut <Self as Actor>::Context) {
+        if self.extensions.json {
+            let text = packet
+                .try_into()
+                .map_err(Into::into)
+                .and_then(|bin: Vec<u8>| json::to_json(&bin, json::Sender::Server));
+
+            match text {
+                Ok(text) => ctx.text(text),
+                Err(err) => println!("[id:{}] failed to encode a packet as JSON: {:?}", self.id, err),
+            }
+
+            return;
+        }
+
+        match self.extensions.encode(packet) {
+            Ok(bytes) => ctx.binary(bytes),
+            Err(err) => println!("[id:{}] failed to encode a packet: {:?}", self.id, err),
+        }
+    }
+}
+
+impl<A: auth::Authenticator> ClientSession<A> for Session<A> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn server(&self) -> &Addr<DalangServer<A>> {
+        &self.server
+    }
+
+    fn state(&mut self) -> &mut SessionState {
+        &mut self.state
+    }
+
+    fn send_packet(&mut self, packet: ServerPacket, ctx: &mut Self::Context) {
+        self.send(packet, ctx);
+    }
 }
 
 impl<A: auth::Authenticator> Actor for Session<A> {
@@ -16,7 +169,7 @@ impl<A: auth::Authenticator> Actor for Session<A> {
     fn started(&mut self, ctx: &mut Self::Context) {
         println!("[id:{}] client connected! sending protocol information", self.id);
 
-        let Ok(payload) = dalang_protocol::protocol_version_packet() else {
+        let Ok(payload) = dalang_protocol::protocol_version_packet_with(&self.extensions.offered()) else {
             println!("[id:{}] failed to run protocol_version_packet(), closing with error", self.id);
 
             // close when we failed to generate the protocol version packet
@@ -26,6 +179,36 @@ impl<A: auth::Authenticator> Actor for Session<A> {
 
         // as we connect, the server should send its protocol version, with maybe some extensions
         ctx.binary(payload);
+
+        // then the token to resume the session, the client's packets wait for it
+        self.server
+            .send(server::Register(ctx.address().recipient()))
+            .into_actor(self)
+            .then(|result, session, ctx| {
+                match result {
+                    Ok(token) => {
+                        session.token = Some(token.clone());
+                        session.send(ServerPacket::Protocol(ServerProtocolPacket::SessionStarted { token }), ctx);
+                    }
+
+                    Err(err) => {
+                        println!("[id:{}] failed to register the session: {:?}, closing", session.id, err);
+
+                        ctx.close(Some(ws::CloseReason { code: ws::CloseCode::Error, description: None }));
+                        ctx.stop();
+                    }
+                }
+
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        // the session is kept for the client to resume it
+        if let Some(token) = self.token.take() {
+            self.server.do_send(server::Disconnect { token, state: self.state.clone() });
+        }
     }
 }
 
@@ -35,6 +218,14 @@ impl<AuthActor: auth::Authenticator> StreamHandler<Result<ws::Message, ws::Proto
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
 
+            // packets are sent as JSON once the client accepts `json-debug`
+            Ok(ws::Message::Text(text)) if self.extensions.json => {
+                match json::from_json(&text, json::Sender::Client) {
+                    Ok(bin) => self.receive(&bin, ctx),
+                    Err(err) => println!("[id:{}] failed to read a JSON packet: {:?}", self.id, err),
+                }
+            }
+
             Ok(ws::Message::Text(text)) => {
                 println!("[id:{}] rececived a text message: `{}`\ndisconnecting", self.id, text); // todo: change to use a logging system
 
@@ -68,36 +259,267 @@ impl<AuthActor: auth::Authenticator> StreamHandler<Result<ws::Message, ws::Proto
                 ctx.stop();
             }
 
-            Ok(ws::Message::Binary(bin)) => {
-                // now its time to process this message
-            },
+            Ok(ws::Message::Binary(bin)) => self.receive(&bin, ctx),
 
             _ => (),
         }
     }
 }
 
-impl<A: auth::Authenticator> Handler<messages::RawMessage> for Session<A> {
+impl<A: auth::Authenticator> Handler<messages::Event> for Session<A> {
     type Result = ();
 
-    fn handle(&mut self, msg: messages::RawMessage, ctx: &mut Self::Context) -> Self::Result {
-        match msg {
-            messages::RawMessage::Text(text) => ctx.text(text),
-            messages::RawMessage::Binary(bytes) => ctx.binary(bytes),
+    fn handle(&mut self, messages::Event(packet): messages::Event, ctx: &mut Self::Context) -> Self::Result {
+        if !packet.is_event() {
+            println!("[id:{}] refusing to push a packet that isn't an event: {:?}", self.id, packet);
+            return;
+        }
+
+        let terminating = matches!(packet, ServerPacket::Protocol(ServerProtocolPacket::SessionTerminating { .. }));
+
+        self.send(packet, ctx);
+
+        if terminating {
+            ctx.close(Some(ws::CloseReason { code: ws::CloseCode::Away, description: None }));
+            ctx.stop();
         }
     }
 }
 
 pub mod messages {
     use actix::Message;
+    use dalang_protocol::ServerPacket;
+
+    /// Pushes an event to the client, a server packet with an opcode in
+    /// `dalang_protocol::EVENT_OPCODES`. Other packets are refused, they are only sent as the
+    /// response of the client's requests.
+    #[derive(Debug, Clone)]
+    pub struct Event(pub ServerPacket);
+
+    impl Message for Event {
+        type Result = ();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, marker::PhantomData, pin::Pin};
+
+    use actix::{Actor, Addr, Context, Handler};
+    use actix_http::ws::{Codec, Frame, Message};
+    use actix_web::{error::PayloadError, web::Bytes};
+    use actix_web_actors::ws;
+    use bytes::BytesMut;
+    use dalang_protocol::{
+        authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+        protocol::{ClientProtocolPacket, ServerProtocolPacket},
+        user::{ClientUserPacket, ServerUserPacket},
+        ClientPacket, DecodeLimits, ServerPacket,
+    };
+    use futures_util::{stream, Stream, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::Session;
+    use crate::{
+        auth::{messages as auth_msg, Role},
+        components::auth::SQLiteAuthenticator,
+        extensions::Extensions,
+        server::{self, DalangServer, GetAuthenticator},
+        session::messages::Event,
+        sessions::SessionState,
+    };
+
+    type Server = Addr<DalangServer<SQLiteAuthenticator>>;
+
+    /// A websocket client of a session, without the HTTP handshake
+    struct Client {
+        input: mpsc::UnboundedSender<Result<Bytes, PayloadError>>,
+        output: Pin<Box<dyn Stream<Item = Result<Bytes, actix_web::Error>>>>,
+        received: BytesMut,
+        codec: Codec,
+    }
+
+    impl Client {
+        /// Connects to the server, the protocol version packet is read. Results in the token of
+        /// the session.
+        async fn connect(server: &Server) -> (Client, String) {
+            Client::connect_with(server, SessionState::default()).await
+        }
+
+        /// Connects to the server with a session in the given state, such as one with an opened
+        /// project, which can't be reached through the packets yet
+        async fn connect_with(server: &Server, state: SessionState) -> (Client, String) {
+            let (input, rx) = mpsc::unbounded_channel();
+            let rx = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|item| (item, rx)) });
+
+            let session = Session {
+                id: 0,
+                server: server.clone(),
+                decode_limits: DecodeLimits::default(),
+                extensions: Extensions::new(false),
+                token: None,
+                state,
+            };
+
+            let mut client = Client {
+                input,
+                output: Box::pin(ws::WebsocketContext::create(session, rx)),
+                received: BytesMut::new(),
+                codec: Codec::new().client_mode(),
+            };
+
+            client.receive_frame().await;
+
+            match client.receive().await {
+                ServerPacket::Protocol(ServerProtocolPacket::SessionStarted { token }) => (client, token),
+                packet => panic!("expected the token of the session, got {:?}", packet),
+            }
+        }
+
+        async fn receive_frame(&mut self) -> Bytes {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.received).expect("invalid frame") {
+                    match frame {
+                        Frame::Binary(bytes) => return bytes,
+                        frame => panic!("expected a binary frame, got {:?}", frame),
+                    }
+                }
+
+                let bytes = self.output.next().await.expect("the session was closed").unwrap();
+                self.received.extend_from_slice(&bytes);
+            }
+        }
+
+        async fn receive(&mut self) -> ServerPacket {
+            let bytes = self.receive_frame().await;
+
+            ServerPacket::try_from(&bytes[..]).unwrap()
+        }
+
+        /// Sends a packet, and reads the answer
+        async fn request(&mut self, packet: ClientPacket<'_>) -> ServerPacket {
+            let bytes: Vec<u8> = packet.try_into().unwrap();
+
+            let mut frame = BytesMut::new();
+            self.codec.encode(Message::Binary(bytes.into()), &mut frame).unwrap();
+            self.input.send(Ok(frame.freeze())).unwrap();
+
+            self.receive().await
+        }
 
-    // Send a raw message directly, do not use except really needed
-    pub enum RawMessage {
-        Text(String),
-        Binary(Vec<u8>)
+        /// Closes the connection, and waits for the session to stop
+        async fn disconnect(self) {
+            let Client { input, mut output, .. } = self;
+            drop(input);
+
+            while output.next().await.is_some() {}
+        }
+    }
+
+    /// A session actor that drops its events
+    struct Dropping;
+
+    impl Actor for Dropping {
+        type Context = Context<Self>;
     }
 
-    impl Message for RawMessage {
+    impl Handler<Event> for Dropping {
         type Result = ();
+
+        fn handle(&mut self, _msg: Event, _ctx: &mut Self::Context) {}
+    }
+
+    async fn start_server() -> Server {
+        let server = DalangServer {
+            authenticator: SQLiteAuthenticator::new_in_memory().start(),
+            storages: HashMap::new(),
+            sessions: Default::default(),
+            registration: Default::default(),
+        }.start();
+
+        let authenticator = server.send(GetAuthenticator(PhantomData)).await.unwrap();
+
+        authenticator.send(auth_msg::Register {
+            username: "lorem".to_string(),
+            password: "12345678".to_string(),
+            role: Role::User,
+            invite: None,
+        }).await.unwrap().expect("failed to register");
+
+        server
+    }
+
+    #[actix_rt::test]
+    async fn session_resumes_logged_in() {
+        let server = start_server().await;
+
+        let (mut client, token) = Client::connect(&server).await;
+
+        let login = ClientAuthenticationPacket::Login { username: "lorem", password: "12345678" };
+        assert!(matches!(
+            client.request(ClientPacket::Authentication(login)).await,
+            ServerPacket::Authentication(ServerAuthenticationPacket::LoginSuccess { .. })
+        ));
+
+        client.disconnect().await;
+
+        // a new connection is logged out until it resumes the session
+        let (mut client, _) = Client::connect(&server).await;
+
+        assert_eq!(
+            client.request(ClientPacket::User(ClientUserPacket::GetUsername)).await,
+            ServerPacket::User(ServerUserPacket::ErrorNotAuthenticated)
+        );
+
+        let resume = ClientProtocolPacket::ResumeSession { token, last_event: 0 };
+        assert_eq!(
+            client.request(ClientPacket::Protocol(resume)).await,
+            ServerPacket::Protocol(ServerProtocolPacket::SessionResumed)
+        );
+
+        assert_eq!(
+            client.request(ClientPacket::User(ClientUserPacket::GetUsername)).await,
+            ServerPacket::User(ServerUserPacket::UsernameResp { username: "lorem".to_string() })
+        );
+
+        let ServerPacket::User(ServerUserPacket::ProfileResp { profile }) =
+            client.request(ClientPacket::User(ClientUserPacket::GetProfile)).await
+        else {
+            panic!("expected the profile");
+        };
+
+        assert_eq!(profile.username, "lorem");
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn session_resumes_the_opened_project() {
+        let server = start_server().await;
+
+        let state = SessionState {
+            uid: Some(42),
+            username: Some("lorem".to_string()),
+            project: Some(3),
+            position: 120,
+        };
+
+        let (client, token) = Client::connect_with(&server, state.clone()).await;
+        client.disconnect().await;
+
+        let (mut client, _) = Client::connect(&server).await;
+
+        let resume = ClientProtocolPacket::ResumeSession { token: token.clone(), last_event: 0 };
+        assert_eq!(
+            client.request(ClientPacket::Protocol(resume)).await,
+            ServerPacket::Protocol(ServerProtocolPacket::SessionResumed)
+        );
+
+        // the state the session is left with once its second connection is closed
+        client.disconnect().await;
+
+        let resume = server::Resume { token, last_event: 0, recipient: Dropping.start().recipient() };
+        let (resumed, _) = server.send(resume).await.unwrap().expect("failed to resume");
+
+        assert_eq!(resumed, state);
+    }
+}
@@ -0,0 +1,351 @@
+//! Sessions that outlive their connection: a client that reconnects within the grace period
+//! takes its session back with its token, and the events it missed are replayed.
+//!
+//! Events are numbered per session from 1, in the order they are sent. The numbers aren't sent
+//! along with the events, both sides count them, and the client resumes with the number of
+//! events it has received.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use actix::Recipient;
+use dalang_protocol::ServerPacket;
+
+use crate::session::messages::Event;
+
+/// What a session carries over to the connection that resumes it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionState {
+    /// The uid of the user the client is logged in as
+    pub uid: Option<u64>,
+    /// The username of that user, as it was registered
+    pub username: Option<String>,
+    /// The project opened by the client
+    pub project: Option<u32>,
+    /// The playback position in the opened project, in frames
+    pub position: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumeOptions {
+    /// How long a session is kept after its client disconnected
+    pub grace_period: Duration,
+    /// How many of the last events of a session are kept to be replayed
+    pub max_events: usize,
+}
+
+impl Default for ResumeOptions {
+    fn default() -> Self {
+        ResumeOptions {
+            grace_period: Duration::from_secs(5 * 60),
+            max_events: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeError {
+    /// No session has this token, or it expired
+    UnknownToken,
+    /// The session is still used by another connection
+    Connected,
+    /// Events the client missed are not kept anymore
+    EventsLost,
+}
+
+/// A session known by the server, connected or waiting to be resumed
+#[derive(Debug)]
+struct StoredSession {
+    /// The session actor of the connection, `None` once its client disconnected
+    recipient: Option<Recipient<Event>>,
+    state: SessionState,
+    /// The number of the last event that was sent
+    last_event: u64,
+    /// The last events, along with their number
+    events: VecDeque<(u64, ServerPacket)>,
+    disconnected_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct Sessions {
+    pub options: ResumeOptions,
+    sessions: HashMap<String, StoredSession>,
+}
+
+impl Sessions {
+    pub fn new(options: ResumeOptions) -> Self {
+        Sessions {
+            options,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Registers the session of a new connection, returns its token
+    pub fn register(&mut self, recipient: Recipient<Event>) -> String {
+        let token = format!("{:032x}", rand::random::<u128>());
+
+        self.sessions.insert(
+            token.clone(),
+            StoredSession {
+                recipient: Some(recipient),
+                state: SessionState::default(),
+                last_event: 0,
+                events: VecDeque::new(),
+                disconnected_at: None,
+            },
+        );
+
+        token
+    }
+
+    /// Sends an event to a session, it is kept to be replayed if its client is disconnected
+    /// or reconnects without having received it. Returns `false` if there's no such session,
+    /// or if the packet isn't an event: the sessions refuse to send it, and counting it would
+    /// shift the numbers of the events the client counts.
+    pub fn push(&mut self, token: &str, packet: ServerPacket) -> bool {
+        if !packet.is_event() {
+            return false;
+        }
+
+        let Some(session) = self.sessions.get_mut(token) else {
+            return false;
+        };
+
+        session.last_event += 1;
+
+        if self.options.max_events > 0 {
+            if session.events.len() == self.options.max_events {
+                session.events.pop_front();
+            }
+
+            session.events.push_back((session.last_event, packet.clone()));
+        }
+
+        if let Some(recipient) = &session.recipient {
+            recipient.do_send(Event(packet));
+        }
+
+        true
+    }
+
+    /// Sends an event to every session whose client is connected
+    pub fn broadcast(&mut self, packet: ServerPacket) {
+        let connected: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.recipient.is_some())
+            .map(|(token, _)| token.clone())
+            .collect();
+
+        for token in connected {
+            self.push(&token, packet.clone());
+        }
+    }
+
+    /// The client of a session disconnected, the session is kept for the grace period
+    pub fn disconnect(&mut self, token: &str, state: SessionState, now: Instant) {
+        if let Some(session) = self.sessions.get_mut(token) {
+            session.recipient = None;
+            session.state = state;
+            session.disconnected_at = Some(now);
+        }
+    }
+
+    /// Forgets a session, it can't be resumed anymore
+    pub fn remove(&mut self, token: &str) {
+        self.sessions.remove(token);
+    }
+
+    /// Hands a disconnected session to a new connection, returns its state and the events
+    /// after `last_event`, that the client hasn't received
+    pub fn resume(
+        &mut self,
+        token: &str,
+        last_event: u64,
+        recipient: Recipient<Event>,
+        now: Instant,
+    ) -> Result<(SessionState, Vec<ServerPacket>), ResumeError> {
+        self.expire(now);
+
+        let session = self.sessions.get_mut(token).ok_or(ResumeError::UnknownToken)?;
+
+        if session.recipient.is_some() {
+            return Err(ResumeError::Connected);
+        }
+
+        // the client can't have received events that weren't sent
+        if last_event > session.last_event {
+            return Err(ResumeError::EventsLost);
+        }
+
+        let first_kept = session.events.front().map_or(session.last_event + 1, |(seq, _)| *seq);
+
+        if last_event + 1 < first_kept {
+            return Err(ResumeError::EventsLost);
+        }
+
+        let missed = session
+            .events
+            .iter()
+            .filter(|(seq, _)| *seq > last_event)
+            .map(|(_, packet)| packet.clone())
+            .collect();
+
+        session.recipient = Some(recipient);
+        session.disconnected_at = None;
+
+        Ok((session.state.clone(), missed))
+    }
+
+    /// Forgets the sessions whose client has been disconnected for longer than the grace period
+    pub fn expire(&mut self, now: Instant) {
+        let grace_period = self.options.grace_period;
+
+        self.sessions.retain(|_, session| {
+            session
+                .disconnected_at
+                .is_none_or(|at| now.duration_since(at) < grace_period)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use actix::{Actor, Context, Handler, Recipient};
+    use dalang_protocol::{user::ServerUserPacket, ServerPacket};
+
+    use super::{ResumeError, ResumeOptions, SessionState, Sessions};
+    use crate::session::messages::Event;
+
+    /// A session actor that drops its events
+    struct Client;
+
+    impl Actor for Client {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Event> for Client {
+        type Result = ();
+
+        fn handle(&mut self, _msg: Event, _ctx: &mut Self::Context) {}
+    }
+
+    fn client() -> Recipient<Event> {
+        Client.start().recipient()
+    }
+
+    fn event() -> ServerPacket {
+        ServerPacket::User(ServerUserPacket::ProjectsListChanged)
+    }
+
+    #[actix_rt::test]
+    async fn resume_replays_missed_events() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+
+        let token = sessions.register(client());
+        let state = SessionState {
+            uid: Some(42),
+            username: Some("lorem".to_string()),
+            project: Some(3),
+            position: 120,
+        };
+
+        // the client received the first event, but not the second
+        sessions.push(&token, event());
+        sessions.push(&token, event());
+        sessions.disconnect(&token, state.clone(), now);
+
+        // and the third is sent while it's disconnected
+        sessions.push(&token, event());
+
+        let (resumed, missed) = sessions.resume(&token, 1, client(), now).unwrap();
+
+        assert_eq!(resumed, state);
+        assert_eq!(missed.len(), 2);
+
+        // the session is used by the new connection now
+        assert_eq!(sessions.resume(&token, 3, client(), now), Err(ResumeError::Connected));
+    }
+
+    #[actix_rt::test]
+    async fn push_refuses_packets_that_arent_events() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+
+        let token = sessions.register(client());
+
+        assert!(!sessions.push(&token, ServerPacket::User(ServerUserPacket::ErrorNotAuthenticated)));
+        assert!(sessions.push(&token, event()));
+        sessions.disconnect(&token, SessionState::default(), now);
+
+        // the client received the only event, the response isn't counted
+        assert_eq!(sessions.resume(&token, 2, client(), now), Err(ResumeError::EventsLost));
+
+        let (_, missed) = sessions.resume(&token, 1, client(), now).unwrap();
+        assert!(missed.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn broadcast_skips_disconnected_sessions() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+
+        let connected = sessions.register(client());
+        let disconnected = sessions.register(client());
+        sessions.disconnect(&disconnected, SessionState::default(), now);
+
+        sessions.broadcast(event());
+
+        let (_, missed) = sessions.resume(&disconnected, 0, client(), now).unwrap();
+        assert!(missed.is_empty());
+
+        sessions.disconnect(&connected, SessionState::default(), now);
+
+        let (_, missed) = sessions.resume(&connected, 0, client(), now).unwrap();
+        assert_eq!(missed.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn resume_fails_after_grace_period() {
+        let mut sessions = Sessions::new(ResumeOptions {
+            grace_period: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        let token = sessions.register(client());
+        sessions.disconnect(&token, SessionState::default(), now);
+
+        assert_eq!(
+            sessions.resume(&token, 0, client(), now + Duration::from_secs(61)),
+            Err(ResumeError::UnknownToken)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn resume_fails_when_events_are_lost() {
+        let mut sessions = Sessions::new(ResumeOptions {
+            max_events: 2,
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        let token = sessions.register(client());
+        sessions.disconnect(&token, SessionState::default(), now);
+
+        for _ in 0..3 {
+            sessions.push(&token, event());
+        }
+
+        // the first event was dropped from the buffer
+        assert_eq!(sessions.resume(&token, 0, client(), now), Err(ResumeError::EventsLost));
+
+        let (_, missed) = sessions.resume(&token, 1, client(), now).unwrap();
+        assert_eq!(missed.len(), 2);
+    }
+}
@@ -0,0 +1,118 @@
+//! Signals sent to the server process: SIGTERM and SIGINT stop the server gracefully, the
+//! clients are told that their session is terminating first. SIGHUP loads the configuration
+//! again, the options given on the command line are applied over it again.
+
+use actix::Addr;
+use actix_web::dev::ServerHandle;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{
+    auth::Authenticator,
+    config::Config,
+    server::{self, DalangServer},
+    Settings, SharedSettings,
+};
+
+pub(crate) async fn handle<A: Authenticator>(
+    mut config: Config,
+    http: ServerHandle,
+    server: Addr<DalangServer<A>>,
+    settings: SharedSettings,
+) {
+    let (Ok(mut terminate), Ok(mut interrupt), Ok(mut hangup)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::hangup()),
+    ) else {
+        println!("failed to listen to signals, the server can't be stopped gracefully");
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            _ = hangup.recv() => reload(&mut config, &server, &settings),
+        }
+    }
+
+    println!("stopping the server");
+
+    let reason = "the server is shutting down".to_string();
+
+    if let Err(err) = server.send(server::Shutdown { reason }).await {
+        println!("failed to tell the clients that the server is stopping: {:?}", err);
+    }
+
+    http.stop(true).await;
+}
+
+/// Applies what can be changed without a restart: the limits of the packets and the `json-debug`
+/// extension for the new connections, and how long sessions are kept
+fn reload<A: Authenticator>(config: &mut Config, server: &Addr<DalangServer<A>>, settings: &SharedSettings) {
+    let Some(source) = &config.source else {
+        println!("the configuration wasn't loaded from a file, there's nothing to reload");
+        return;
+    };
+
+    let mut reloaded = match Config::load(source) {
+        Ok(reloaded) => reloaded,
+        Err(err) => {
+            println!("failed to reload {}: {}, the configuration is kept as is", source.display(), err);
+            return;
+        }
+    };
+
+    reloaded.apply_overrides(config.overrides.clone());
+
+    *settings.write().expect("the settings lock is poisoned") = Settings::from_config(&reloaded);
+    server.do_send(server::SetResumeOptions(reloaded.sessions.resume_options()));
+    server.do_send(server::SetRegistration(reloaded.auth.registration_policy()));
+
+    println!("reloaded {}", source.display());
+
+    if needs_restart(config, &reloaded) {
+        println!("the listeners, storage and daemon settings that changed are only used once the server restarts");
+    }
+
+    *config = reloaded;
+}
+
+/// Whether settings that are only used once the server restarts changed
+fn needs_restart(config: &Config, reloaded: &Config) -> bool {
+    reloaded.server.listen != config.server.listen
+        || reloaded.server.endpoint != config.server.endpoint
+        || reloaded.server.static_dir != config.server.static_dir
+        || reloaded.server.tcp != config.server.tcp
+        || reloaded.server.unix != config.server.unix
+        || reloaded.storage != config.storage
+        || reloaded.daemon != config.daemon
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, path::PathBuf};
+
+    use crate::config::{Config, Overrides};
+
+    #[test]
+    fn overrides_are_applied_again_on_reload() {
+        let text = "[server]\nlisten = [\"127.0.0.1:8080\"]\n";
+
+        let mut config = Config::from_sources(text, []).unwrap();
+        config.apply_overrides(Overrides {
+            listen: Some(vec![SocketAddr::from(([0, 0, 0, 0], 80))]),
+            database: Some(PathBuf::from("other.db")),
+            json_debug: true,
+            ..Default::default()
+        });
+
+        let mut reloaded = Config::from_sources(text, []).unwrap();
+        assert!(super::needs_restart(&config, &reloaded));
+
+        reloaded.apply_overrides(config.overrides.clone());
+        assert!(!super::needs_restart(&config, &reloaded));
+        assert!(reloaded.server.json_debug);
+        assert_eq!(reloaded, config);
+    }
+}
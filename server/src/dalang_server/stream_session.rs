@@ -0,0 +1,717 @@
+//! Sessions of the clients that connect through a raw byte stream (TCP or a Unix socket)
+//! instead of a websocket, the packets are framed with `dalang_protocol::codec`.
+
+use std::io;
+
+use actix::{
+    fut,
+    io::{FramedWrite, WriteHandler},
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Running,
+    StreamHandler, WrapFuture,
+};
+use actix_rt::net::TcpListener;
+use bytes::BytesMut;
+use dalang_protocol::{
+    codec::{CodecError, PacketCodec},
+    protocol::{ClientProtocolPacket, ServerProtocolPacket},
+    ClientPacket, DecodeLimits, ServerPacket,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::FramedRead;
+
+use crate::{server::{self, DalangServer}, auth, client::ClientSession, extensions::Extensions, session::messages, sessions::SessionState, RawListener, SharedSettings};
+
+/// Represents a session over a raw byte stream, `W` is the writing half of the stream
+pub struct StreamSession<AuthActor: auth::Authenticator, W: AsyncWrite + Unpin + 'static> {
+    /// A unique ID
+    pub id: usize,
+    pub server: Addr<DalangServer<AuthActor>>,
+    /// The limits of the packets sent by the client
+    pub decode_limits: DecodeLimits,
+    /// The extensions of the protocol accepted by the client
+    pub extensions: Extensions,
+    /// What the client is logged in as, stream sessions can't be resumed
+    state: SessionState,
+    /// The token of the session registered with the server, through which events are pushed.
+    /// It isn't sent to the client, as the session can't be resumed
+    token: Option<String>,
+    writer: FramedWrite<Vec<u8>, W, PacketCodec>,
+}
+
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> StreamSession<A, W> {
+    /// Starts a session of a stream, split into its reading and writing half
+    pub fn start_with<R: AsyncRead + Unpin + 'static>(
+        reader: R,
+        writer: W,
+        server: Addr<DalangServer<A>>,
+        decode_limits: DecodeLimits,
+    ) -> Addr<Self> {
+        StreamSession::create(|ctx| {
+            ctx.add_stream(FramedRead::new(reader, PacketCodec::new(decode_limits)));
+
+            StreamSession {
+                id: rand::random(),
+                server,
+                decode_limits,
+                extensions: Extensions::default(),
+                state: SessionState::default(),
+                token: None,
+                writer: FramedWrite::new(writer, PacketCodec::new(decode_limits), ctx),
+            }
+        })
+    }
+
+    /// Processes a packet sent by the client
+    fn handle_packet(&mut self, packet: ClientPacket, ctx: &mut <Self as Actor>::Context) {
+        match packet {
+            ClientPacket::Protocol(ClientProtocolPacket::AcceptExtensions { extensions }) => {
+                let extensions = self.extensions.accept(&extensions);
+
+                self.send(ServerPacket::Protocol(ServerProtocolPacket::ExtensionsAccepted { extensions }));
+            }
+
+            // stream sessions don't start a session that can be resumed
+            ClientPacket::Protocol(ClientProtocolPacket::ResumeSession { .. }) => {
+                self.send(ServerPacket::Protocol(ServerProtocolPacket::ResumeFailed));
+            }
+
+            ClientPacket::Authentication(packet) => self.handle_authentication(packet, ctx),
+
+            ClientPacket::User(packet) => self.handle_user(packet, ctx),
+
+            // the other categories are not processed yet
+            _ => (),
+        }
+    }
+
+    /// Sends a packet to the client, encoded with the extensions it accepted
+    fn send(&mut self, packet: ServerPacket) {
+        match self.extensions.encode(packet) {
+            Ok(bytes) => self.writer.write(bytes),
+            Err(err) => println!("[id:{}] failed to encode a packet: {:?}", self.id, err),
+        }
+    }
+}
+
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> ClientSession<A> for StreamSession<A, W> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn server(&self) -> &Addr<DalangServer<A>> {
+        &self.server
+    }
+
+    fn state(&mut self) -> &mut SessionState {
+        &mut self.state
+    }
+
+    fn send_packet(&mut self, packet: ServerPacket, _ctx: &mut Self::Context) {
+        self.send(packet);
+    }
+}
+
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> Actor for StreamSession<A, W> {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        println!("[id:{}] client connected through a stream! sending protocol information", self.id);
+
+        let Ok(payload) = dalang_protocol::protocol_version_packet_with(&self.extensions.offered()) else {
+            println!("[id:{}] failed to run protocol_version_packet(), closing", self.id);
+
+            ctx.stop();
+            return;
+        };
+
+        // as we connect, the server should send its protocol version, with maybe some extensions
+        self.writer.write(payload);
+
+        // then the session is registered to receive the events, the client's packets wait for it
+        self.server
+            .send(server::Register(ctx.address().recipient()))
+            .into_actor(self)
+            .then(|result, session, ctx| {
+                match result {
+                    Ok(token) => session.token = Some(token),
+
+                    Err(err) => {
+                        println!("[id:{}] failed to register the session: {:?}, closing", session.id, err);
+
+                        session.writer.close();
+                        ctx.stop();
+                    }
+                }
+
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        // there's no resuming it, the session isn't kept
+        if let Some(token) = self.token.take() {
+            self.server.do_send(server::Forget(token));
+        }
+    }
+}
+
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> WriteHandler<CodecError> for StreamSession<A, W> {
+    fn error(&mut self, err: CodecError, _ctx: &mut Self::Context) -> Running {
+        println!("[id:{}] failed to write to the stream: {:?}", self.id, err);
+
+        Running::Stop
+    }
+}
+
+/// Handler for the packets read from the stream
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> StreamHandler<Result<BytesMut, CodecError>> for StreamSession<A, W> {
+    fn handle(&mut self, msg: Result<BytesMut, CodecError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(bin) => {
+                match ClientPacket::decode_with_limits(&bin, &self.decode_limits) {
+                    Ok(packet) => self.handle_packet(packet, ctx),
+
+                    Err(err) if err.is_limit_exceeded() => {
+                        println!("[id:{}] packet exceeds the limits: {:?}, disconnecting", self.id, err);
+
+                        self.writer.close();
+                        ctx.stop();
+                    }
+
+                    Err(err) => {
+                        println!("[id:{}] failed to decode a packet: {:?}", self.id, err);
+                    }
+                }
+            }
+
+            // the stream can't be read any further once a length is rejected
+            Err(err) => {
+                println!("[id:{}] stream error: {:?}, disconnecting", self.id, err);
+
+                self.writer.close();
+                ctx.stop();
+            }
+        }
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        println!("[id:{}] client closed the stream", self.id);
+
+        ctx.stop();
+    }
+}
+
+impl<A: auth::Authenticator, W: AsyncWrite + Unpin + 'static> Handler<messages::Event> for StreamSession<A, W> {
+    type Result = ();
+
+    fn handle(&mut self, messages::Event(packet): messages::Event, _ctx: &mut Self::Context) -> Self::Result {
+        if !packet.is_event() {
+            println!("[id:{}] refusing to push a packet that isn't an event: {:?}", self.id, packet);
+            return;
+        }
+
+        let terminating = matches!(packet, ServerPacket::Protocol(ServerProtocolPacket::SessionTerminating { .. }));
+
+        self.send(packet);
+
+        // the session stops once the packet is flushed and the stream closed
+        if terminating {
+            self.writer.close();
+        }
+    }
+}
+
+/// Binds the given listener, and starts a [`StreamSession`] for every connection it accepts
+pub(crate) async fn listen<A: auth::Authenticator>(
+    listener: RawListener,
+    server: Addr<DalangServer<A>>,
+    settings: SharedSettings,
+) -> io::Result<()> {
+    match listener {
+        RawListener::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+
+            actix::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            let (reader, writer) = stream.into_split();
+                            let decode_limits = settings.read().expect("the settings lock is poisoned").decode_limits;
+
+                            StreamSession::start_with(reader, writer, server.clone(), decode_limits);
+                        }
+                        Err(err) => println!("failed to accept a tcp connection: {:?}", err),
+                    }
+                }
+            });
+        }
+
+        #[cfg(unix)]
+        RawListener::Unix(path) => {
+            remove_stale_socket(&path)?;
+
+            let listener = actix_rt::net::UnixListener::bind(path)?;
+
+            actix::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            let (reader, writer) = stream.into_split();
+                            let decode_limits = settings.read().expect("the settings lock is poisoned").decode_limits;
+
+                            StreamSession::start_with(reader, writer, server.clone(), decode_limits);
+                        }
+                        Err(err) => println!("failed to accept a unix socket connection: {:?}", err),
+                    }
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the file of a unix socket left behind by a server that didn't stop properly. Other
+/// files are left for the binding to fail, and a socket that still accepts connections is in use.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Ok(());
+    }
+
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        ));
+    }
+
+    println!("removing the stale unix socket {}", path.display());
+
+    std::fs::remove_file(path)
+}
+
+/// Removes the file of a unix socket once the server stopped listening on it
+#[cfg(unix)]
+pub(crate) fn remove_socket(path: &std::path::Path) {
+    if let Err(err) = std::fs::remove_file(path) {
+        println!("failed to remove the unix socket {}: {:?}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, marker::PhantomData};
+
+    use actix::{Actor, Addr};
+    use dalang_protocol::{
+        authentication::{ClientAuthenticationPacket, ServerAuthenticationPacket},
+        codec::PacketCodec,
+        protocol::{ClientProtocolPacket, ServerProtocolPacket},
+        user::{ClientUserPacket, ServerUserPacket},
+        ClientPacket, DecodeLimits, ServerPacket,
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{DuplexStream, WriteHalf};
+    use tokio_util::codec::Framed;
+
+    use super::StreamSession;
+    use crate::{
+        auth::messages as auth_msg,
+        components::auth::SQLiteAuthenticator,
+        registration::{RegistrationMode, RegistrationPolicy},
+        server::{DalangServer, GetAuthenticator, Shutdown},
+        session::messages::Event,
+    };
+
+    type Client = Framed<DuplexStream, PacketCodec>;
+
+    fn start_server(registration: RegistrationPolicy) -> Addr<DalangServer<SQLiteAuthenticator>> {
+        DalangServer {
+            authenticator: SQLiteAuthenticator::new_in_memory().start(),
+            storages: HashMap::new(),
+            sessions: Default::default(),
+            registration,
+        }.start()
+    }
+
+    /// Connects to the server, the protocol version packet is read
+    async fn connect(server: Addr<DalangServer<SQLiteAuthenticator>>) -> Client {
+        let (client, stream) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(stream);
+
+        StreamSession::start_with(reader, writer, server, DecodeLimits::default());
+
+        let mut client = Framed::new(client, PacketCodec::default());
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        client
+    }
+
+    /// Sends an authentication packet, and reads the answer
+    async fn request(client: &mut Client, packet: ClientAuthenticationPacket<'_>) -> ServerAuthenticationPacket {
+        let bytes: Vec<u8> = ClientPacket::Authentication(packet).try_into().unwrap();
+        client.send(bytes).await.expect("failed to send");
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        match ServerPacket::try_from(&frame[..]).unwrap() {
+            ServerPacket::Authentication(packet) => packet,
+            packet => panic!("expected an authentication packet, got {:?}", packet),
+        }
+    }
+
+    /// Sends a user packet, and reads the answer
+    async fn request_user(client: &mut Client, packet: ClientUserPacket) -> ServerUserPacket {
+        let bytes: Vec<u8> = ClientPacket::User(packet).try_into().unwrap();
+        client.send(bytes).await.expect("failed to send");
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        match ServerPacket::try_from(&frame[..]).unwrap() {
+            ServerPacket::User(packet) => packet,
+            packet => panic!("expected a user packet, got {:?}", packet),
+        }
+    }
+
+    fn login<'a>(username: &'a str, password: &'a str) -> ClientAuthenticationPacket<'a> {
+        ClientAuthenticationPacket::Login { username, password }
+    }
+
+    fn register<'a>(username: &'a str, invite: Option<&'a str>) -> ClientAuthenticationPacket<'a> {
+        ClientAuthenticationPacket::Register { username, password: "12345678", invite }
+    }
+
+    fn start_session(limits: DecodeLimits) -> Framed<DuplexStream, PacketCodec> {
+        start_session_with_addr(limits).1
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn start_session_with_addr(
+        limits: DecodeLimits,
+    ) -> (
+        Addr<StreamSession<SQLiteAuthenticator, WriteHalf<DuplexStream>>>,
+        Framed<DuplexStream, PacketCodec>,
+    ) {
+        let server = start_server(RegistrationPolicy::default());
+
+        let (client, stream) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(stream);
+
+        let session = StreamSession::start_with(reader, writer, server, limits);
+
+        // the limits are the server's, the client reads the packets with the default ones
+        (session, Framed::new(client, PacketCodec::default()))
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_sends_protocol_version() {
+        let mut client = start_session(DecodeLimits::default());
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        assert_eq!(&frame[..], dalang_protocol::protocol_version_packet().unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_accepts_extensions() {
+        let mut client = start_session(DecodeLimits::default());
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        let accept: Vec<u8> = ClientPacket::Protocol(ClientProtocolPacket::AcceptExtensions {
+            extensions: vec!["compression".to_string(), "unknown".to_string()],
+        })
+        .try_into()
+        .unwrap();
+        client.send(accept).await.expect("failed to send");
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        assert_eq!(
+            ServerPacket::try_from(&frame[..]).unwrap(),
+            ServerPacket::Protocol(ServerProtocolPacket::ExtensionsAccepted {
+                extensions: vec!["compression".to_string()],
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_cannot_resume() {
+        let mut client = start_session(DecodeLimits::default());
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        let resume: Vec<u8> = ClientPacket::Protocol(ClientProtocolPacket::ResumeSession {
+            token: "0123456789abcdef".to_string(),
+            last_event: 0,
+        })
+        .try_into()
+        .unwrap();
+        client.send(resume).await.expect("failed to send");
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        assert_eq!(
+            ServerPacket::try_from(&frame[..]).unwrap(),
+            ServerPacket::Protocol(ServerProtocolPacket::ResumeFailed)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_pushes_events() {
+        let (session, mut client) = start_session_with_addr(DecodeLimits::default());
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        // responses can't be pushed, only events
+        session.send(Event(ServerPacket::User(ServerUserPacket::SuccessResp))).await.unwrap();
+        session.send(Event(ServerPacket::User(ServerUserPacket::ProjectsListChanged))).await.unwrap();
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        assert_eq!(
+            ServerPacket::try_from(&frame[..]).unwrap(),
+            ServerPacket::User(ServerUserPacket::ProjectsListChanged)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_closes_once_terminating() {
+        let (session, mut client) = start_session_with_addr(DecodeLimits::default());
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        let terminating = ServerPacket::Protocol(ServerProtocolPacket::SessionTerminating {
+            reason: "the server is shutting down".to_string(),
+        });
+        session.send(Event(terminating.clone())).await.unwrap();
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+        assert_eq!(ServerPacket::try_from(&frame[..]).unwrap(), terminating);
+
+        assert!(client.next().await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_closes_on_too_large_packet() {
+        let mut client = start_session(DecodeLimits { max_size: 16, ..Default::default() });
+
+        client.next().await.expect("the stream was closed").expect("invalid frame");
+        client.send(vec![0u8; 17]).await.expect("failed to send");
+
+        // the server closes its side of the stream
+        assert!(client.next().await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_checks_usernames() {
+        let mut client = connect(start_server(RegistrationPolicy::default())).await;
+
+        let check = |username| ClientAuthenticationPacket::UsernameCheckExists { username };
+
+        assert_eq!(request(&mut client, ClientAuthenticationPacket::RegisterCheckEnabled).await, ServerAuthenticationPacket::SuccessResp);
+        assert_eq!(request(&mut client, check("lorem")).await, ServerAuthenticationPacket::SuccessResp);
+        assert_eq!(request(&mut client, register("lorem", None)).await, ServerAuthenticationPacket::SuccessResp);
+
+        // usernames are unique regardless of their case
+        assert_eq!(request(&mut client, check("LOREM")).await, ServerAuthenticationPacket::RegisterFailedUsernameTaken);
+        assert_eq!(request(&mut client, register("Lorem", None)).await, ServerAuthenticationPacket::RegisterFailedUsernameTaken);
+
+        assert_eq!(request(&mut client, check("Admin")).await, ServerAuthenticationPacket::RegisterFailedUsernameInvalid);
+        assert_eq!(request(&mut client, check("lo rem")).await, ServerAuthenticationPacket::RegisterFailedUsernameInvalid);
+        assert_eq!(request(&mut client, register("admin", None)).await, ServerAuthenticationPacket::RegisterFailedUsernameInvalid);
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_registers_by_invitation() {
+        let server = start_server(RegistrationPolicy {
+            mode: RegistrationMode::Invite,
+            ..Default::default()
+        });
+
+        let authenticator = server.send(GetAuthenticator(PhantomData)).await.unwrap();
+        let invite = authenticator.send(auth_msg::CreateInvite).await.unwrap().unwrap();
+
+        let mut client = connect(server).await;
+
+        assert_eq!(request(&mut client, ClientAuthenticationPacket::RegisterCheckEnabled).await, ServerAuthenticationPacket::RegisterInviteOnly);
+        assert_eq!(request(&mut client, register("lorem", None)).await, ServerAuthenticationPacket::RegisterFailedInvalidInvite);
+        assert_eq!(request(&mut client, register("lorem", Some("unknown"))).await, ServerAuthenticationPacket::RegisterFailedInvalidInvite);
+        assert_eq!(request(&mut client, register("lorem", Some(&invite))).await, ServerAuthenticationPacket::SuccessResp);
+
+        // the codes can only be used once
+        assert_eq!(request(&mut client, register("ipsum", Some(&invite))).await, ServerAuthenticationPacket::RegisterFailedInvalidInvite);
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_refuses_closed_registration() {
+        let mut client = connect(start_server(RegistrationPolicy {
+            mode: RegistrationMode::Closed,
+            ..Default::default()
+        })).await;
+
+        let disabled = ServerAuthenticationPacket::RegisterFailedFeatureDisabled;
+
+        assert_eq!(request(&mut client, ClientAuthenticationPacket::RegisterCheckEnabled).await, disabled);
+        assert_eq!(request(&mut client, register("lorem", None)).await, disabled);
+        // whether users exist isn't told either
+        assert_eq!(request(&mut client, ClientAuthenticationPacket::UsernameCheckExists { username: "lorem" }).await, disabled);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_unix_sockets_are_removed() {
+        let dir = std::env::temp_dir().join(format!("dalang-socket-{:x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // a socket accepting connections is in use
+        let path = dir.join("dalang.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+        let err = super::remove_stale_socket(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+
+        // its file is left behind once it's closed
+        drop(listener);
+        super::remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+
+        // other files are not removed
+        let file = dir.join("dalang.txt");
+        std::fs::write(&file, "lorem").unwrap();
+        super::remove_stale_socket(&file).unwrap();
+        assert!(file.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_logs_in_and_updates_the_profile() {
+        let mut client = connect(start_server(RegistrationPolicy::default())).await;
+
+        assert_eq!(request_user(&mut client, ClientUserPacket::GetProfile).await, ServerUserPacket::ErrorNotAuthenticated);
+        assert_eq!(request(&mut client, register("Lorem", None)).await, ServerAuthenticationPacket::SuccessResp);
+
+        assert_eq!(
+            request(&mut client, login("lorem", "87654321")).await,
+            ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+        );
+        assert_eq!(
+            request(&mut client, login("ipsum", "12345678")).await,
+            ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+        );
+        assert!(matches!(
+            request(&mut client, login("lorem", "12345678")).await,
+            ServerAuthenticationPacket::LoginSuccess { .. }
+        ));
+        assert_eq!(
+            request(&mut client, login("lorem", "12345678")).await,
+            ServerAuthenticationPacket::ErrorAlreadyLoggedIn
+        );
+
+        // the username is the one that was registered
+        assert_eq!(
+            request_user(&mut client, ClientUserPacket::GetUsername).await,
+            ServerUserPacket::UsernameResp { username: "Lorem".to_string() }
+        );
+
+        let ServerUserPacket::ProfileResp { profile } = request_user(&mut client, ClientUserPacket::GetProfile).await else {
+            panic!("expected the profile");
+        };
+
+        assert_eq!(profile.username, "Lorem");
+        assert_eq!((profile.display_name.as_deref(), profile.email.as_deref(), profile.avatar), (None, None, None));
+        assert!(profile.last_login.is_some());
+
+        let update = ClientUserPacket::UpdateProfile {
+            display_name: Some("Lorem Ipsum".to_string()),
+            email: Some("lorem@ipsum.dolor".to_string()),
+            avatar: Some(7),
+        };
+
+        let ServerUserPacket::ProfileResp { profile: updated } = request_user(&mut client, update).await else {
+            panic!("expected the updated profile");
+        };
+
+        assert_eq!(updated.uid, profile.uid);
+        assert_eq!(updated.display_name.as_deref(), Some("Lorem Ipsum"));
+        assert_eq!(updated.email.as_deref(), Some("lorem@ipsum.dolor"));
+        assert_eq!(updated.avatar, Some(7));
+
+        assert_eq!(
+            request_user(&mut client, ClientUserPacket::GetProfile).await,
+            ServerUserPacket::ProfileResp { profile: updated }
+        );
+
+        assert_eq!(request(&mut client, ClientAuthenticationPacket::Logout).await, ServerAuthenticationPacket::SuccessResp);
+        assert_eq!(request_user(&mut client, ClientUserPacket::GetProfile).await, ServerUserPacket::ErrorNotAuthenticated);
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_refuses_disabled_users() {
+        let server = start_server(RegistrationPolicy::default());
+        let authenticator = server.send(GetAuthenticator(PhantomData)).await.unwrap();
+
+        let mut client = connect(server).await;
+        assert_eq!(request(&mut client, register("lorem", None)).await, ServerAuthenticationPacket::SuccessResp);
+
+        authenticator.send(auth_msg::SetDisabled { username: "lorem".to_string(), disabled: true }).await.unwrap().unwrap();
+
+        // not told apart from a wrong password
+        assert_eq!(
+            request(&mut client, login("lorem", "12345678")).await,
+            ServerAuthenticationPacket::LoginFailedInvalidUsernameWrongPassword
+        );
+        assert_eq!(request_user(&mut client, ClientUserPacket::GetUsername).await, ServerUserPacket::ErrorNotAuthenticated);
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_answers_the_unprocessed_requests() {
+        let mut client = connect(start_server(RegistrationPolicy::default())).await;
+
+        assert_eq!(
+            request(&mut client, ClientAuthenticationPacket::LoginWithToken { token: "" }).await,
+            ServerAuthenticationPacket::LoginFailedTokenExpired
+        );
+        assert_eq!(request_user(&mut client, ClientUserPacket::RetrieveProjects).await, ServerUserPacket::ErrorNotAuthenticated);
+
+        assert_eq!(request(&mut client, register("lorem", None)).await, ServerAuthenticationPacket::SuccessResp);
+        assert!(matches!(
+            request(&mut client, login("lorem", "12345678")).await,
+            ServerAuthenticationPacket::LoginSuccess { .. }
+        ));
+
+        for packet in [
+            ClientUserPacket::RetrieveProjects,
+            ClientUserPacket::RetrieveProjectsPaged { offset: 0, count: 10 },
+            ClientUserPacket::RetrieveProjectsTotal,
+            ClientUserPacket::RetrieveProjectImage { imgid: 1 },
+            ClientUserPacket::OpenProject,
+        ] {
+            assert_eq!(request_user(&mut client, packet).await, ServerUserPacket::ErrorInternal);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn stream_session_is_told_the_server_shuts_down() {
+        let server = start_server(RegistrationPolicy::default());
+        let mut client = connect(server.clone()).await;
+
+        server.send(Shutdown { reason: "the server is shutting down".to_string() }).await.unwrap();
+
+        let frame = client.next().await.expect("the stream was closed").expect("invalid frame");
+
+        assert_eq!(
+            ServerPacket::try_from(&frame[..]).unwrap(),
+            ServerPacket::Protocol(ServerProtocolPacket::SessionTerminating {
+                reason: "the server is shutting down".to_string(),
+            })
+        );
+        assert!(client.next().await.is_none());
+    }
+}